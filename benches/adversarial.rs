@@ -0,0 +1,118 @@
+//! Parse time on inputs that are pathological for backtracking parsers, and
+//! import time on HTML that is pathological for the HTML importer.
+//!
+//! Each input is parsed at several sizes and the time per byte reported; if
+//! parsing is linear, that figure stays roughly constant as the input grows.
+//! Run with `cargo bench`; exits with an error if the time per byte of the
+//! largest input is far worse than that of the smallest, or if deeply nested
+//! input isn't cut off at the default depth limit.
+
+extern crate bbcode;
+
+use bbcode::{Segment, DEFAULT_MAX_DEPTH};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Builds an input from the number of repetitions of some pattern.
+type Generator = fn(usize) -> String;
+
+/// Parses an input, returning the number of segments.
+type Parse = fn(&str) -> usize;
+
+const INPUTS: &[(&str, Generator)] = &[
+    ("unclosed [b]", |n| "[b]".repeat(n)),
+    ("unclosed nested tags", |n| {
+        "[b][i][quote][u][center]".repeat(n)
+    }),
+    ("closed then unclosed", |n| {
+        "[b]".repeat(n) + &"x[/b]".repeat(n / 2)
+    }),
+    ("mismatched closers", |n| "[b][i]x[/b]".repeat(n)),
+    ("unclosed list items", |n| "[list][*]".repeat(n)),
+    ("unterminated url targets", |n| "[url=[url=\"".repeat(n)),
+    ("unclosed code", |n| "[code][img][url]".repeat(n)),
+    ("embeds sharing a closer", |n| {
+        "[youtube]dQw4w9WgXcQ".repeat(n) + "[/youtube]"
+    }),
+    ("deep nesting", |n| "[b]".repeat(n) + &"[/b]".repeat(n)),
+    ("plain text", |n| "lorem ipsum dolor sit amet ".repeat(n)),
+];
+
+const HTML_INPUTS: &[(&str, Generator)] = &[
+    ("unclosed html lists", |n| "<ul><li>".repeat(n)),
+    ("html raw text elements", |n| "<script></script>".repeat(n)),
+];
+
+/// Repetition counts to measure at.
+const SIZES: &[usize] = &[1_000, 10_000, 100_000];
+
+/// How much worse the time per byte may get across `SIZES` before the bench
+/// fails. A quadratic parser would be around 100 times worse; a little growth
+/// is expected as the parser's tables outgrow the CPU caches.
+const TOLERANCE: f64 = 5.0;
+
+fn time_parse(input: &str, parse: Parse) -> Duration {
+    // Best of a few runs, to reduce noise.
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(parse(black_box(input)));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// How many decorations are nested one inside the next in segments.
+fn depth(mut segments: &[Segment]) -> usize {
+    let mut depth = 0;
+    while let [Segment::Decorated { ref text, .. }] = segments[..] {
+        depth += 1;
+        segments = text;
+    }
+    depth
+}
+
+fn main() {
+    let mut failed = false;
+
+    let n = *SIZES.last().unwrap();
+    let nested = "[b]".repeat(n) + &"[/b]".repeat(n);
+    if depth(&bbcode::parse(&nested)) != DEFAULT_MAX_DEPTH {
+        println!("deep nesting: depth limit was not applied");
+        failed = true;
+    }
+
+    let bbcode: Parse = |input| bbcode::parse(input).len();
+    let html: Parse = |input| bbcode::import::html(input).len();
+    let inputs = INPUTS
+        .iter()
+        .map(|&(name, make)| (name, make, bbcode))
+        .chain(HTML_INPUTS.iter().map(|&(name, make)| (name, make, html)));
+    for (name, make, parse) in inputs {
+        let mut per_byte = vec![];
+        for &n in SIZES {
+            let input = make(n);
+            let elapsed = time_parse(&input, parse);
+            let ns = elapsed.as_secs() as f64 * 1e9 + f64::from(elapsed.subsec_nanos());
+            per_byte.push(ns / input.len() as f64);
+            println!(
+                "{:>26} {:>9} bytes {:>12.3?} ({:.1} ns/byte)",
+                name,
+                input.len(),
+                elapsed,
+                per_byte.last().unwrap()
+            );
+        }
+
+        let growth = per_byte.last().unwrap() / per_byte[0];
+        if growth > TOLERANCE {
+            println!("{}: time per byte grew {:.1}x", name, growth);
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,54 @@
+//! Render time for documents with many headings of the same title.
+//!
+//! Every heading needs an anchor unlike those before it, which mustn't take
+//! longer the more headings share a title. Run with `cargo bench`; exits with
+//! an error if the time per heading of the largest document is far worse than
+//! that of the smallest.
+
+extern crate bbcode;
+
+use bbcode::render::{Renderer, SimpleHtml};
+use std::time::{Duration, Instant};
+
+/// Numbers of headings to measure at.
+const SIZES: &[usize] = &[1_000, 10_000, 100_000];
+
+/// How much worse the time per heading may get across `SIZES` before the
+/// bench fails.
+const TOLERANCE: f64 = 5.0;
+
+fn time_render(input: &str) -> Duration {
+    let segments = bbcode::parse(input);
+    // Best of a few runs, to reduce noise.
+    (0..3)
+        .map(|_| {
+            let mut out = vec![];
+            let start = Instant::now();
+            SimpleHtml::new(&mut out).render(&segments).unwrap();
+            bbcode::toc(&segments);
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let mut per_heading = vec![];
+    for &n in SIZES {
+        let elapsed = time_render(&"[h1]a[/h1]".repeat(n));
+        let ns = elapsed.as_secs() as f64 * 1e9 + f64::from(elapsed.subsec_nanos());
+        per_heading.push(ns / n as f64);
+        println!(
+            "{:>9} headings {:>12.3?} ({:.1} ns/heading)",
+            n,
+            elapsed,
+            per_heading.last().unwrap()
+        );
+    }
+
+    let growth = per_heading.last().unwrap() / per_heading[0];
+    if growth > TOLERANCE {
+        println!("time per heading grew {:.1}x", growth);
+        std::process::exit(1);
+    }
+}
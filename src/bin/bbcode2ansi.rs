@@ -0,0 +1,36 @@
+extern crate bbcode;
+
+use bbcode::render::{Ansi, ColorDepth, Renderer};
+use std::env;
+use std::io::Read;
+
+/// Guess how many colors the terminal can show from the environment.
+fn color_depth() -> ColorDepth {
+    if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return ColorDepth::Monochrome;
+    }
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        ColorDepth::TrueColor
+    } else if env::var("TERM").is_ok_and(|term| term.contains("256color")) {
+        ColorDepth::Palette256
+    } else {
+        ColorDepth::Palette16
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let text = {
+        let stdin = std::io::stdin();
+        let mut l = stdin.lock();
+        let mut s = String::new();
+        l.read_to_string(&mut s)?;
+        s
+    };
+
+    Ansi::new(std::io::stdout())
+        .colors(color_depth())
+        .render(&bbcode::parse(&text))?;
+
+    Ok(())
+}
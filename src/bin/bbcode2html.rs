@@ -3,7 +3,7 @@ extern crate bbcode;
 use bbcode::render::{Renderer, SimpleHtml};
 use std::io::Read;
 
-fn main() -> Result<(), Box<std::error::Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let text = {
         let stdin = std::io::stdin();
         let mut l = stdin.lock();
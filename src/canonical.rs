@@ -0,0 +1,257 @@
+//! Canonical forms of bbcode.
+
+use super::normalize;
+use super::render::{BbCode, ColorFormat, Renderer};
+use super::{Parser, Segment};
+
+/// Rewrite bbcode in canonical form.
+///
+/// Equivalent to `Canonicalizer::new().canonicalize(input)`.
+pub fn canonicalize(input: &str) -> String {
+    Canonicalizer::new().canonicalize(input)
+}
+
+/// Rewrites bbcode in a canonical form, so inputs that differ only in how
+/// they are marked up give the same output.
+///
+/// The output is written by `render::BbCode`, so tags are lowercase and
+/// colors and lists are always written the same way. Decorations that are
+/// redundant because they are nested in the same decoration are removed,
+/// like the inner tags of `[b][b]x[/b][/b]`.
+///
+/// Canonicalizing is idempotent, and parsing its output gives the same
+/// segments as parsing its input, except for removed decorations. Input
+/// that `BbCode` cannot write back is left as it is.
+///
+/// ```
+/// use bbcode::{canonicalize, render::ColorFormat, Canonicalizer};
+///
+/// assert_eq!(
+///     canonicalize("[B][color=red]x[b]y[/b][/color][/B]"),
+///     "[b][color=#ff0000]xy[/color][/b]"
+/// );
+/// assert_eq!(
+///     Canonicalizer::new()
+///         .colors(ColorFormat::Named)
+///         .canonicalize("[color=#F00]x[/color]"),
+///     "[color=red]x[/color]"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Canonicalizer {
+    parser: Parser,
+    colors: ColorFormat,
+}
+
+impl Default for Canonicalizer {
+    fn default() -> Self {
+        Canonicalizer {
+            parser: Parser::new(),
+            colors: ColorFormat::Hex,
+        }
+    }
+}
+
+impl Canonicalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse input with `parser`, which is needed to handle custom tags.
+    pub fn parser(mut self, parser: Parser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Choose how to write colors; `ColorFormat::Hex` by default.
+    pub fn colors(mut self, colors: ColorFormat) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Rewrite bbcode in canonical form.
+    pub fn canonicalize(&self, input: &str) -> String {
+        let segments = self.parser.parse(input);
+        let mut collapsed = segments.clone();
+        normalize::collapse_styles(&mut collapsed);
+        // Removing tags joins up the text around them, which could then be
+        // parsed as something else.
+        match self.write(&collapsed) {
+            Some(output) if equivalent(&self.parser.parse(&output), &collapsed) => output,
+            _ => self.write(&segments).unwrap_or_else(|| input.to_owned()),
+        }
+    }
+
+    fn write(&self, segments: &Vec<Segment>) -> Option<String> {
+        let mut out = vec![];
+        BbCode::new(&mut out)
+            .parser(self.parser.clone())
+            .colors(self.colors)
+            .render(segments)
+            .ok()?;
+        Some(String::from_utf8(out).expect("bbcode output is UTF-8"))
+    }
+}
+
+/// Whether two sequences of segments are the same, ignoring how their text
+/// is divided between text segments.
+fn equivalent(a: &[Segment], b: &[Segment]) -> bool {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (a_text, a_rest) = split_text(a);
+        let (b_text, b_rest) = split_text(b);
+        if a_text != b_text {
+            return false;
+        }
+        let same = match (a_rest.first(), b_rest.first()) {
+            (None, None) => return true,
+            (
+                Some(Segment::Decorated { style, text }),
+                Some(Segment::Decorated {
+                    style: b_style,
+                    text: b_text,
+                }),
+            ) => style == b_style && equivalent(text, b_text),
+            (
+                Some(Segment::Quote { attribution, body }),
+                Some(Segment::Quote {
+                    attribution: b_attribution,
+                    body: b_body,
+                }),
+            ) => attribution == b_attribution && equivalent(body, b_body),
+            (
+                Some(Segment::List { style, items }),
+                Some(Segment::List {
+                    style: b_style,
+                    items: b_items,
+                }),
+            ) => {
+                style == b_style
+                    && items.len() == b_items.len()
+                    && items.iter().zip(b_items).all(|(a, b)| equivalent(a, b))
+            }
+            (
+                Some(Segment::Link { target, text }),
+                Some(Segment::Link {
+                    target: b_target,
+                    text: b_text,
+                }),
+            ) => target == b_target && equivalent(text, b_text),
+            (
+                Some(Segment::Custom { name, attrs, body }),
+                Some(Segment::Custom {
+                    name: b_name,
+                    attrs: b_attrs,
+                    body: b_body,
+                }),
+            ) => name == b_name && attrs == b_attrs && equivalent(body, b_body),
+            (
+                Some(Segment::Heading { level, text }),
+                Some(Segment::Heading {
+                    level: b_level,
+                    text: b_text,
+                }),
+            ) => level == b_level && equivalent(text, b_text),
+            (
+                Some(Segment::Spoiler { title, body }),
+                Some(Segment::Spoiler {
+                    title: b_title,
+                    body: b_body,
+                }),
+            ) => title == b_title && equivalent(body, b_body),
+            (Some(Segment::Table { rows }), Some(Segment::Table { rows: b_rows })) => {
+                rows.len() == b_rows.len()
+                    && rows.iter().zip(b_rows).all(|(a, b)| {
+                        a.len() == b.len()
+                            && a.iter()
+                                .zip(b)
+                                .all(|(a, b)| a.header == b.header && equivalent(&a.body, &b.body))
+                    })
+            }
+            (a, b) => a == b,
+        };
+        if !same {
+            return false;
+        }
+        a = &a_rest[1..];
+        b = &b_rest[1..];
+    }
+}
+
+/// Split leading text segments from the rest, joining up their text.
+fn split_text<'s, 'a>(segments: &'s [Segment<'a>]) -> (String, &'s [Segment<'a>]) {
+    let mut text = String::new();
+    let mut rest = segments;
+    while let Some((Segment::Text(s), tail)) = rest.split_first() {
+        text.push_str(s);
+        rest = tail;
+    }
+    (text, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::render::ColorFormat;
+    use super::{canonicalize, Canonicalizer};
+
+    #[test]
+    fn normalizes_markup() {
+        assert_eq!(
+            canonicalize("[LIST=1][*][URL=x]y[/Url][/list][Img]z[/IMG][quote=\"A\"][/QUOTE]"),
+            "[list=1][*][url=\"x\"]y[/url][/list][img]z[/img][quote=\"A\"][/quote]"
+        );
+    }
+
+    #[test]
+    fn colors() {
+        let named = Canonicalizer::new().colors(ColorFormat::Named);
+        assert_eq!(
+            named.canonicalize("[color=#0ff]a[/color][color=#123456]b[/color]"),
+            "[color=aqua]a[/color][color=#123456]b[/color]"
+        );
+        assert_eq!(
+            canonicalize("[color=cyan]a[/color]"),
+            "[color=#00ffff]a[/color]"
+        );
+    }
+
+    #[test]
+    fn redundant_nesting() {
+        assert_eq!(canonicalize("[b][b]x[/b][/b]"), "[b]x[/b]");
+        assert_eq!(
+            canonicalize("[i]a[quote][I]b[/i][/quote][/i]"),
+            "[i]a[quote]b[/quote][/i]"
+        );
+        // Different colors in between mean it is not redundant.
+        let input = "[color=#ff0000][color=#0000ff][color=#ff0000]x[/color][/color][/color]";
+        assert_eq!(canonicalize(input), input);
+    }
+
+    #[test]
+    fn keeps_tags_that_separate_text() {
+        // Without the inner tags, `[` and `b]` would form a tag.
+        assert_eq!(canonicalize("[b]x[[b][/b]b][/b]"), "[b]x[[b][/b]b][/b]");
+    }
+
+    #[test]
+    fn leaves_unwritable_input() {
+        // Only a later `"]` keeps the `[hide="` in the spoiler from pairing
+        // with `[/hide]`, and `[spoiler]` would end at `[/spoiler]`.
+        let input = "[hide][hide=\"[media]=[/spoiler][/hide][/url][/hide]\"]";
+        assert_eq!(canonicalize(input), input);
+    }
+
+    #[test]
+    fn idempotent() {
+        for input in &[
+            "[U][u]a[/U][/u]",
+            "[b]x[[b][/b]b][/b] [url=[b]]y[/url]",
+            "[tt][mono][url=[*][/tt]|",
+            "[mono][tt][/mono][/b][/tt][tr] ",
+            "[del][s][strike][/del][/s]",
+        ] {
+            let once = canonicalize(input);
+            assert_eq!(canonicalize(&once), once);
+        }
+    }
+}
@@ -1,28 +1,25 @@
 //! Inline code blocks.
 
-use super::Segment;
-use super::Segment::Code;
+#[cfg(test)]
+use super::parse;
+#[cfg(test)]
+use super::Segment::{Code, Text};
 
-named!(pub code(&str) -> Segment,
-    map!(
-        delimited!(
-            tag_no_case!("[code]"),
-            take_until_no_case!("[/code]"),
-            tag_no_case!("[/code]")
-        ),
-        |text| Code(text)
-    )
-);
+// Recognizes `[code]`; the body runs verbatim to the first `[/code]`.
+named!(pub head(&str) -> &str, tag_no_case!("[code]"));
 
 #[test]
 fn empty_block_ok() {
-    assert_eq!(code("[code][/code]"), Ok(("", Code(""))));
+    assert_eq!(parse("[code][/code]"), vec![Code("".into())]);
 }
 
 #[test]
 fn takes_text_to_first_close() {
     assert_eq!(
-        code("[code]10 PRINT HELLO WORLD\n20 GOTO 10[/code]sup[/code]"),
-        Ok(("sup[/code]", Code("10 PRINT HELLO WORLD\n20 GOTO 10")))
+        parse("[code]10 PRINT HELLO WORLD\n20 GOTO 10[/code]sup[/code]"),
+        vec![
+            Code("10 PRINT HELLO WORLD\n20 GOTO 10".into()),
+            Text("sup[/code]".into())
+        ]
     );
 }
@@ -0,0 +1,255 @@
+//! Tags defined outside this crate.
+
+use super::parser::Lookahead;
+use std::fmt;
+
+/// How the body of a custom tag is parsed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Body {
+    /// Any segments, ending at the matching closing tag: `[name]...[/name]`.
+    Nested,
+    /// Raw text up to the first closing tag, like `[code]`.
+    Verbatim,
+    /// No body or closing tag; the opening tag stands alone: `[name]`.
+    Empty,
+}
+
+/// A tag that the parser does not know about on its own.
+///
+/// Register handlers with `Parser::register`; matching tags are parsed into
+/// `Segment::Custom`. The opening tag may have a value for the tag itself and
+/// named attributes:
+///
+///  * `[user=123]`: the value runs to the first `]`
+///  * `[reveal="Ending"]`: quote-delimited value
+///  * `[dice sides=6 count="2"]`: named attributes
+///  * `[box="Note" color=red]`: both, when the value is quoted
+///
+/// The value for the tag itself is given an empty name. Named attributes
+/// may not contain `[`.
+///
+/// ```
+/// use bbcode::{Body, Parser, Segment, TagHandler};
+///
+/// struct User;
+///
+/// impl TagHandler for User {
+///     fn name(&self) -> &str {
+///         "user"
+///     }
+///
+///     fn body(&self) -> Body {
+///         Body::Empty
+///     }
+///
+///     fn accepts(&self, attrs: &[(&str, &str)]) -> bool {
+///         match attrs {
+///             [("", id)] => id.parse::<u32>().is_ok(),
+///             _ => false,
+///         }
+///     }
+/// }
+///
+/// let parser = Parser::new().register(User);
+/// assert_eq!(
+///     parser.parse("Ask [user=42] or [user=me]"),
+///     vec![
+///         Segment::Text("Ask ".into()),
+///         Segment::Custom {
+///             name: "user".into(),
+///             attrs: vec![("".into(), "42".into())],
+///             body: vec![],
+///         },
+///         Segment::Text(" or [user=me]".into()),
+///     ]
+/// );
+/// ```
+pub trait TagHandler: Send + Sync {
+    /// The name of the tag, matched ignoring ASCII case.
+    ///
+    /// Must be ASCII alphanumeric.
+    fn name(&self) -> &str;
+
+    /// How the body of the tag is parsed.
+    fn body(&self) -> Body {
+        Body::Nested
+    }
+
+    /// Decide whether an opening tag with the given attributes is valid.
+    ///
+    /// If not, it is left as text like any other malformed tag. Accepts
+    /// anything by default.
+    fn accepts(&self, _attrs: &[(&str, &str)]) -> bool {
+        true
+    }
+}
+
+impl<'h> fmt::Debug for dyn TagHandler + 'h {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TagHandler({:?})", self.name())
+    }
+}
+
+/// The name and attributes of an opening tag, and the offset of its end.
+type Head<'a> = (&'a str, Vec<(&'a str, &'a str)>, usize);
+
+/// Recognizes the opening tag of a custom tag at `offset`.
+pub fn head<'a>(input: &'a str, offset: usize, lookahead: &mut Lookahead) -> Option<Head<'a>> {
+    let bytes = input.as_bytes();
+    let name_end = offset + 1 + alphanumeric_len(&bytes[offset + 1..]);
+    if name_end == offset + 1 {
+        return None;
+    }
+    let name = &input[offset + 1..name_end];
+
+    let mut attrs = vec![];
+    let mut i = name_end;
+    if bytes.get(i) == Some(&b'=') {
+        if bytes.get(i + 1) == Some(&b'"') {
+            let end = lookahead.find("\"", i + 2)?;
+            attrs.push(("", &input[i + 2..end]));
+            i = end + 1;
+        } else {
+            let end = lookahead.find("]", i + 1)?;
+            attrs.push(("", &input[i + 1..end]));
+            return Some((name, attrs, end + 1));
+        }
+    }
+
+    loop {
+        while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        if bytes.get(i) == Some(&b']') {
+            return Some((name, attrs, i + 1));
+        }
+
+        let key_end = i + alphanumeric_len(&bytes[i..]);
+        if key_end == i || bytes.get(key_end) != Some(&b'=') {
+            return None;
+        }
+        let key = &input[i..key_end];
+
+        let (value, end) = if bytes.get(key_end + 1) == Some(&b'"') {
+            let start = key_end + 2;
+            let len = bytes[start..]
+                .iter()
+                .position(|&b| b == b'"' || b == b'[')?;
+            if bytes[start + len] != b'"' {
+                return None;
+            }
+            (&input[start..start + len], start + len + 1)
+        } else {
+            let start = key_end + 1;
+            let len = bytes[start..]
+                .iter()
+                .take_while(|&&b| !b.is_ascii_whitespace() && b != b']' && b != b'[')
+                .count();
+            (&input[start..start + len], start + len)
+        };
+        attrs.push((key, value));
+        i = end;
+    }
+}
+
+fn alphanumeric_len(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Parser, Segment};
+    use super::{Body, TagHandler};
+
+    struct Tag(&'static str, Body);
+
+    impl TagHandler for Tag {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn body(&self) -> Body {
+            self.1
+        }
+    }
+
+    fn parser() -> Parser {
+        Parser::new()
+            .register(Tag("reveal", Body::Nested))
+            .register(Tag("dice", Body::Verbatim))
+            .register(Tag("br", Body::Empty))
+    }
+
+    #[test]
+    fn nested_body() {
+        assert_eq!(
+            parser().parse("[REVEAL=\"Act 2\" open=yes][i]x[/i][/reveal]"),
+            vec![Segment::Custom {
+                name: "REVEAL".into(),
+                attrs: vec![("".into(), "Act 2".into()), ("open".into(), "yes".into())],
+                body: vec![Segment::Decorated {
+                    style: ::DecorationStyle::Italic,
+                    text: vec![Segment::Text("x".into())],
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn verbatim_body() {
+        assert_eq!(
+            parser().parse("[dice count=\"2\" sides=6][b]d6[/dice]"),
+            vec![Segment::Custom {
+                name: "dice".into(),
+                attrs: vec![("count".into(), "2".into()), ("sides".into(), "6".into())],
+                body: vec![Segment::Text("[b]d6".into())],
+            }]
+        );
+    }
+
+    #[test]
+    fn empty_body() {
+        assert_eq!(
+            parser().parse("a[br]b"),
+            vec![
+                Segment::Text("a".into()),
+                Segment::Custom {
+                    name: "br".into(),
+                    attrs: vec![],
+                    body: vec![],
+                },
+                Segment::Text("b".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unregistered_and_malformed_tags_are_text() {
+        for s in &[
+            "[reveal][/dice]",
+            "[reveal open][/reveal]",
+            "[reveal a=\"[b]\"][/reveal]",
+            "[blink]x[/blink]",
+        ] {
+            assert_eq!(parser().parse(s), vec![Segment::Text((*s).into())]);
+        }
+    }
+
+    #[test]
+    fn replaces_disabled_builtin() {
+        let parser = Parser::new()
+            .disable(::Tag::Quote)
+            .register(Tag("quote", Body::Verbatim));
+        assert_eq!(
+            parser.parse("[quote=\"A\"][b]x[/quote]"),
+            vec![Segment::Custom {
+                name: "quote".into(),
+                attrs: vec![("".into(), "A".into())],
+                body: vec![Segment::Text("[b]x".into())],
+            }]
+        );
+    }
+}
@@ -1,11 +1,17 @@
 //! Plain text spans with additional decoration.
 
-use super::{segment, Segment};
+#[cfg(test)]
+use super::{parse, Segment};
 use palette::Srgb;
 use std::num::NonZeroU8;
 
 /// Styles that can be applied to decorated spans.
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum DecorationStyle {
     Bold,
     Italic,
@@ -13,81 +19,140 @@ pub enum DecorationStyle {
     /// Horizontally centered.
     Center,
     /// Colored with specified sRGB components (as in CSS).
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            serialize_with = "::serialize::serialize_color",
+            deserialize_with = "::serialize::deserialize_color"
+        )
+    )]
     Color(u8, u8, u8),
     /// Font size at some arbitrary scale.
     Size(NonZeroU8),
+    Subscript,
+    Superscript,
+    Strikethrough,
+    /// In a monospaced font.
+    Monospace,
 }
 
-fn styled(s: DecorationStyle) -> impl for<'a> Fn(Vec<Segment<'a>>) -> Segment<'a> {
-    move |text| Segment::Decorated { style: s, text }
-}
-
-named!(pub decorated(&str) -> Segment,
-    alt!(map!(bold, styled(DecorationStyle::Bold))
-        | map!(italic, styled(DecorationStyle::Italic))
-        | map!(underline, styled(DecorationStyle::Underline))
-        | map!(center, styled(DecorationStyle::Center))
-        | color
-        | size
+// Recognizes the opening tag of any decoration, like `[b]` or `[color=red]`,
+// giving its style and the name of the tag that closes it, which differs
+// between aliases like `[s]` and `[strike]`.
+named!(pub head(&str) -> (DecorationStyle, &'static str),
+    alt!(value!((DecorationStyle::Bold, "b"), tag_no_case!("[b]"))
+        | value!((DecorationStyle::Italic, "i"), tag_no_case!("[i]"))
+        | value!((DecorationStyle::Underline, "u"), tag_no_case!("[u]"))
+        | value!((DecorationStyle::Center, "center"), tag_no_case!("[center]"))
+        | map!(color_head, |(r, g, b)| (DecorationStyle::Color(r, g, b), "color"))
+        | map!(size_head, |size| (DecorationStyle::Size(size), "size"))
+        | value!((DecorationStyle::Subscript, "sub"), tag_no_case!("[sub]"))
+        | value!((DecorationStyle::Superscript, "sup"), tag_no_case!("[sup]"))
+        | value!((DecorationStyle::Strikethrough, "strike"), tag_no_case!("[strike]"))
+        | value!((DecorationStyle::Strikethrough, "s"), tag_no_case!("[s]"))
+        | value!((DecorationStyle::Strikethrough, "del"), tag_no_case!("[del]"))
+        | value!((DecorationStyle::Monospace, "mono"), tag_no_case!("[mono]"))
+        | value!((DecorationStyle::Monospace, "tt"), tag_no_case!("[tt]"))
     )
 );
 
-/// Recognizes `[b]This text is bold[/b]`.
-named!(pub bold(&str) -> Vec<Segment>,
-    delimited!(
-        tag_no_case!("[b]"),
-        many0!(call!(segment, "[/b]")),
-        tag_no_case!("[/b]")
-    )
-);
+/// The name of the tag that closes a decoration of the given style, as
+/// opened by its usual tag rather than an alias.
+pub fn closer(style: DecorationStyle) -> &'static str {
+    match style {
+        DecorationStyle::Bold => "b",
+        DecorationStyle::Italic => "i",
+        DecorationStyle::Underline => "u",
+        DecorationStyle::Center => "center",
+        DecorationStyle::Color(..) => "color",
+        DecorationStyle::Size(_) => "size",
+        DecorationStyle::Subscript => "sub",
+        DecorationStyle::Superscript => "sup",
+        DecorationStyle::Strikethrough => "strike",
+        DecorationStyle::Monospace => "mono",
+    }
+}
+
+/// The names of the tags that open a decoration of the given style, its
+/// usual tag first and then any aliases.
+pub fn names(style: DecorationStyle) -> &'static [&'static str] {
+    match style {
+        DecorationStyle::Strikethrough => &["strike", "s", "del"],
+        DecorationStyle::Monospace => &["mono", "tt"],
+        _ => &[],
+    }
+}
 
 #[test]
 fn bold_text() {
-    assert_eq!(bold("[b]BOLD![/b]"), Ok(("", vec![Segment::Text("BOLD!")])));
+    assert_eq!(
+        parse("[b]BOLD![/b]"),
+        vec![Segment::Decorated {
+            style: DecorationStyle::Bold,
+            text: vec![Segment::Text("BOLD!".into())],
+        }]
+    );
 }
 
-named!(pub italic(&str) -> Vec<Segment>,
-    delimited!(
-        tag_no_case!("[i]"),
-        many0!(call!(segment, "[/i]")),
-        tag_no_case!("[/i]")
-    )
-);
-
-named!(pub underline(&str) -> Vec<Segment>,
-    delimited!(
-        tag_no_case!("[u]"),
-        many0!(call!(segment, "[/u]")),
-        tag_no_case!("[/u]")
-    )
-);
-
 #[test]
 fn underlined_text() {
-    assert_eq!(underline("[u]um[/u]"), Ok(("", vec![Segment::Text("um")])));
+    assert_eq!(
+        parse("[u]um[/u]"),
+        vec![Segment::Decorated {
+            style: DecorationStyle::Underline,
+            text: vec![Segment::Text("um".into())],
+        }]
+    );
 }
 
-named!(pub center(&str) -> Vec<Segment>,
-    delimited!(
-        tag_no_case!("[center]"),
-        many0!(call!(segment, "[/center]")),
-        tag_no_case!("[/center]")
-    )
-);
+#[test]
+fn scripts() {
+    assert_eq!(
+        parse("H[sub]2[/sub]O, x[SUP]n[/sup]"),
+        vec![
+            Segment::Text("H".into()),
+            Segment::Decorated {
+                style: DecorationStyle::Subscript,
+                text: vec![Segment::Text("2".into())],
+            },
+            Segment::Text("O, x".into()),
+            Segment::Decorated {
+                style: DecorationStyle::Superscript,
+                text: vec![Segment::Text("n".into())],
+            },
+        ]
+    );
+}
 
-named!(pub color(&str) -> Segment,
-    map!(
-        pair!(
-            color_head,
-            terminated!(many0!(call!(segment, "[/color]")),
-                        tag_no_case!("[/color]"))
-        ),
-        |((r, g, b), text)| Segment::Decorated {
-            style: DecorationStyle::Color(r, g, b),
-            text,
-        }
-    )
-);
+#[test]
+fn aliases() {
+    let struck = |s: &str| Segment::Decorated {
+        style: DecorationStyle::Strikethrough,
+        text: vec![Segment::Text(s.to_owned().into())],
+    };
+    assert_eq!(
+        parse("[strike]a[/strike][s]b[/s][del]c[/del]"),
+        vec![struck("a"), struck("b"), struck("c")]
+    );
+    assert_eq!(
+        parse("[mono]a[/mono][tt]b[/tt]"),
+        vec![
+            Segment::Decorated {
+                style: DecorationStyle::Monospace,
+                text: vec![Segment::Text("a".into())],
+            },
+            Segment::Decorated {
+                style: DecorationStyle::Monospace,
+                text: vec![Segment::Text("b".into())],
+            },
+        ]
+    );
+    // Each alias is closed by its own closing tag.
+    assert_eq!(
+        parse("[s]a[/strike]"),
+        vec![Segment::Text("[s]a[/strike]".into())]
+    );
+}
 
 named!(color_head(&str) -> (u8, u8, u8),
     delimited!(
@@ -132,30 +197,204 @@ named!(css_color(&str) -> (u8, u8, u8),
     )
 );
 
+/// Parse a color as `[color=...]` accepts it: `#rgb`, `#rrggbb` or a name.
+pub fn parse_color(s: &str) -> Option<(u8, u8, u8)> {
+    // The parsers can't tell that digits or a name end with the input, so
+    // give them the bracket that would end the tag.
+    let input = format!("{}]", s);
+    match alt!(&input[..], rgb_color | css_color) {
+        Ok(("]", color)) => Some(color),
+        _ => None,
+    }
+}
+
+/// Names of the colors accepted by `[color=...]`, in alphabetical order.
+const COLOR_NAMES: [&str; 148] = [
+    "aliceblue",
+    "antiquewhite",
+    "aqua",
+    "aquamarine",
+    "azure",
+    "beige",
+    "bisque",
+    "black",
+    "blanchedalmond",
+    "blue",
+    "blueviolet",
+    "brown",
+    "burlywood",
+    "cadetblue",
+    "chartreuse",
+    "chocolate",
+    "coral",
+    "cornflowerblue",
+    "cornsilk",
+    "crimson",
+    "cyan",
+    "darkblue",
+    "darkcyan",
+    "darkgoldenrod",
+    "darkgray",
+    "darkgreen",
+    "darkgrey",
+    "darkkhaki",
+    "darkmagenta",
+    "darkolivegreen",
+    "darkorange",
+    "darkorchid",
+    "darkred",
+    "darksalmon",
+    "darkseagreen",
+    "darkslateblue",
+    "darkslategray",
+    "darkslategrey",
+    "darkturquoise",
+    "darkviolet",
+    "deeppink",
+    "deepskyblue",
+    "dimgray",
+    "dimgrey",
+    "dodgerblue",
+    "firebrick",
+    "floralwhite",
+    "forestgreen",
+    "fuchsia",
+    "gainsboro",
+    "ghostwhite",
+    "gold",
+    "goldenrod",
+    "gray",
+    "grey",
+    "green",
+    "greenyellow",
+    "honeydew",
+    "hotpink",
+    "indianred",
+    "indigo",
+    "ivory",
+    "khaki",
+    "lavender",
+    "lavenderblush",
+    "lawngreen",
+    "lemonchiffon",
+    "lightblue",
+    "lightcoral",
+    "lightcyan",
+    "lightgoldenrodyellow",
+    "lightgray",
+    "lightgreen",
+    "lightgrey",
+    "lightpink",
+    "lightsalmon",
+    "lightseagreen",
+    "lightskyblue",
+    "lightslategray",
+    "lightslategrey",
+    "lightsteelblue",
+    "lightyellow",
+    "lime",
+    "limegreen",
+    "linen",
+    "magenta",
+    "maroon",
+    "mediumaquamarine",
+    "mediumblue",
+    "mediumorchid",
+    "mediumpurple",
+    "mediumseagreen",
+    "mediumslateblue",
+    "mediumspringgreen",
+    "mediumturquoise",
+    "mediumvioletred",
+    "midnightblue",
+    "mintcream",
+    "mistyrose",
+    "moccasin",
+    "navajowhite",
+    "navy",
+    "oldlace",
+    "olive",
+    "olivedrab",
+    "orange",
+    "orangered",
+    "orchid",
+    "palegoldenrod",
+    "palegreen",
+    "paleturquoise",
+    "palevioletred",
+    "papayawhip",
+    "peachpuff",
+    "peru",
+    "pink",
+    "plum",
+    "powderblue",
+    "purple",
+    "rebeccapurple",
+    "red",
+    "rosybrown",
+    "royalblue",
+    "saddlebrown",
+    "salmon",
+    "sandybrown",
+    "seagreen",
+    "seashell",
+    "sienna",
+    "silver",
+    "skyblue",
+    "slateblue",
+    "slategray",
+    "slategrey",
+    "snow",
+    "springgreen",
+    "steelblue",
+    "tan",
+    "teal",
+    "thistle",
+    "tomato",
+    "turquoise",
+    "violet",
+    "wheat",
+    "white",
+    "whitesmoke",
+    "yellow",
+    "yellowgreen",
+];
+
+/// The name of a color, if it has one.
+///
+/// Of colors with several names, like `aqua` and `cyan`, the first in
+/// alphabetical order is chosen.
+pub fn color_name(r: u8, g: u8, b: u8) -> Option<&'static str> {
+    COLOR_NAMES
+        .iter()
+        .cloned()
+        .find(|&name| palette::named::from_str(name).map(Srgb::into_components) == Some((r, g, b)))
+}
+
 #[test]
 fn accepts_colors() {
     assert_eq!(
-        color("[color=red]asdf[/color]").unwrap().1,
-        Segment::Decorated {
+        parse("[color=red]asdf[/color]"),
+        vec![Segment::Decorated {
             style: DecorationStyle::Color(255, 0, 0),
-            text: vec![Segment::Text("asdf")],
-        }
+            text: vec![Segment::Text("asdf".into())],
+        }]
     );
 
     assert_eq!(
-        color("[color=#81f][/color]").unwrap().1,
-        Segment::Decorated {
+        parse("[color=#81f][/color]"),
+        vec![Segment::Decorated {
             style: DecorationStyle::Color(0x88, 0x11, 0xFF),
             text: vec![],
-        }
+        }]
     );
 
     assert_eq!(
-        color("[color=#01FE9A]and[/color]").unwrap().1,
-        Segment::Decorated {
+        parse("[color=#01FE9A]and[/color]"),
+        vec![Segment::Decorated {
             style: DecorationStyle::Color(1, 0xFE, 0x9A),
-            text: vec![Segment::Text("and")],
-        }
+            text: vec![Segment::Text("and".into())],
+        }]
     );
 }
 
@@ -164,19 +403,21 @@ fn rejects_invalid_css_colors() {
     assert!(css_color("beyblade").is_err());
 }
 
-named!(pub size(&str) -> Segment,
-    map!(
-        pair!(
-            size_head,
-            terminated!(many0!(call!(segment, "[/size]")),
-                        tag_no_case!("[/size]"))
-        ),
-        |(size, text)| Segment::Decorated {
-            style: DecorationStyle::Size(size),
-            text,
-        }
-    )
-);
+#[test]
+fn parses_whole_colors() {
+    assert_eq!(parse_color("teal"), Some((0, 0x80, 0x80)));
+    assert_eq!(parse_color("#0aF"), Some((0, 0xAA, 0xFF)));
+    assert_eq!(parse_color("#0aF0"), None);
+    assert_eq!(parse_color("red]"), None);
+    assert_eq!(parse_color(""), None);
+}
+
+#[test]
+fn names_colors() {
+    assert_eq!(color_name(0, 255, 255), Some("aqua"));
+    assert_eq!(color_name(0x66, 0x33, 0x99), Some("rebeccapurple"));
+    assert_eq!(color_name(1, 2, 3), None);
+}
 
 named!(size_head(&str) -> NonZeroU8,
     map_opt!(
@@ -189,20 +430,25 @@ named!(size_head(&str) -> NonZeroU8,
                 ),
                 str::parse::<u8>
             ),
-            |x| x >= 2 && x < 30
+            is_valid_size
         ),
         NonZeroU8::new
     )
 );
 
+/// Whether `[size=...]` accepts a size.
+pub fn is_valid_size(size: u8) -> bool {
+    (2..30).contains(&size)
+}
+
 #[test]
 fn enforces_size_limits() {
     assert_eq!(
-        size("[size=10]midsize[/size]").unwrap().1,
-        Segment::Decorated {
+        parse("[size=10]midsize[/size]"),
+        vec![Segment::Decorated {
             style: DecorationStyle::Size(NonZeroU8::new(10).unwrap()),
-            text: vec![Segment::Text("midsize")],
-        }
+            text: vec![Segment::Text("midsize".into())],
+        }]
     );
     assert!(size_head("[size=0]").is_err());
     assert!(size_head("[size=50]").is_err());
@@ -0,0 +1,656 @@
+//! Media embedded from other sites.
+//!
+//! There are several forms of embed tag:
+//!  * `[youtube]dQw4w9WgXcQ[/youtube]`: a YouTube video, by id or URL
+//!  * `[media]https://vimeo.com/76979871[/media]`: media from any provider,
+//!    by URL; `[video]` is the same
+//!  * `[media=vimeo]76979871[/media]`: media from the named provider, by id
+//!    or URL
+//!
+//! Like `[code]`, an embed tag runs to the first closing tag of the same
+//! name. It is only recognized if a provider recognizes its body, which is
+//! stripped of surrounding whitespace first.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+// Recognizes the opening tag of an embed, giving the name of the tag that
+// closes it and the provider it names, if any.
+named!(pub head(&str) -> (&'static str, Option<&str>),
+    alt!(value!(("youtube", Some("youtube")), tag_no_case!("[youtube]"))
+        | map!(preceded!(tag_no_case!("[video"), provider), |p| ("video", p))
+        | map!(preceded!(tag_no_case!("[media"), provider), |p| ("media", p))
+    )
+);
+
+// Recognizes the end of the opening tag of an embed: `]`, or `=name]` with
+// the name of a provider.
+named!(provider(&str) -> Option<&str>,
+    alt!(value!(None, char!(']'))
+        | map!(delimited!(char!('='), nom::alphanumeric1, char!(']')), Some)
+    )
+);
+
+/// A piece of media, as an `EmbedProvider` identifies it.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct Media {
+    /// The id of the media on the provider's site.
+    pub id: String,
+    /// Any further parameters, like the time to start playing at, as
+    /// name-value pairs.
+    pub params: Vec<(String, String)>,
+}
+
+/// A site whose media can be embedded, like YouTube.
+///
+/// Providers are registered with `Providers::register`. Those given to a
+/// `Parser` with `Parser::providers` turn embed tags into `Segment::Embed`,
+/// and those given to a renderer give the URLs to write for them.
+///
+/// `Segment::Embed` may be built or deserialized with any id and parameters,
+/// so a provider must make sure the URLs it gives for them are safe, such as
+/// by percent-encoding the id. The URL of a page must also be recognized as
+/// the same media, so that `BbCode` can write media as its page.
+///
+/// ```
+/// use bbcode::{EmbedProvider, Media, Parser, Providers, Segment};
+/// use std::borrow::Cow;
+///
+/// struct Clips;
+///
+/// impl EmbedProvider for Clips {
+///     fn name(&self) -> &str {
+///         "clips"
+///     }
+///
+///     fn media(&self, url: &str) -> Option<Media> {
+///         let id = url.strip_prefix("https://clips.example/")?;
+///         if self.is_id(id) {
+///             Some(Media { id: id.to_owned(), params: vec![] })
+///         } else {
+///             None
+///         }
+///     }
+///
+///     fn is_id(&self, id: &str) -> bool {
+///         !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit())
+///     }
+///
+///     fn player(&self, id: &str, _params: &[(Cow<str>, Cow<str>)]) -> String {
+///         format!("https://clips.example/embed/{}", id)
+///     }
+///
+///     fn page(&self, id: &str, _params: &[(Cow<str>, Cow<str>)]) -> String {
+///         format!("https://clips.example/{}", id)
+///     }
+/// }
+///
+/// let parser = Parser::new().providers(Providers::new().register(Clips));
+/// assert_eq!(
+///     parser.parse("[media]https://clips.example/42[/media]"),
+///     vec![Segment::Embed {
+///         provider: "clips".into(),
+///         id: "42".into(),
+///         params: vec![],
+///     }]
+/// );
+/// ```
+pub trait EmbedProvider: Send + Sync {
+    /// The name of the provider, as in `[media=name]`, matched ignoring ASCII
+    /// case.
+    ///
+    /// Must be ASCII alphanumeric.
+    fn name(&self) -> &str;
+
+    /// The media at a URL, if it is one of the provider's.
+    fn media(&self, url: &str) -> Option<Media>;
+
+    /// Whether a string is the id of some media of the provider's, as in
+    /// `[media=name]id[/media]`.
+    fn is_id(&self, id: &str) -> bool;
+
+    /// The URL of a player for the media, to be embedded in a frame.
+    fn player(&self, id: &str, params: &[(Cow<str>, Cow<str>)]) -> String;
+
+    /// The URL of a page showing the media, to link to where it can't be
+    /// embedded.
+    fn page(&self, id: &str, params: &[(Cow<str>, Cow<str>)]) -> String;
+}
+
+impl<'p> fmt::Debug for dyn EmbedProvider + 'p {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EmbedProvider({:?})", self.name())
+    }
+}
+
+/// A registry of `EmbedProvider`s, looked up by name.
+///
+/// `Providers::new()` has the built-in providers, `YouTube` and `Vimeo`;
+/// `Providers::empty()` has none, so no media is recognized.
+///
+/// ```
+/// use bbcode::{parse, Parser, Providers, Segment};
+///
+/// let segments = parse("[media]https://youtu.be/dQw4w9WgXcQ?t=1m5s[/media]");
+/// assert_eq!(
+///     segments,
+///     vec![Segment::Embed {
+///         provider: "youtube".into(),
+///         id: "dQw4w9WgXcQ".into(),
+///         params: vec![("start".into(), "65".into())],
+///     }]
+/// );
+///
+/// let parser = Parser::new().providers(Providers::empty());
+/// assert!(matches!(parser.parse("[youtube]dQw4w9WgXcQ[/youtube]")[0], Segment::Text(_)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Providers {
+    providers: Vec<Arc<dyn EmbedProvider>>,
+}
+
+impl Default for Providers {
+    fn default() -> Self {
+        Providers::new()
+    }
+}
+
+impl Providers {
+    pub fn new() -> Self {
+        Providers::empty().register(YouTube).register(Vimeo)
+    }
+
+    /// A registry without any providers.
+    pub fn empty() -> Self {
+        Providers { providers: vec![] }
+    }
+
+    /// Add a provider. If several have the same name, the first one
+    /// registered wins, and any provider recognizing a URL may be given it.
+    ///
+    /// # Panics
+    ///
+    /// If the provider's name is empty or not ASCII alphanumeric.
+    pub fn register<P: EmbedProvider + 'static>(mut self, provider: P) -> Self {
+        let name = provider.name();
+        assert!(
+            !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric()),
+            "provider name {:?} must be ASCII alphanumeric",
+            name
+        );
+        self.providers.push(Arc::new(provider));
+        self
+    }
+
+    /// The provider with a name, matching it ignoring ASCII case.
+    pub fn get(&self, name: &str) -> Option<&dyn EmbedProvider> {
+        self.providers
+            .iter()
+            .find(|p| p.name().eq_ignore_ascii_case(name))
+            .map(|p| &**p)
+    }
+
+    /// Find the media given by the body of an embed tag, with its provider.
+    ///
+    /// With the name of a provider, the body may be the URL or id of its
+    /// media; otherwise it must be a URL that some provider recognizes.
+    pub fn find(&self, name: Option<&str>, body: &str) -> Option<(&dyn EmbedProvider, Media)> {
+        let body = body.trim();
+        if let Some(name) = name {
+            let provider = self.get(name)?;
+            let media = provider.media(body).or_else(|| {
+                if provider.is_id(body) {
+                    Some(Media {
+                        id: body.to_owned(),
+                        params: vec![],
+                    })
+                } else {
+                    None
+                }
+            })?;
+            return Some((provider, media));
+        }
+        self.providers
+            .iter()
+            .filter_map(|p| p.media(body).map(|media| (&**p, media)))
+            .next()
+    }
+}
+
+/// YouTube videos.
+///
+/// Watch, embed, shorts and live URLs of `youtube.com` are recognized, as
+/// are `youtu.be` links. A start time given by `t` or `start` in seconds or
+/// like `1m30s` becomes a `start` parameter in seconds. Players are from
+/// `youtube-nocookie.com`, which sets no cookies until a video is played.
+#[derive(Debug, Clone, Copy)]
+pub struct YouTube;
+
+impl EmbedProvider for YouTube {
+    fn name(&self) -> &str {
+        "youtube"
+    }
+
+    fn media(&self, url: &str) -> Option<Media> {
+        let url = Url::parse(url)?;
+        let id = match &url.host[..] {
+            "youtu.be" => url.path.trim_matches('/'),
+            "youtube.com" | "youtube-nocookie.com" if url.path == "/watch" => url.param("v")?,
+            "youtube.com" | "youtube-nocookie.com" => {
+                let (kind, id) = url.path.trim_matches('/').split_once('/')?;
+                match kind {
+                    "embed" | "shorts" | "live" | "v" => id,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        if !self.is_id(id) {
+            return None;
+        }
+        let start = url
+            .param("t")
+            .or_else(|| url.param("start"))
+            .or_else(|| url.fragment.strip_prefix("t="));
+        Some(Media {
+            id: id.to_owned(),
+            params: start_param(start),
+        })
+    }
+
+    fn is_id(&self, id: &str) -> bool {
+        id.len() == 11
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    }
+
+    fn player(&self, id: &str, params: &[(Cow<str>, Cow<str>)]) -> String {
+        let mut url = format!("https://www.youtube-nocookie.com/embed/{}", encode(id));
+        if let Some(start) = param(params, "start") {
+            url.push_str(&format!("?start={}", start));
+        }
+        url
+    }
+
+    fn page(&self, id: &str, params: &[(Cow<str>, Cow<str>)]) -> String {
+        let mut url = format!("https://www.youtube.com/watch?v={}", encode(id));
+        if let Some(start) = param(params, "start") {
+            url.push_str(&format!("&t={}s", start));
+        }
+        url
+    }
+}
+
+/// Vimeo videos.
+///
+/// URLs of videos on `vimeo.com` are recognized, including those in
+/// channels, groups and showcases, as are players on `player.vimeo.com`.
+/// The hash of an unlisted video becomes an `h` parameter, and a start time
+/// like `#t=1m30s` a `start` parameter in seconds. Players are told not to
+/// track viewers.
+#[derive(Debug, Clone, Copy)]
+pub struct Vimeo;
+
+impl EmbedProvider for Vimeo {
+    fn name(&self) -> &str {
+        "vimeo"
+    }
+
+    fn media(&self, url: &str) -> Option<Media> {
+        let url = Url::parse(url)?;
+        let segments: Vec<&str> = url.path.split('/').filter(|s| !s.is_empty()).collect();
+        let (id, hash) = match &url.host[..] {
+            "player.vimeo.com" => match segments[..] {
+                ["video", id] => (id, url.param("h")),
+                _ => return None,
+            },
+            "vimeo.com" => {
+                // Albums and showcases have numeric ids of their own, before
+                // that of the video.
+                let i = segments.iter().enumerate().position(|(i, s)| {
+                    self.is_id(s) && (i == 0 || !["album", "showcase"].contains(&segments[i - 1]))
+                })?;
+                let hash = segments.get(i + 1).cloned().or_else(|| url.param("h"));
+                (segments[i], hash)
+            }
+            _ => return None,
+        };
+        if !self.is_id(id) {
+            return None;
+        }
+
+        let mut params = vec![];
+        if let Some(hash) = hash {
+            if !is_hash(hash) {
+                return None;
+            }
+            params.push(("h".to_owned(), hash.to_owned()));
+        }
+        let start = url.fragment.strip_prefix("t=").or_else(|| url.param("t"));
+        params.extend(start_param(start));
+        Some(Media {
+            id: id.to_owned(),
+            params,
+        })
+    }
+
+    fn is_id(&self, id: &str) -> bool {
+        !id.is_empty() && id.len() <= 12 && id.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    fn player(&self, id: &str, params: &[(Cow<str>, Cow<str>)]) -> String {
+        let mut url = format!("https://player.vimeo.com/video/{}?dnt=1", encode(id));
+        if let Some(hash) = param(params, "h").filter(|h| is_hash(h)) {
+            url.push_str(&format!("&h={}", hash));
+        }
+        if let Some(start) = param(params, "start") {
+            url.push_str(&format!("#t={}s", start));
+        }
+        url
+    }
+
+    fn page(&self, id: &str, params: &[(Cow<str>, Cow<str>)]) -> String {
+        let mut url = format!("https://vimeo.com/{}", encode(id));
+        if let Some(hash) = param(params, "h").filter(|h| is_hash(h)) {
+            url.push_str(&format!("/{}", hash));
+        }
+        if let Some(start) = param(params, "start") {
+            url.push_str(&format!("#t={}s", start));
+        }
+        url
+    }
+}
+
+/// The parts of an `http` or `https` URL that providers look at.
+struct Url<'u> {
+    /// The host in lowercase, without any `www.` or `m.` in front.
+    host: String,
+    path: &'u str,
+    query: &'u str,
+    fragment: &'u str,
+}
+
+impl<'u> Url<'u> {
+    /// Split up a URL, which may also have no scheme, like `youtu.be/...`.
+    fn parse(url: &'u str) -> Option<Self> {
+        let rest = ["https://", "http://", "//"]
+            .iter()
+            .find_map(|scheme| {
+                url.get(..scheme.len())
+                    .filter(|s| s.eq_ignore_ascii_case(scheme))
+                    .map(|_| &url[scheme.len()..])
+            })
+            .unwrap_or(url);
+        let (rest, fragment) = rest.split_once('#').unwrap_or((rest, ""));
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (host, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, ""),
+        };
+        if host.is_empty() || host.contains([':', '@']) {
+            return None;
+        }
+        let host = host.to_ascii_lowercase();
+        let host = host
+            .strip_prefix("www.")
+            .or_else(|| host.strip_prefix("m."))
+            .unwrap_or(&host)
+            .to_owned();
+        Some(Url {
+            host,
+            path,
+            query,
+            fragment,
+        })
+    }
+
+    /// The value of the first query parameter with a name.
+    fn param(&self, name: &str) -> Option<&'u str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|&(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// The value of the first parameter of an embed with a name.
+fn param<'p>(params: &'p [(Cow<str>, Cow<str>)], name: &str) -> Option<&'p str> {
+    params
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| &value[..])
+        .filter(|value| !value.is_empty())
+        .filter(|value| name != "start" || value.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// A `start` parameter for a time given in a URL, unless it is missing or
+/// zero.
+fn start_param(time: Option<&str>) -> Vec<(String, String)> {
+    match time.and_then(seconds) {
+        Some(seconds) if seconds > 0 => vec![("start".to_owned(), seconds.to_string())],
+        _ => vec![],
+    }
+}
+
+/// Parse a time like `90`, `90s` or `1h2m30s` into seconds.
+fn seconds(time: &str) -> Option<u32> {
+    if !time.is_empty() && time.bytes().all(|b| b.is_ascii_digit()) {
+        return time.parse().ok();
+    }
+    let mut total: u32 = 0;
+    let mut rest = time;
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let n: u32 = rest[..digits].parse().ok()?;
+        let unit = match rest[digits..].chars().next()? {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(n.checked_mul(unit)?)?;
+        rest = &rest[digits + 1..];
+    }
+    Some(total)
+}
+
+/// Whether a string could be the hash of an unlisted Vimeo video.
+fn is_hash(s: &str) -> bool {
+    !s.is_empty() && s.len() <= 32 && s.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Percent-encode everything but unreserved characters, so an id can't
+/// change the rest of a URL.
+fn encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse, Segment};
+    use super::{head, EmbedProvider, Media, Providers, Vimeo, YouTube};
+    use std::borrow::Cow;
+
+    fn media(id: &str, params: &[(&str, &str)]) -> Option<Media> {
+        Some(Media {
+            id: id.to_owned(),
+            params: params
+                .iter()
+                .map(|&(n, v)| (n.to_owned(), v.to_owned()))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn heads() {
+        assert_eq!(head("[YouTube]x"), Ok(("x", ("youtube", Some("youtube")))));
+        assert_eq!(head("[video]x"), Ok(("x", ("video", None))));
+        assert_eq!(head("[media=Vimeo]x"), Ok(("x", ("media", Some("Vimeo")))));
+        assert!(head("[media=]x").is_err());
+        assert!(head("[media=a b]x").is_err());
+    }
+
+    #[test]
+    fn youtube_urls() {
+        let id = "dQw4w9WgXcQ";
+        for url in &[
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "http://youtube.com/watch?feature=share&v=dQw4w9WgXcQ",
+            "HTTPS://M.YOUTUBE.COM/watch?v=dQw4w9WgXcQ",
+            "youtu.be/dQw4w9WgXcQ",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ",
+            "https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ",
+            "https://youtube.com/shorts/dQw4w9WgXcQ/",
+            "//www.youtube.com/live/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(YouTube.media(url), media(id, &[]), "{}", url);
+        }
+        for &(url, start) in &[
+            ("https://youtu.be/dQw4w9WgXcQ?t=42", "42"),
+            ("https://youtu.be/dQw4w9WgXcQ?si=abc&t=1h2m3s", "3723"),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=90s", "90"),
+            ("https://www.youtube.com/embed/dQw4w9WgXcQ?start=7", "7"),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ#t=2m", "120"),
+        ] {
+            assert_eq!(
+                YouTube.media(url),
+                media(id, &[("start", start)]),
+                "{}",
+                url
+            );
+        }
+        for url in &[
+            "https://www.youtube.com/watch?v=dQw4w9WgXc",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ!",
+            "https://www.youtube.com/channel/dQw4w9WgXcQ",
+            "https://youtube.com.evil/watch?v=dQw4w9WgXcQ",
+            "https://evil@youtube.com/watch?v=dQw4w9WgXcQ",
+            "javascript:youtu.be/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(YouTube.media(url), None, "{}", url);
+        }
+        assert_eq!(
+            YouTube.media("https://youtu.be/dQw4w9WgXcQ?t=0"),
+            media(id, &[])
+        );
+        assert_eq!(
+            YouTube.media("https://youtu.be/dQw4w9WgXcQ?t=soon"),
+            media(id, &[])
+        );
+    }
+
+    #[test]
+    fn vimeo_urls() {
+        for url in &[
+            "https://vimeo.com/76979871",
+            "https://vimeo.com/channels/staffpicks/76979871",
+            "https://vimeo.com/groups/name/videos/76979871",
+            "https://vimeo.com/showcase/123/video/76979871",
+            "https://player.vimeo.com/video/76979871",
+        ] {
+            assert_eq!(Vimeo.media(url), media("76979871", &[]), "{}", url);
+        }
+        assert_eq!(
+            Vimeo.media("https://vimeo.com/76979871/a1b2c3#t=1m5s"),
+            media("76979871", &[("h", "a1b2c3"), ("start", "65")])
+        );
+        assert_eq!(
+            Vimeo.media("https://player.vimeo.com/video/76979871?h=a1b2c3"),
+            media("76979871", &[("h", "a1b2c3")])
+        );
+        assert_eq!(Vimeo.media("https://vimeo.com/about"), None);
+        assert_eq!(Vimeo.media("https://vimeo.com/76979871/a-b"), None);
+    }
+
+    #[test]
+    fn urls_are_recognized_as_media() {
+        for provider in &[&YouTube as &dyn EmbedProvider, &Vimeo] {
+            for params in &[
+                vec![],
+                vec![("h", "a1b2c3"), ("start", "65")],
+                vec![("start", "3")],
+            ] {
+                let id = if provider.name() == "youtube" {
+                    "dQw4w9WgXcQ"
+                } else {
+                    "76979871"
+                };
+                let params: Vec<(Cow<str>, Cow<str>)> = params
+                    .iter()
+                    .filter(|&&(name, _)| name != "h" || provider.name() == "vimeo")
+                    .map(|&(n, v)| (n.into(), v.into()))
+                    .collect();
+                let expected = Some(Media {
+                    id: id.to_owned(),
+                    params: params
+                        .iter()
+                        .map(|(n, v)| (n.to_string(), v.to_string()))
+                        .collect(),
+                });
+                assert_eq!(provider.media(&provider.page(id, &params)), expected);
+                assert_eq!(provider.media(&provider.player(id, &params)), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn urls_are_safe() {
+        let params = vec![
+            ("start".into(), "1&autoplay=1".into()),
+            ("h".into(), "\"><".into()),
+        ];
+        assert_eq!(
+            YouTube.player("a/../\"b", &params),
+            "https://www.youtube-nocookie.com/embed/a%2F..%2F%22b"
+        );
+        assert_eq!(Vimeo.page("1#x", &params), "https://vimeo.com/1%23x");
+    }
+
+    #[test]
+    fn embed_tags() {
+        let embed = |provider: &str, id: &str| Segment::Embed {
+            provider: provider.to_owned().into(),
+            id: id.to_owned().into(),
+            params: vec![],
+        };
+        assert_eq!(
+            parse(
+                "[youtube]dQw4w9WgXcQ[/youtube][YOUTUBE] youtu.be/dQw4w9WgXcQ\n[/youtube]\
+                 [video]https://vimeo.com/76979871[/video][media=Vimeo]76979871[/media]"
+            ),
+            vec![
+                embed("youtube", "dQw4w9WgXcQ"),
+                embed("youtube", "dQw4w9WgXcQ"),
+                embed("vimeo", "76979871"),
+                embed("vimeo", "76979871"),
+            ]
+        );
+        for input in &[
+            "[youtube]https://vimeo.com/76979871[/youtube]",
+            "[media]76979871[/media]",
+            "[media=vimeo]dQw4w9WgXcQ[/media]",
+            "[media=dailymotion]x7tgad0[/media]",
+            "[video]https://example.com/v.mp4[/video]",
+            "[youtube]dQw4w9WgXcQ",
+        ] {
+            assert_eq!(parse(input), vec![Segment::Text((*input).into())]);
+        }
+        assert_eq!(
+            Providers::new()
+                .find(Some("YOUTUBE"), "dQw4w9WgXcQ")
+                .map(|(p, _)| p.name()),
+            Some("youtube")
+        );
+    }
+}
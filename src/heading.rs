@@ -0,0 +1,84 @@
+//! Headings and horizontal rules.
+
+#[cfg(test)]
+use super::DecorationStyle;
+#[cfg(test)]
+use super::Segment::{Decorated, Heading, Rule, Text};
+#[cfg(test)]
+use super::{parse, validate, DiagnosticKind};
+
+/// Names of the closing tags of headings, by level from 1.
+const CLOSERS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+// Recognizes the opening tag of a heading, `[h1]` to `[h6]`, giving its
+// level.
+named!(pub head(&str) -> u8,
+    delimited!(
+        tag_no_case!("[h"),
+        map!(one_of!("123456"), |c| c as u8 - b'0'),
+        char!(']')
+    )
+);
+
+// Recognizes `[hr]`, which has no body or closing tag.
+named!(pub rule(&str) -> &str, tag_no_case!("[hr]"));
+
+/// The name of the closing tag for a heading of `level`, from 1 to 6.
+pub fn closer(level: u8) -> &'static str {
+    CLOSERS[level as usize - 1]
+}
+
+#[test]
+fn just_head() {
+    assert_eq!(head("[h1]"), Ok(("", 1)));
+    assert_eq!(head("[H6]x"), Ok(("x", 6)));
+    assert!(head("[h7]").is_err());
+    assert!(head("[h]").is_err());
+}
+
+#[test]
+fn headings() {
+    assert_eq!(
+        parse("[h2]A [b]b[/b][/h2]c"),
+        vec![
+            Heading {
+                level: 2,
+                text: vec![
+                    Text("A ".into()),
+                    Decorated {
+                        style: DecorationStyle::Bold,
+                        text: vec![Text("b".into())],
+                    },
+                ],
+            },
+            Text("c".into()),
+        ]
+    );
+    assert_eq!(parse("[h1]x[/h2]"), vec![Text("[h1]x[/h2]".into())]);
+}
+
+#[test]
+fn rules() {
+    assert_eq!(
+        parse("a[HR]b[hr][/hr]"),
+        vec![
+            Text("a".into()),
+            Rule,
+            Text("b".into()),
+            Rule,
+            Text("[/hr]".into()),
+        ]
+    );
+}
+
+#[test]
+fn unclosed_headings() {
+    let kinds: Vec<_> = validate("[h1]a[/h2][hr]")
+        .into_iter()
+        .map(|d| d.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![DiagnosticKind::Unclosed, DiagnosticKind::StrayClose]
+    );
+}
@@ -0,0 +1,18 @@
+//! Converting other markup to segments.
+//!
+//! Each importer maps what it can of another kind of markup onto segments,
+//! so the result can be rendered or written back out as bbcode like any
+//! parsed bbcode. Anything without a corresponding segment is reduced to its
+//! text.
+//!
+//! Importers that depend on a parser for their markup are behind a feature:
+//! `markdown` for `markdown`. `html` parses the little HTML it accepts
+//! itself, so it is always available.
+
+mod html;
+#[cfg(feature = "markdown")]
+mod markdown;
+
+pub use self::html::html;
+#[cfg(feature = "markdown")]
+pub use self::markdown::markdown;
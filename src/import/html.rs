@@ -0,0 +1,821 @@
+use super::super::decoration::{is_valid_size, parse_color};
+use super::super::normalize;
+use super::super::{DecorationStyle, ListStyle, OwnedSegment, Segment, DEFAULT_MAX_DEPTH};
+use std::cmp;
+use std::collections::HashMap;
+use std::num::NonZeroU8;
+
+/// Convert a conservative subset of HTML to segments.
+///
+/// `b` and `strong`, `i` and `em`, `u`, `s`, `strike` and `del`, `sub`,
+/// `sup`, `tt`, and `center` become decorations, as do `color`, `font-size`
+/// in pixels or points, a `font-family` including `monospace`, and
+/// `text-align: center` in `style` attributes. `blockquote` becomes a quote, `pre` and `code` become
+/// code, `ul` and `ol` become lists of their `li` items, and `a` and `img`
+/// become links and images when they have an `href` or `src`. `h1` to `h6`
+/// become headings, and `hr` a rule.
+///
+/// Any other element is reduced to its text, except that scripts, styles and
+/// titles are dropped entirely. Whitespace is collapsed as a browser would,
+/// block elements are separated by a line break, paragraphs and headings by
+/// a blank line, and `br` is a line break.
+///
+/// The input need not be well-formed: unclosed elements end with their
+/// parents, and stray end tags are ignored. Elements that would nest segments
+/// deeper than `DEFAULT_MAX_DEPTH` are reduced to their text.
+///
+/// ```
+/// use bbcode::import::html;
+/// use bbcode::parse;
+///
+/// let input = "<p>Some <em>emphasis</em> and <code>code</code>.</p>\n\
+///              <blockquote><a href=\"http://example.com\"><b>Bold</b></a></blockquote>";
+/// assert_eq!(
+///     html(input),
+///     parse(
+///         "Some [i]emphasis[/i] and [code]code[/code].\n\n\
+///          [quote][url=http://example.com][b]Bold[/b][/url][/quote]"
+///     )
+/// );
+/// ```
+pub fn html(input: &str) -> Vec<OwnedSegment> {
+    let mut builder = Builder {
+        frames: vec![Frame::new(String::new(), Kind::Root, vec![])],
+        breaks: 0,
+        space: false,
+        gap_depth: 1,
+        fresh: true,
+        links: 0,
+        depth: 0,
+        open: HashMap::new(),
+        segments: vec![],
+    };
+    let mut rest = input;
+    while let Some(i) = rest.find('<') {
+        builder.text(&decode(&rest[..i]));
+        rest = builder.markup(&rest[i..]);
+    }
+    builder.text(&decode(rest));
+
+    while builder.frames.len() > 1 {
+        builder.end_frame();
+    }
+    let mut segments = builder.segments;
+    normalize::merge_text(&mut segments);
+    segments
+}
+
+/// Builds segments from HTML tags and text.
+struct Builder {
+    /// The elements enclosing the current position, innermost last.
+    frames: Vec<Frame>,
+    /// Line breaks to write before the next content.
+    breaks: usize,
+    /// Whether to write a space before the next content, if no line breaks.
+    space: bool,
+    /// How many frames enclose the pending line breaks or space, which may
+    /// be fewer than enclose the next content.
+    gap_depth: usize,
+    /// Whether nothing has been written in the innermost quote, list item or
+    /// the root, so that whitespace is dropped.
+    fresh: bool,
+    /// The number of links among `frames`.
+    links: usize,
+    /// How deeply the segments of `frames` will be nested.
+    depth: usize,
+    /// The number of `frames` with each name.
+    open: HashMap<String, usize>,
+    /// The segments inside each of `frames` so far, in order, so that
+    /// elements without a segment of their own can end without moving them.
+    segments: Vec<OwnedSegment>,
+}
+
+/// An element that has been started but not ended.
+struct Frame {
+    /// The lowercase name of the element.
+    name: String,
+    kind: Kind,
+    /// Decorations applied to the element, outermost first.
+    styles: Vec<DecorationStyle>,
+    /// Where the segments inside the element begin in `Builder::segments`.
+    start: usize,
+}
+
+enum Kind {
+    Root,
+    Quote,
+    Heading(u8),
+    /// Code, with its text so far.
+    Code(String),
+    List(ListStyle, Vec<Vec<OwnedSegment>>),
+    Item,
+    Link(String),
+    /// Elements without a segment, which keep only what is inside them.
+    Other,
+}
+
+impl Frame {
+    fn new(name: String, kind: Kind, styles: Vec<DecorationStyle>) -> Self {
+        Frame {
+            name,
+            kind,
+            styles,
+            start: 0,
+        }
+    }
+
+    /// How many levels of segments the element nests what is inside it in.
+    fn depth(&self) -> usize {
+        let segment = match self.kind {
+            Kind::Root | Kind::Item | Kind::Other => 0,
+            _ => 1,
+        };
+        self.styles.len() + segment
+    }
+}
+
+impl Kind {
+    /// Whether whitespace at the start and end of the element is dropped.
+    fn is_container(&self) -> bool {
+        matches!(
+            *self,
+            Kind::Quote | Kind::Heading(_) | Kind::List(..) | Kind::Item
+        )
+    }
+}
+
+/// Elements that never have content or an end tag.
+const VOID: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is not markup, and is dropped.
+const RAW: [&str; 3] = ["script", "style", "title"];
+
+/// How many line breaks separate an element from its surroundings.
+fn breaks(name: &str) -> usize {
+    match name {
+        "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => 2,
+        "address" | "article" | "aside" | "blockquote" | "center" | "dd" | "div" | "dl" | "dt"
+        | "fieldset" | "figcaption" | "figure" | "footer" | "form" | "header" | "li" | "main"
+        | "nav" | "ol" | "pre" | "section" | "table" | "tr" | "ul" => 1,
+        _ => 0,
+    }
+}
+
+impl Builder {
+    fn current(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("root is never ended")
+    }
+
+    fn push(&mut self, segment: OwnedSegment) {
+        self.segments.push(segment);
+    }
+
+    fn push_frame(&mut self, mut frame: Frame) {
+        frame.start = self.segments.len();
+        if let Kind::Link(_) = frame.kind {
+            self.links += 1;
+        }
+        self.depth += frame.depth();
+        *self.open.entry(frame.name.clone()).or_insert(0) += 1;
+        self.frames.push(frame);
+    }
+
+    /// Handle the markup at the start of `s`, returning what follows it.
+    fn markup<'s>(&mut self, s: &'s str) -> &'s str {
+        if let Some(comment) = s.strip_prefix("<!--") {
+            return comment.find("-->").map_or("", |i| &comment[i + 3..]);
+        }
+        if s.starts_with("<!") || s.starts_with("<?") {
+            return s.find('>').map_or("", |i| &s[i + 1..]);
+        }
+
+        let (end, tag) = match s.strip_prefix("</") {
+            Some(tag) => (true, tag),
+            None => (false, &s[1..]),
+        };
+        if !tag.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            self.text("<");
+            return &s[1..];
+        }
+        let len = tag
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(tag.len());
+        let name = tag[..len].to_ascii_lowercase();
+        let (attrs, rest) = attributes(&tag[len..]);
+
+        if end {
+            self.end(&name);
+            rest
+        } else if RAW.contains(&&name[..]) {
+            match find_end_tag(rest, &name) {
+                Some(i) => {
+                    let rest = &rest[i..];
+                    rest.find('>').map_or("", |i| &rest[i + 1..])
+                }
+                None => "",
+            }
+        } else {
+            self.start(name, attrs);
+            rest
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Kind::Code(ref mut code) = self.current().kind {
+            code.push_str(text);
+            return;
+        }
+
+        for (i, word) in text.split(is_space).enumerate() {
+            if i > 0 && self.breaks == 0 {
+                self.gap();
+                self.space = true;
+            }
+            if !word.is_empty() {
+                self.content();
+                self.push(Segment::Text(word.to_owned().into()));
+            }
+        }
+    }
+
+    /// Note the frame enclosing a line break or space about to be requested.
+    fn gap(&mut self) {
+        self.gap_depth = if self.breaks > 0 || self.space {
+            cmp::min(self.gap_depth, self.frames.len())
+        } else {
+            self.frames.len()
+        };
+    }
+
+    /// Separate what follows from what came before by at least `breaks` line
+    /// breaks.
+    fn block(&mut self, breaks: usize) {
+        self.gap();
+        self.breaks = cmp::max(self.breaks, breaks);
+        self.space = false;
+    }
+
+    fn line_break(&mut self) {
+        self.gap();
+        self.breaks += 1;
+        self.space = false;
+    }
+
+    /// Prepare to write content, writing any pending line breaks or space.
+    fn content(&mut self) {
+        if let Kind::List(..) = self.current().kind {
+            self.open_item();
+        }
+        if !self.fresh {
+            let gap = if self.breaks > 0 {
+                "\n".repeat(self.breaks)
+            } else if self.space {
+                " ".to_owned()
+            } else {
+                String::new()
+            };
+            if !gap.is_empty() {
+                self.segments.push(Segment::Text(gap.into()));
+                // Elements begun since the gap are still empty, and begin
+                // after it.
+                let len = self.segments.len();
+                for frame in &mut self.frames[self.gap_depth..] {
+                    debug_assert_eq!(frame.start, len - 1);
+                    frame.start = len;
+                }
+            }
+        }
+        self.breaks = 0;
+        self.space = false;
+        self.fresh = false;
+    }
+
+    fn open_item(&mut self) {
+        self.push_frame(Frame::new("li".to_owned(), Kind::Item, vec![]));
+        self.breaks = 0;
+        self.space = false;
+        self.fresh = true;
+    }
+
+    fn start(&mut self, name: String, attrs: Vec<(String, String)>) {
+        if let Kind::Code(ref mut code) = self.current().kind {
+            if name == "br" {
+                code.push('\n');
+            }
+            return;
+        }
+        match &name[..] {
+            "br" => return self.line_break(),
+            "img" => {
+                if let Some(src) = attr(&attrs, "src") {
+                    self.content();
+                    self.push(Segment::Image {
+                        src: src.to_owned().into(),
+                    });
+                }
+                return;
+            }
+            "hr" => {
+                self.block(1);
+                self.content();
+                self.push(Segment::Rule);
+                return self.block(1);
+            }
+            _ => {}
+        }
+
+        let in_list = match self.current().kind {
+            Kind::Item if name == "li" => {
+                self.end_frame();
+                true
+            }
+            Kind::List(..) if name == "li" => true,
+            Kind::List(..) => {
+                self.open_item();
+                false
+            }
+            _ => false,
+        };
+        let breaks = breaks(&name);
+        if breaks > 0 {
+            self.block(breaks);
+        }
+        if VOID.contains(&&name[..]) {
+            return;
+        }
+
+        let mut styles = match &name[..] {
+            "b" | "strong" => vec![DecorationStyle::Bold],
+            "i" | "em" => vec![DecorationStyle::Italic],
+            "u" => vec![DecorationStyle::Underline],
+            "s" | "strike" | "del" => vec![DecorationStyle::Strikethrough],
+            "sub" => vec![DecorationStyle::Subscript],
+            "sup" => vec![DecorationStyle::Superscript],
+            "tt" => vec![DecorationStyle::Monospace],
+            "center" => vec![DecorationStyle::Center],
+            _ => vec![],
+        };
+        if attr(&attrs, "align").is_some_and(|a| a.eq_ignore_ascii_case("center")) {
+            styles.push(DecorationStyle::Center);
+        }
+        if let Some(style) = attr(&attrs, "style") {
+            styles.extend(style.split(';').filter_map(declaration));
+        }
+
+        let in_link = self.links > 0;
+        let kind = match &name[..] {
+            "blockquote" => Kind::Quote,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Kind::Heading(name.as_bytes()[1] - b'0'),
+            "pre" | "code" => Kind::Code(String::new()),
+            "ul" => Kind::List(ListStyle::Unordered, vec![]),
+            "ol" => match attr(&attrs, "type") {
+                Some("a") | Some("A") => Kind::List(ListStyle::Alphabetic, vec![]),
+                _ => Kind::List(ListStyle::Numeric, vec![]),
+            },
+            "li" if in_list => Kind::Item,
+            "a" if !in_link => match attr(&attrs, "href") {
+                Some(href) => Kind::Link(href.to_owned()),
+                None => Kind::Other,
+            },
+            _ => Kind::Other,
+        };
+        let mut frame = Frame::new(name, kind, styles);
+        if self.depth + frame.depth() > DEFAULT_MAX_DEPTH {
+            frame.kind = Kind::Other;
+            frame.styles.clear();
+        }
+
+        match frame.kind {
+            Kind::Quote | Kind::Heading(_) | Kind::Code(_) | Kind::List(..) => self.content(),
+            _ => {}
+        }
+        let container = frame.kind.is_container();
+        self.push_frame(frame);
+        if container {
+            self.breaks = 0;
+            self.space = false;
+            self.fresh = true;
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        if let Kind::Code(_) = self.current().kind {
+            if self.current().name != name {
+                return;
+            }
+        }
+        if self.open.get(name).is_none_or(|&n| n == 0) {
+            return;
+        }
+        if let Some(i) = self.frames.iter().rposition(|frame| frame.name == name) {
+            while self.frames.len() > i {
+                self.end_frame();
+            }
+        }
+    }
+
+    fn end_frame(&mut self) {
+        let frame = self.frames.pop().expect("root is never ended");
+        if let Kind::Link(_) = frame.kind {
+            self.links -= 1;
+        }
+        self.depth -= frame.depth();
+        if let Some(n) = self.open.get_mut(&frame.name) {
+            *n -= 1;
+        }
+        self.gap_depth = cmp::min(self.gap_depth, self.frames.len());
+        let container = frame.kind.is_container();
+        if container {
+            self.breaks = 0;
+            self.space = false;
+        }
+
+        // What is inside an element without a segment or decorations stays
+        // where it is.
+        let transparent = matches!(frame.kind, Kind::Other) && frame.styles.is_empty();
+        if !transparent {
+            let inner: Vec<_> = self.segments.drain(frame.start..).collect();
+            let item = matches!(frame.kind, Kind::Item);
+            let segments = match frame.kind {
+                Kind::Root => unreachable!("root is never ended"),
+                Kind::Other | Kind::Item => inner,
+                Kind::Quote => vec![Segment::Quote {
+                    attribution: None,
+                    body: inner,
+                }],
+                Kind::Heading(level) => vec![Segment::Heading { level, text: inner }],
+                Kind::Code(mut code) => {
+                    // Like browsers, ignore a line break at the start of a `pre`.
+                    if frame.name == "pre" {
+                        if code.ends_with('\n') {
+                            code.pop();
+                        }
+                        if code.starts_with('\n') {
+                            code.remove(0);
+                        }
+                    }
+                    vec![Segment::Code(code.into())]
+                }
+                Kind::List(style, items) => vec![Segment::List { style, items }],
+                Kind::Link(target) => vec![Segment::Link {
+                    target: target.into(),
+                    text: inner,
+                }],
+            };
+            let segments = decorate(frame.styles, segments);
+
+            if item {
+                if let Kind::List(_, ref mut items) = self.current().kind {
+                    items.push(segments);
+                }
+                self.fresh = true;
+                return;
+            }
+            self.segments.extend(segments);
+        }
+        if container {
+            self.fresh = false;
+        }
+        let breaks = breaks(&frame.name);
+        if breaks > 0 {
+            self.block(breaks);
+        }
+    }
+}
+
+/// Wrap segments in decorations, the first outermost.
+fn decorate(styles: Vec<DecorationStyle>, mut segments: Vec<OwnedSegment>) -> Vec<OwnedSegment> {
+    if segments.is_empty() {
+        return segments;
+    }
+    for style in styles.into_iter().rev() {
+        segments = vec![Segment::Decorated {
+            style,
+            text: segments,
+        }];
+    }
+    segments
+}
+
+/// Whitespace as HTML sees it, which excludes non-breaking spaces.
+fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C')
+}
+
+/// The position of the first end tag named `name` in `s`, ignoring ASCII
+/// case.
+fn find_end_tag(s: &str, name: &str) -> Option<usize> {
+    s.match_indices("</").map(|(i, _)| i).find(|&i| {
+        s.as_bytes()
+            .get(i + 2..i + 2 + name.len())
+            .is_some_and(|n| n.eq_ignore_ascii_case(name.as_bytes()))
+    })
+}
+
+/// Parse the attributes of a tag, returning them with what follows the tag.
+///
+/// Names are made lowercase, and values have character references decoded.
+fn attributes(mut s: &str) -> (Vec<(String, String)>, &str) {
+    let mut attrs = vec![];
+    loop {
+        s = s.trim_start_matches(|c| is_space(c) || c == '/');
+        let c = match s.chars().next() {
+            None => return (attrs, s),
+            Some('>') => return (attrs, &s[1..]),
+            Some(c) => c,
+        };
+        let len = s
+            .find(|c| is_space(c) || c == '=' || c == '>' || c == '/')
+            .filter(|&len| len > 0)
+            .unwrap_or_else(|| c.len_utf8());
+        let name = s[..len].to_ascii_lowercase();
+        s = s[len..].trim_start_matches(is_space);
+
+        let mut value = String::new();
+        if let Some(rest) = s.strip_prefix('=') {
+            let rest = rest.trim_start_matches(is_space);
+            let (raw, rest) = match rest.chars().next() {
+                Some(quote @ '"') | Some(quote @ '\'') => {
+                    let rest = &rest[1..];
+                    match rest.find(quote) {
+                        Some(i) => (&rest[..i], &rest[i + 1..]),
+                        None => (rest, ""),
+                    }
+                }
+                _ => {
+                    let i = rest.find(|c| is_space(c) || c == '>').unwrap_or(rest.len());
+                    (&rest[..i], &rest[i..])
+                }
+            };
+            value = decode(raw);
+            s = rest;
+        }
+        attrs.push((name, value));
+    }
+}
+
+/// The value of the first attribute with a name, if there is one.
+fn attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| &value[..])
+}
+
+/// The decoration for a CSS declaration like `color: red`, if it has one.
+fn declaration(s: &str) -> Option<DecorationStyle> {
+    let (property, value) = s.split_once(':')?;
+    let value = value.trim().to_ascii_lowercase();
+    let value = value
+        .strip_suffix("!important")
+        .unwrap_or(&value)
+        .trim_end();
+    match &property.trim().to_ascii_lowercase()[..] {
+        "color" => css_color(value).map(|(r, g, b)| DecorationStyle::Color(r, g, b)),
+        "font-size" => font_size(value).map(DecorationStyle::Size),
+        "text-align" if value == "center" => Some(DecorationStyle::Center),
+        "font-family" if value.split(',').any(|family| family.trim() == "monospace") => {
+            Some(DecorationStyle::Monospace)
+        }
+        _ => None,
+    }
+}
+
+/// Parse a lowercase CSS color: `rgb()`, or anything `[color=...]` accepts.
+fn css_color(value: &str) -> Option<(u8, u8, u8)> {
+    let args = value
+        .strip_prefix("rgb(")
+        .or_else(|| value.strip_prefix("rgba("))
+        .and_then(|args| args.strip_suffix(')'));
+    let args = match args {
+        Some(args) => args,
+        None => return parse_color(value),
+    };
+
+    let mut components = args
+        .split(|c: char| c == ',' || c == '/' || is_space(c))
+        .filter(|c| !c.is_empty())
+        .map(|c| {
+            let (number, scale) = match c.strip_suffix('%') {
+                Some(percentage) => (percentage, 2.55),
+                None => (c, 1.0),
+            };
+            number
+                .parse::<f32>()
+                .ok()
+                .map(|n| (n * scale).round() as u8)
+        });
+    Some((
+        components.next()??,
+        components.next()??,
+        components.next()??,
+    ))
+}
+
+/// Parse a lowercase CSS font size in pixels or points, if `[size=...]`
+/// would accept it.
+fn font_size(value: &str) -> Option<NonZeroU8> {
+    let pixels = if let Some(pixels) = value.strip_suffix("px") {
+        pixels.trim().parse::<f32>().ok()?
+    } else if let Some(points) = value.strip_suffix("pt") {
+        points.trim().parse::<f32>().ok()? * 4.0 / 3.0
+    } else {
+        return None;
+    };
+    let size = pixels.round() as u8;
+    if is_valid_size(size) {
+        NonZeroU8::new(size)
+    } else {
+        None
+    }
+}
+
+/// Named character references that are decoded; others are left as text.
+const ENTITIES: [(&str, char); 22] = [
+    ("amp", '&'),
+    ("apos", '\''),
+    ("bull", '\u{2022}'),
+    ("copy", '\u{A9}'),
+    ("deg", '\u{B0}'),
+    ("euro", '\u{20AC}'),
+    ("gt", '>'),
+    ("hellip", '\u{2026}'),
+    ("laquo", '\u{AB}'),
+    ("ldquo", '\u{201C}'),
+    ("lsquo", '\u{2018}'),
+    ("lt", '<'),
+    ("mdash", '\u{2014}'),
+    ("middot", '\u{B7}'),
+    ("nbsp", '\u{A0}'),
+    ("ndash", '\u{2013}'),
+    ("quot", '"'),
+    ("raquo", '\u{BB}'),
+    ("rdquo", '\u{201D}'),
+    ("reg", '\u{AE}'),
+    ("rsquo", '\u{2019}'),
+    ("trade", '\u{2122}'),
+];
+
+/// Decode the character references in text, like `&amp;` or `&#x263A;`.
+fn decode(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        match reference(rest) {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => decoded.push('&'),
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The character referred to at the start of `s`, which follows an `&`, and
+/// the length of the reference.
+fn reference(s: &str) -> Option<(char, usize)> {
+    let end = s.find(';').filter(|&end| end <= 32)?;
+    let name = &s[..end];
+    let c = match name.strip_prefix('#') {
+        Some(number) => {
+            let (digits, radix) = match number.strip_prefix(|c| c == 'x' || c == 'X') {
+                Some(digits) => (digits, 16),
+                None => (number, 10),
+            };
+            if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+                return None;
+            }
+            u32::from_str_radix(digits, radix)
+                .ok()
+                .and_then(::std::char::from_u32)
+                .filter(|&c| c != '\0')
+                .unwrap_or('\u{FFFD}')
+        }
+        None => ENTITIES.iter().find(|&&(entity, _)| entity == name)?.1,
+    };
+    Some((c, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{parse, DecorationStyle, Segment, DEFAULT_MAX_DEPTH};
+    use super::html;
+
+    #[test]
+    fn inline() {
+        assert_eq!(
+            html("<b>a</b> <strong>b</strong> <i>c</i> <em>d</em> <u>e</u> <code>f  g</code>"),
+            parse("[b]a[/b] [b]b[/b] [i]c[/i] [i]d[/i] [u]e[/u] [code]f  g[/code]")
+        );
+        assert_eq!(
+            html("<a href='h'>i</a> <img src=j alt=\"k\"> <a name=l>m</a> <img>"),
+            parse("[url=h]i[/url] [img]j[/img] m")
+        );
+        assert_eq!(
+            html("<blink>a <b>b </b></blink> c&amp;d &lt;&#x263A;&#9731;&bogus; &"),
+            parse("a [b]b[/b] c&d <\u{263A}\u{2603}&bogus; &")
+        );
+    }
+
+    #[test]
+    fn styles() {
+        assert_eq!(
+            html(
+                "<span style=\"color: rgb(255, 128, 0); font-size: 12px\">a</span>\
+                 <span style='COLOR:Teal !important;font-size:9pt'>b</span>\
+                 <span style=\"color: #f00; font-size: 1000px; margin: 0\">c</span>\
+                 <span style=\"color: bogus\">d</span>"
+            ),
+            parse(
+                "[color=#ff8000][size=12]a[/size][/color][color=teal][size=12]b[/size][/color]\
+                 [color=red]c[/color]d"
+            )
+        );
+        assert_eq!(
+            html(
+                "<s>a</s><strike>b</strike><del>c</del> x<sub>1</sub><sup>2</sup> <tt>d</tt>\
+                 <span style=\"font-family: Menlo, monospace\">e</span>\
+                 <span style=\"font-family: serif\">f</span>"
+            ),
+            parse("[s]a[/s][s]b[/s][s]c[/s] x[sub]1[/sub][sup]2[/sup] [tt]d[/tt][tt]e[/tt]f")
+        );
+        assert_eq!(
+            html("<center>a</center><div style=\"text-align: center\">b</div><p align=center>c"),
+            parse("[center]a[/center]\n[center]b[/center]\n\n[center]c[/center]")
+        );
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(
+            html(
+                "\n  <h1>A</h1>\n  <p>b\n   c<br>d</p><div>e</div>f\
+                 <blockquote> <p>g</p> <p>h</p> </blockquote>\
+                 <pre>\ni <b>j</b>\n  k\n</pre>l"
+            ),
+            parse("[h1]A[/h1]\n\nb c\nd\n\ne\nf\n[quote]g\n\nh[/quote]\n[code]i j\n  k[/code]\nl")
+        );
+        assert_eq!(
+            html("<h3> a <i>b</i> </h3>c<hr>d<HR/><p>e</p><hr>"),
+            parse("[h3]a [i]b[/i][/h3]\n\nc\n[hr]\nd\n[hr]\n\ne\n\n[hr]")
+        );
+        assert_eq!(
+            html("<script>a<b>b</b></script><style>c</style>d<!-- <e> -->f<!DOCTYPE html>"),
+            parse("df")
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            html(
+                "<ul>\n  <li>a\n  <li>b<ol type=a><li>c</li> <li>d</li></ol></li>\n</ul>\
+                 <ol><li>e</ol>"
+            ),
+            parse("[list][*]a[*]b\n[list=a][*]c[*]d[/list][/list]\n[list=1][*]e[/list]")
+        );
+        assert_eq!(
+            html("<ul>a <b>b</b><li>c</li></ul>"),
+            parse("[list][*]a [b]b[/b][*]c[/list]")
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(html("<b>a<i>b</b>c</i>d"), parse("[b]a[i]b[/i][/b]cd"));
+        assert_eq!(html("a</p>b<p"), parse("ab"));
+        assert_eq!(html("a < b <3"), parse("a < b <3"));
+        assert_eq!(html("<b><u></u></b>"), vec![] as Vec<Segment>);
+        assert_eq!(
+            html("<span style=color:red>x"),
+            vec![Segment::Decorated {
+                style: DecorationStyle::Color(255, 0, 0),
+                text: vec![Segment::Text("x".into())],
+            }]
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let n = 100_000;
+        let segments = html(&("<b>".repeat(n) + "x"));
+        let mut depth = 0;
+        let mut level = &segments;
+        while let [Segment::Decorated { ref text, .. }] = level[..] {
+            depth += 1;
+            level = text;
+        }
+        assert_eq!(depth, DEFAULT_MAX_DEPTH);
+        assert_eq!(level[..], [Segment::Text("x".into())]);
+
+        let segments = html(&"<ul><li><i>a</i>".repeat(n));
+        assert!(matches!(segments[..], [Segment::List { .. }]));
+    }
+}
@@ -0,0 +1,275 @@
+use super::super::normalize;
+use super::super::{DecorationStyle, ListStyle, Segment};
+use pulldown_cmark::{Event, LinkType, Parser, Tag, TagEnd};
+use std::borrow::Cow;
+
+/// Convert CommonMark to segments.
+///
+/// Emphasis and strong emphasis become italic and bold decorations, block
+/// quotes become quotes, links and images keep their destinations, and
+/// lists are unordered or numbered like the originals. Code blocks become
+/// code, and code spans monospaced text. Headings keep their levels, and
+/// thematic breaks become rules.
+///
+/// Blocks are separated by a line break, and paragraphs by a blank line, as
+/// they would be written in bbcode. Line breaks within paragraphs are kept,
+/// and raw HTML is kept as text.
+///
+/// ```
+/// use bbcode::import::markdown;
+/// use bbcode::parse;
+///
+/// let input = "Some *emphasis* and `code`.\n\n> [**Bold**](http://example.com)\n";
+/// assert_eq!(
+///     markdown(input),
+///     parse(
+///         "Some [i]emphasis[/i] and [mono]code[/mono].\n\
+///          [quote][url=http://example.com][b]Bold[/b][/url][/quote]"
+///     )
+/// );
+/// ```
+pub fn markdown(input: &str) -> Vec<Segment<'_>> {
+    let mut builder = Builder {
+        frames: vec![Frame::new(Kind::Root)],
+    };
+    for event in Parser::new(input) {
+        builder.event(event);
+    }
+
+    let mut segments = builder.frames.pop().expect("root is never ended").segments;
+    normalize::merge_text(&mut segments);
+    segments
+}
+
+/// Builds segments from the events of a Markdown parser.
+struct Builder<'a> {
+    /// The elements enclosing the current event, innermost last.
+    frames: Vec<Frame<'a>>,
+}
+
+/// An element that has been started but not ended.
+struct Frame<'a> {
+    kind: Kind<'a>,
+    /// The segments inside the element so far.
+    segments: Vec<Segment<'a>>,
+    /// Whether the last block inside the element was a paragraph, if there
+    /// was one.
+    last_block: Option<bool>,
+}
+
+enum Kind<'a> {
+    Root,
+    Paragraph,
+    Heading(u8),
+    Quote,
+    Code,
+    List(ListStyle, Vec<Vec<Segment<'a>>>),
+    Item,
+    Decorated(DecorationStyle),
+    Link(Cow<'a, str>),
+    Image(Cow<'a, str>),
+    /// Elements without a segment, which keep only what is inside them.
+    Other,
+}
+
+impl<'a> Frame<'a> {
+    fn new(kind: Kind<'a>) -> Self {
+        Frame {
+            kind,
+            segments: vec![],
+            last_block: None,
+        }
+    }
+}
+
+impl<'a> Kind<'a> {
+    /// Whether the element is a block, and if so whether it is a paragraph.
+    fn block(&self) -> Option<bool> {
+        match *self {
+            Kind::Paragraph | Kind::Heading(_) => Some(true),
+            Kind::Quote | Kind::Code | Kind::List(..) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Builder<'a> {
+    fn current(&mut self) -> &mut Frame<'a> {
+        self.frames.last_mut().expect("root is never ended")
+    }
+
+    fn push(&mut self, segment: Segment<'a>) {
+        self.current().segments.push(segment);
+    }
+
+    fn event(&mut self, event: Event<'a>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(s) | Event::Html(s) | Event::InlineHtml(s) => {
+                self.push(Segment::Text(s.into()))
+            }
+            Event::Code(s) => self.push(Segment::Decorated {
+                style: DecorationStyle::Monospace,
+                text: vec![Segment::Text(s.into())],
+            }),
+            Event::SoftBreak | Event::HardBreak => self.push(Segment::Text("\n".into())),
+            Event::Rule => {
+                self.separate(false);
+                self.push(Segment::Rule);
+                self.current().last_block = Some(false);
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'a>) {
+        let kind = match tag {
+            Tag::Paragraph => Kind::Paragraph,
+            Tag::Heading { level, .. } => Kind::Heading(level as u8),
+            Tag::BlockQuote(_) => Kind::Quote,
+            Tag::CodeBlock(_) => Kind::Code,
+            Tag::List(None) => Kind::List(ListStyle::Unordered, vec![]),
+            Tag::List(Some(_)) => Kind::List(ListStyle::Numeric, vec![]),
+            Tag::Item => Kind::Item,
+            Tag::Emphasis => Kind::Decorated(DecorationStyle::Italic),
+            Tag::Strong => Kind::Decorated(DecorationStyle::Bold),
+            Tag::Link {
+                link_type: LinkType::Email,
+                dest_url,
+                ..
+            } => Kind::Link(format!("mailto:{}", dest_url).into()),
+            Tag::Link { dest_url, .. } => Kind::Link(dest_url.into()),
+            Tag::Image { dest_url, .. } => Kind::Image(dest_url.into()),
+            _ => Kind::Other,
+        };
+
+        if let Some(paragraph) = kind.block() {
+            self.separate(paragraph);
+        }
+        self.frames.push(Frame::new(kind));
+    }
+
+    /// Separate a new block from the one before it, if there is one.
+    fn separate(&mut self, paragraph: bool) {
+        let separator = match self.current().last_block {
+            Some(true) if paragraph => "\n\n",
+            Some(_) => "\n",
+            None => "",
+        };
+        if !separator.is_empty() {
+            self.push(Segment::Text(separator.into()));
+        }
+    }
+
+    fn end(&mut self, _tag: TagEnd) {
+        let frame = self.frames.pop().expect("ended more elements than started");
+        let block = frame.kind.block();
+        let mut segments = frame.segments;
+        let segment = match frame.kind {
+            Kind::Root => unreachable!("root is never ended"),
+            Kind::Paragraph | Kind::Other => {
+                self.current().segments.append(&mut segments);
+                None
+            }
+            Kind::Heading(level) => Some(Segment::Heading {
+                level,
+                text: segments,
+            }),
+            Kind::Quote => Some(Segment::Quote {
+                attribution: None,
+                body: segments,
+            }),
+            Kind::Code => {
+                normalize::merge_text(&mut segments);
+                let code = match segments.pop() {
+                    Some(Segment::Text(s)) => s,
+                    _ => "".into(),
+                };
+                Some(Segment::Code(match code {
+                    Cow::Borrowed(s) => s.strip_suffix('\n').unwrap_or(s).into(),
+                    Cow::Owned(mut s) => {
+                        if s.ends_with('\n') {
+                            s.pop();
+                        }
+                        s.into()
+                    }
+                }))
+            }
+            Kind::List(style, items) => Some(Segment::List { style, items }),
+            Kind::Item => {
+                if let Kind::List(_, ref mut items) = self.current().kind {
+                    items.push(segments);
+                }
+                None
+            }
+            Kind::Decorated(style) => Some(Segment::Decorated {
+                style,
+                text: segments,
+            }),
+            Kind::Link(target) => Some(Segment::Link {
+                target,
+                text: segments,
+            }),
+            // The text inside an image is its description, which images
+            // don't have.
+            Kind::Image(src) => Some(Segment::Image { src }),
+        };
+
+        if let Some(segment) = segment {
+            self.push(segment);
+        }
+        if block.is_some() {
+            self.current().last_block = block;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{parse, Segment};
+    use super::markdown;
+
+    #[test]
+    fn inline() {
+        assert_eq!(
+            markdown("*a* _b_ **c** __d__ ***e*** `f` [g](h) ![i](j) <k@l.m>"),
+            parse(
+                "[i]a[/i] [i]b[/i] [b]c[/b] [b]d[/b] [i][b]e[/b][/i] [mono]f[/mono] \
+                 [url=h]g[/url] [img]j[/img] [url=mailto:k@l.m]k@l.m[/url]"
+            )
+        );
+        assert_eq!(
+            markdown("a\nb  \nc <span>d</span> \\*"),
+            vec![Segment::Text("a\nb\nc <span>d</span> *".into())]
+        );
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(
+            markdown("# A\n\nb\n\n> c\n>\n> d\n\n```rust\ne\n\nf\n```\n\n    g\n\n---\nh"),
+            parse(
+                "[h1]A[/h1]\n\nb\n[quote]c\n\nd[/quote]\n[code]e\n\nf[/code]\n[code]g[/code]\n\
+                 [hr]\nh"
+            )
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            markdown("- a\n- b\n  1. c\n  2. d\n\n3) e\n\n   f\n"),
+            parse("[list][*]a[*]b[list=1][*]c[*]d[/list][/list]\n[list=1][*]e\n\nf[/list]")
+        );
+    }
+
+    #[test]
+    fn borrows_input() {
+        let segments = markdown("a *b*");
+        match segments[0] {
+            Segment::Text(::std::borrow::Cow::Borrowed("a ")) => {}
+            ref segment => panic!("{:?} is not borrowed", segment),
+        }
+    }
+}
@@ -17,38 +17,98 @@
 //! let ast = parse(MESSAGE);
 //! assert_eq!(ast, vec![
 //!     Segment::Quote {
-//!         attribution: Some("Batman"),
-//!         body: vec![Segment::Text("I'm batman")],
+//!         attribution: Some("Batman".into()),
+//!         body: vec![Segment::Text("I'm batman".into())],
 //!     },
-//!     Segment::Text("\nIsn't he "),
+//!     Segment::Text("\nIsn't he ".into()),
 //!     Segment::Decorated {
 //!         style: DecorationStyle::Italic,
-//!         text: vec![Segment::Text("dreamy")],
+//!         text: vec![Segment::Text("dreamy".into())],
 //!     },
-//!     Segment::Text("?"),
+//!     Segment::Text("?".into()),
 //! ]);
 //! ```
+//!
+//! Parsing takes time linear in the length of the input, however malformed
+//! the markup: tags that are never closed are simply treated as text.
+//!
+//! # Serialization
+//!
+//! With the `serde` feature, `Segment`, `DecorationStyle`, `ListStyle` and
+//! `TableCell` implement `Serialize` and `Deserialize`. Each segment is a map
+//! with a `type` naming its variant in `snake_case`, and its fields by their
+//! names here, except that text and code have their contents as `text` and
+//! `code`; table cells are maps of their fields:
+//!
+//! ```json
+//! [
+//!   {"type": "text", "text": "Hi "},
+//!   {"type": "quote", "attribution": "Batman", "body": [
+//!     {"type": "decorated", "style": "bold", "text": [
+//!       {"type": "text", "text": "I'm batman"}
+//!     ]}
+//!   ]},
+//!   {"type": "decorated", "style": {"color": "#ff8000"}, "text": []},
+//!   {"type": "decorated", "style": {"size": 12}, "text": []},
+//!   {"type": "list", "style": "numeric", "items": [[{"type": "code", "code": "x"}]]},
+//!   {"type": "heading", "level": 2, "text": [{"type": "text", "text": "Intro"}]},
+//!   {"type": "rule"},
+//!   {"type": "custom", "name": "reveal", "attrs": [["", "Act 2"]], "body": []},
+//!   {"type": "embed", "provider": "youtube", "id": "dQw4w9WgXcQ", "params": [["start", "42"]]},
+//!   {"type": "spoiler", "title": null, "body": [{"type": "text", "text": "Snape"}]},
+//!   {"type": "table", "rows": [[{"header": true, "body": []}]]}
+//! ]
+//! ```
+//!
+//! Styles without values are strings; colors are written as `#rrggbb` and
+//! sizes as numbers. List styles are `unordered`, `numeric` or `alphabetic`.
+//! Deserialized segments own all of their strings.
 #[macro_use]
 extern crate log;
 #[macro_use]
 extern crate nom;
 extern crate palette;
+#[cfg(feature = "markdown")]
+extern crate pulldown_cmark;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 
-use nom::{types::CompleteStr, AtEof};
-use std::fmt::Debug;
+use std::borrow::Cow;
 use std::os::raw::c_char;
 
-#[macro_use]
-mod macros;
+mod canonical;
 mod code;
+mod custom;
 mod decoration;
+mod embed;
+mod heading;
+pub mod import;
 mod list;
+pub mod normalize;
+mod parser;
 mod quote;
 pub mod render;
+#[cfg(feature = "serde")]
+mod serialize;
+mod span;
+mod spoiler;
+mod table;
+mod toc;
 mod url;
+mod validate;
+pub mod visit;
 
+pub use canonical::{canonicalize, Canonicalizer};
+pub use custom::{Body, TagHandler};
 pub use decoration::DecorationStyle;
+pub use embed::{EmbedProvider, Media, Providers, Vimeo, YouTube};
 pub use list::ListStyle;
+pub use span::{Position, Span, Spans};
+pub use table::TableCell;
+pub use toc::{toc, toc_list, Anchors, TocEntry};
+pub use url::{RejectedUrl, UrlPolicy};
+pub use validate::{Diagnostic, DiagnosticKind};
 
 /// FFI entry point; converts a UTF-8 string of bbcode to rendered code.
 ///
@@ -56,14 +116,16 @@ pub use list::ListStyle;
 /// recovered by insertion of U+FFFD REPLACEMENT CHARACTER.
 ///
 /// The returned pointer must be freed by calling `bbcode_dispose`.
+///
+/// # Safety
+///
+/// `s` must point to a valid null-terminated string.
 #[no_mangle]
-pub extern "C" fn bbcode_translate(s: *const c_char) -> *mut c_char {
-    use std::ffi::{CStr, CString};
+pub unsafe extern "C" fn bbcode_translate(s: *const c_char) -> *mut c_char {
     use render::Renderer;
+    use std::ffi::{CStr, CString};
 
-    let utf8 = unsafe {
-        CStr::from_ptr(s).to_string_lossy()
-    };
+    let utf8 = CStr::from_ptr(s).to_string_lossy();
     let segments = parse(&utf8);
 
     // Render into a memory buffer; we're likely to emit about as many bytes
@@ -71,29 +133,35 @@ pub extern "C" fn bbcode_translate(s: *const c_char) -> *mut c_char {
     let mut buf = Vec::<u8>::with_capacity(utf8.len());
     {
         let mut renderer = render::SimpleHtml::new(&mut buf);
-        renderer.render(&segments)
+        renderer
+            .render(&segments)
             .expect("Rendering to a memory buffer should never fail");
     }
-    return CString::new(buf)
+    CString::new(buf)
         .expect("Rendering should not generate null bytes")
-        .into_raw();
+        .into_raw()
 }
 
 /// Free a string returned from `bbcode_translate`.
+///
+/// # Safety
+///
+/// `s` must have been returned by `bbcode_translate` and not already freed.
 #[no_mangle]
-pub extern "C" fn bbcode_dispose(s: *mut c_char) {
+pub unsafe extern "C" fn bbcode_dispose(s: *mut c_char) {
     use std::ffi::CString;
 
-    let _ = unsafe {
-        CString::from_raw(s)
-    };
+    let _ = CString::from_raw(s);
 }
 
 /// Any logical segment of data- a tag or plain text.
+///
+/// Parsing borrows strings from the input; segments built from computed
+/// strings own them instead, as do those converted by `into_owned`.
 #[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub enum Segment<'a> {
     /// Unadorned text.
-    Text(&'a str),
+    Text(Cow<'a, str>),
     /// A span of text with simple decoration.
     Decorated {
         style: DecorationStyle,
@@ -101,11 +169,11 @@ pub enum Segment<'a> {
     },
     /// A blockquote with a body and optional attribution.
     Quote {
-        attribution: Option<&'a str>,
+        attribution: Option<Cow<'a, str>>,
         body: Vec<Segment<'a>>,
     },
     /// A block of code, displayed verbatim.
-    Code(&'a str),
+    Code(Cow<'a, str>),
     /// A list of items with a specified style.
     List {
         style: ListStyle,
@@ -114,161 +182,146 @@ pub enum Segment<'a> {
     /// A hyperlink.
     Link {
         // The target of the hyperlink (`href` attribute for HTML `a`).
-        target: &'a str,
+        target: Cow<'a, str>,
         text: Vec<Segment<'a>>,
     },
     /// A picture, displayed inline.
-    Image { src: &'a str }, // TODO extra items
-                            // [youtube]
-                            // [hr]
-                            // [h1] - [h6]
-                            // [sub]
-                            // [sup]
-                            // [strike]
-                            // [mono]
+    Image { src: Cow<'a, str> },
+    /// A tag recognized by a `TagHandler`.
+    Custom {
+        /// The name of the tag, as written in the input.
+        name: Cow<'a, str>,
+        /// Attributes of the opening tag, as name-value pairs; see `TagHandler`.
+        attrs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+        body: Vec<Segment<'a>>,
+    },
+    /// A heading, from level 1 (`[h1]`) down to level 6 (`[h6]`).
+    Heading { level: u8, text: Vec<Segment<'a>> },
+    /// A horizontal rule, `[hr]`.
+    Rule,
+    /// Media embedded from another site, like `[youtube]`; see `EmbedProvider`.
+    Embed {
+        /// The name of the provider, as given by `EmbedProvider::name`.
+        provider: Cow<'a, str>,
+        /// The id of the media on the provider's site.
+        id: Cow<'a, str>,
+        /// Further parameters of the media, as name-value pairs.
+        params: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    },
+    /// Content hidden until the reader chooses to see it, with an optional
+    /// title: `[spoiler]`, `[spoiler=Title]` or `[hide]`.
+    Spoiler {
+        title: Option<Cow<'a, str>>,
+        body: Vec<Segment<'a>>,
+    },
+    /// A table, as a list of rows of cells.
+    Table { rows: Vec<Vec<TableCell<'a>>> },
 }
 
-/// Parse a string into a sequence of `Segment`s.
-pub fn parse(s: &str) -> Vec<Segment> {
-    _parse(CompleteStr(s))
-}
+/// A segment that owns all of its strings, so it can outlive the input.
+pub type OwnedSegment = Segment<'static>;
 
-fn _parse(mut input: CompleteStr) -> Vec<Segment> {
-    let mut out = Vec::new();
-    loop {
-        if input.at_eof() && input.is_empty() {
-            break;
+impl<'a> Segment<'a> {
+    /// Copy any borrowed strings, so the segment no longer borrows the input.
+    ///
+    /// ```
+    /// use bbcode::{parse, OwnedSegment};
+    ///
+    /// let segments: Vec<OwnedSegment> = {
+    ///     let input = String::from("[b]Hi[/b]");
+    ///     parse(&input).into_iter().map(|s| s.into_owned()).collect()
+    /// };
+    /// assert_eq!(segments, parse("[b]Hi[/b]"));
+    /// ```
+    pub fn into_owned(self) -> OwnedSegment {
+        fn owned(s: Cow<str>) -> Cow<'static, str> {
+            Cow::Owned(s.into_owned())
         }
-
-        match segment(&input, ()) {
-            Ok((tail, s)) => {
-                out.push(s);
-                input = CompleteStr(tail);
-            }
-            e => panic!("segment() should not fail but did: {:?}", e),
+        fn all(segments: Vec<Segment>) -> Vec<OwnedSegment> {
+            segments.into_iter().map(Segment::into_owned).collect()
         }
-    }
-    out
-}
-
-/// Strings that can mark the end of a segment.
-///
-/// This trait is used by `text_segment` (and thus also `segment`) to determine
-/// where a given freeform (coded or not) segment ends when inside another coded
-/// segment.
-///
-/// When not required, pass `()`; the impl for unit never matches.
-trait Terminal {
-    /// Return true if `s` begins with a substring that matches `self`.
-    fn leads(&self, s: &str) -> bool;
-}
 
-/// Never matches the input; appropriate for unbounded segments.
-impl Terminal for () {
-    fn leads(&self, _s: &str) -> bool {
-        false
-    }
-}
-
-/// Simple leading substring match, case-insensitive ASCII-only.
-impl Terminal for &str {
-    fn leads(&self, s: &str) -> bool {
-        if s.len() < self.len() {
-            // Slice on RangeTo fails if end index exceeds len,
-            // so short-circuit this case.
-            return false;
+        match self {
+            Segment::Text(s) => Segment::Text(owned(s)),
+            Segment::Decorated { style, text } => Segment::Decorated {
+                style,
+                text: all(text),
+            },
+            Segment::Quote { attribution, body } => Segment::Quote {
+                attribution: attribution.map(owned),
+                body: all(body),
+            },
+            Segment::Code(s) => Segment::Code(owned(s)),
+            Segment::List { style, items } => Segment::List {
+                style,
+                items: items.into_iter().map(all).collect(),
+            },
+            Segment::Link { target, text } => Segment::Link {
+                target: owned(target),
+                text: all(text),
+            },
+            Segment::Image { src } => Segment::Image { src: owned(src) },
+            Segment::Custom { name, attrs, body } => Segment::Custom {
+                name: owned(name),
+                attrs: attrs
+                    .into_iter()
+                    .map(|(name, value)| (owned(name), owned(value)))
+                    .collect(),
+                body: all(body),
+            },
+            Segment::Heading { level, text } => Segment::Heading {
+                level,
+                text: all(text),
+            },
+            Segment::Rule => Segment::Rule,
+            Segment::Embed {
+                provider,
+                id,
+                params,
+            } => Segment::Embed {
+                provider: owned(provider),
+                id: owned(id),
+                params: params
+                    .into_iter()
+                    .map(|(name, value)| (owned(name), owned(value)))
+                    .collect(),
+            },
+            Segment::Spoiler { title, body } => Segment::Spoiler {
+                title: title.map(owned),
+                body: all(body),
+            },
+            Segment::Table { rows } => Segment::Table {
+                rows: rows
+                    .into_iter()
+                    .map(|row| row.into_iter().map(TableCell::into_owned).collect())
+                    .collect(),
+            },
         }
-        self.eq_ignore_ascii_case(&s[..self.len()])
-    }
-}
-
-/// Leading substring match for any item.
-impl Terminal for &[&str; 2] {
-    fn leads(&self, s: &str) -> bool {
-        self.iter().any(|p| p.leads(s))
     }
 }
 
-fn segment<'a, T: Terminal + Debug>(
-    input: &'a str,
-    terminal: T,
-) -> nom::IResult<&'a str, Segment<'a>, u32> {
-    // Try to find a valid coded segment, otherwise take plain text.
-    //
-    // TODO this could be converted to support streaming; it returns either
-    // a coded segment, eof or incomplete- a leading coded segment can be
-    // returned, and a text segment can be returned if the tail's length is
-    // nonzero or the input is at eof. This means in the pure-text case we
-    // still need to buffer the entire input, but can opportunistically stream
-    // segments as they appear.
-    if let Ok((tail, segment)) = coded_segment(input) {
-        return Ok((tail, segment));
-    }
-    let (tail, text) = text_segment(input, terminal)?;
-    return Ok((tail, Segment::Text(text)));
-}
-
-named!(coded_segment(&str) -> Segment,
-    alt_complete!(
-        decoration::decorated
-        | code::code
-        | image
-        | list::list
-        | quote::quote
-        | url::url
-    )
-);
-
-/// Matches any nonzero amount of input that is not a coded_segment.
-///
-/// Terminates at end of input or if the provided terminal is found, but does
-/// not consume the terminal.
-fn text_segment<'a, T: Terminal + Debug>(
-    input: &'a str,
-    terminal: T,
-) -> nom::IResult<&'a str, &'a str, u32> {
-    debug!("text_segment until {:?} in {}", terminal, input);
-
-    if terminal.leads(input) {
-        return Err(nom::Err::Error(nom::Context::Code(
-            input,
-            nom::ErrorKind::Custom(0),
-        )));
-    }
-
-    // Search for either a coded segment or the terminal string, either of which
-    // ends the plain text.
-    //
-    // Text segment applies only if no coded segment was found, so we do not
-    // consider the first character of input for coded segments- asking for text
-    // implies the caller doesn't expect a coded segment right away.
-    //
-    // This is required to be expensive- malformed markup should become plain text.
-    // The reference implementation does substantially this but the costs are
-    // hidden inside regexes.
-    //
-    // TODO a validation mode that errors out on malformed markup could be useful.
-    for (idx, _) in input.char_indices().skip(1) {
-        let search_point = &input[idx..];
-        if terminal.leads(search_point) || coded_segment(search_point).is_ok() {
-            let (consumed, rest) = input.split_at(idx);
-            return Ok((rest, consumed));
-        }
-    }
-
-    // Fell off the end of input without finding a coded segment,
-    // so return all of it.
-    Ok((&input[input.len()..], input))
-}
+pub use parser::{parse, parse_strict, validate, Parser, Tag, DEFAULT_MAX_DEPTH};
 
 #[test]
-fn text_segment_no_terminal() {
-    assert_eq!(text_segment("Hello, world!", ()), Ok(("", "Hello, world!")));
+fn text_without_tags() {
+    assert_eq!(
+        parse("Hello, world!"),
+        vec![Segment::Text("Hello, world!".into())]
+    );
 }
 
 #[test]
-fn text_segment_with_terminal() {
-    assert_eq!(text_segment("Foo\r\nBar", "\r\n"), Ok(("\r\nBar", "Foo")));
+fn text_ends_at_closing_tag() {
+    assert_eq!(
+        parse("[i]Foo\r\n[/i]Bar"),
+        vec![
+            Segment::Decorated {
+                style: DecorationStyle::Italic,
+                text: vec![Segment::Text("Foo\r\n".into())],
+            },
+            Segment::Text("Bar".into()),
+        ]
+    );
 }
 
 #[test]
@@ -279,33 +332,71 @@ fn nested_segments() {
         vec![Segment::Decorated {
             style: DecorationStyle::Bold,
             text: vec![
-                Segment::Text("Foo"),
+                Segment::Text("Foo".into()),
                 Segment::Decorated {
                     style: DecorationStyle::Italic,
-                    text: vec![Segment::Text("bar")],
+                    text: vec![Segment::Text("bar".into())],
                 }
             ],
         }]
     );
 }
 
-named!(image(&str) -> Segment,
-    map!(
-        delimited!(
-            tag_no_case!("[img]"),
-            take_until_no_case!("[/img]"),
-            tag_no_case!("[/img]")
-        ),
-        |src| Segment::Image { src }
-    )
-);
+#[test]
+fn unclosed_tags_are_text() {
+    assert_eq!(
+        parse("[b][i]x[/b][/i]"),
+        vec![
+            Segment::Text("[b]".into()),
+            Segment::Decorated {
+                style: DecorationStyle::Italic,
+                text: vec![Segment::Text("x[/b]".into())],
+            },
+        ]
+    );
+    assert_eq!(
+        parse("[b][i]x[/b]"),
+        vec![Segment::Decorated {
+            style: DecorationStyle::Bold,
+            text: vec![Segment::Text("[i]x".into())],
+        }]
+    );
+}
 
 #[test]
 fn parse_image() {
     assert_eq!(
         parse("[img]http://example.com/foo.webp[/img]"),
         vec![Segment::Image {
-            src: "http://example.com/foo.webp"
+            src: "http://example.com/foo.webp".into()
         }]
     )
 }
+
+#[test]
+fn owned_segments_outlive_input() {
+    use render::{Renderer, SimpleHtml};
+
+    let segments: Vec<OwnedSegment> = {
+        let input = format!("[quote=\"{}\"]{}[/quote]", "A", "[b]x[/b]");
+        parse(&input).into_iter().map(Segment::into_owned).collect()
+    };
+    let built = vec![Segment::Quote {
+        attribution: Some(String::from("A").into()),
+        body: vec![Segment::Decorated {
+            style: DecorationStyle::Bold,
+            text: vec![Segment::Text(String::from("x").into())],
+        }],
+    }];
+    assert_eq!(segments, built);
+
+    let html = std::thread::spawn(move || {
+        let mut out = vec![];
+        SimpleHtml::new(&mut out).render(&segments).unwrap();
+        out
+    });
+    assert_eq!(
+        html.join().unwrap(),
+        b"<div>A wrote:</div><div><b>x</b></div>".to_vec()
+    );
+}
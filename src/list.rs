@@ -1,10 +1,17 @@
 //! Lists of items.
 
-use super::Segment::List;
-use super::{segment, Segment};
+#[cfg(test)]
+use super::Segment::{List, Text};
+#[cfg(test)]
+use super::{parse, Segment};
 
 /// The general appearance of a list.
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum ListStyle {
     /// No particular order; items usually marked with bullet points.
     ///
@@ -20,18 +27,11 @@ pub enum ListStyle {
     Alphabetic,
 }
 
-/// Recognizes a list: `[list][*] Item [*] Item[/list]`
-named!(pub list(&str) -> Segment,
-    do_parse!(
-        style: listhead >>
-        items: many0!(preceded!(tag_no_case!("[*]"),
-                                many0!(call!(segment, &["[*]", "[/list]"])))) >>
-        tag_no_case!("[/list]") >>
-        (List { style, items })
-    )
-);
-
-named!(listhead(&str) -> ListStyle,
+// Recognizes the opening tag of a list: `[list]`, `[list=1]` or `[list=a]`.
+//
+// The body of a list is a sequence of items, each beginning with `[*]`,
+// followed by `[/list]`: `[list][*] Item [*] Item[/list]`.
+named!(pub listhead(&str) -> ListStyle,
     map!(
         delimited!(
             tag_no_case!("[list"),
@@ -54,27 +54,33 @@ named!(listhead(&str) -> ListStyle,
 
 #[test]
 fn list_with_multiple_items() {
-    let (tail, x) = list("[list][*] One\n[*] Two[/list]Tail").expect("Should parse successfully");
     assert_eq!(
-        x,
-        List {
-            style: ListStyle::Unordered,
-            items: vec![vec![Segment::Text(" One\n")], vec![Segment::Text(" Two")],]
-        }
+        parse("[list][*] One\n[*] Two[/list]Tail"),
+        vec![
+            List {
+                style: ListStyle::Unordered,
+                items: vec![vec![Text(" One\n".into())], vec![Text(" Two".into())]],
+            },
+            Text("Tail".into()),
+        ]
     );
-    assert_eq!(tail, "Tail");
 }
 
 #[test]
 fn empty_list() {
     assert_eq!(
-        list("[list=a][/list]"),
-        Ok((
-            "",
-            List {
-                style: ListStyle::Alphabetic,
-                items: vec![],
-            }
-        ))
+        parse("[list=a][/list]"),
+        vec![List {
+            style: ListStyle::Alphabetic,
+            items: vec![],
+        }]
+    );
+}
+
+#[test]
+fn list_requires_leading_item() {
+    assert_eq!(
+        parse("[list]x[*]y[/list]"),
+        vec![Segment::Text("[list]x[*]y[/list]".into())]
     );
 }
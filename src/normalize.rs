@@ -0,0 +1,238 @@
+//! Simplifying trees of segments.
+//!
+//! Parsing can give trees with more structure than their markup needs, like
+//! the empty decoration in `a[b][/b]b` which leaves two text segments
+//! either side of it. Each pass here removes one kind of redundancy, changing
+//! segments in place; `normalize` applies all of them.
+//!
+//! ```
+//! use bbcode::{normalize, parse};
+//!
+//! let mut segments = parse("a[b][/b]b[i][i]c[/i][/i]");
+//! normalize::normalize(&mut segments);
+//! assert_eq!(segments, parse("ab[i]c[/i]"));
+//! ```
+
+use super::visit::{walk_segment_mut, walk_segments_mut, VisitorMut};
+use super::{DecorationStyle, Segment, Tag};
+use std::mem;
+
+/// Apply every pass, in an order where none leaves work for the others.
+pub fn normalize(segments: &mut Vec<Segment>) {
+    collapse_styles(segments);
+    drop_empty_decorations(segments);
+    drop_blank_items(segments);
+    merge_text(segments);
+}
+
+/// Join adjacent text segments into one, and remove empty ones.
+pub fn merge_text(segments: &mut Vec<Segment>) {
+    MergeText.visit_segments_mut(segments);
+}
+
+/// Remove decorations that contain nothing, like `[b][/b]`.
+///
+/// Decorations containing only empty decorations are removed too.
+pub fn drop_empty_decorations(segments: &mut Vec<Segment>) {
+    DropEmptyDecorations.visit_segments_mut(segments);
+}
+
+/// Remove decorations nested in a decoration of the same kind and style,
+/// keeping what they contain: `[b][b]x[/b][/b]` becomes `[b]x[/b]`.
+///
+/// A decoration inside another of the same kind but a different style is
+/// kept, as are decorations inside it: the last `[color]` in
+/// `[color=red][color=blue][color=red]x[/color][/color][/color]` is needed.
+/// Subscripts and superscripts are never redundant, as each one nested in
+/// another lowers or raises its text further.
+pub fn collapse_styles(segments: &mut Vec<Segment>) {
+    CollapseStyles { styles: vec![] }.visit_segments_mut(segments);
+}
+
+/// Remove list items that contain only whitespace, like the second in
+/// `[list][*]a[*] [/list]`.
+pub fn drop_blank_items(segments: &mut Vec<Segment>) {
+    DropBlankItems.visit_segments_mut(segments);
+}
+
+struct MergeText;
+
+impl<'a> VisitorMut<'a> for MergeText {
+    fn visit_segments_mut(&mut self, segments: &mut Vec<Segment<'a>>) {
+        for segment in mem::take(segments) {
+            match (segments.last_mut(), segment) {
+                (_, Segment::Text(ref s)) if s.is_empty() => {}
+                (Some(&mut Segment::Text(ref mut last)), Segment::Text(s)) => {
+                    last.to_mut().push_str(&s)
+                }
+                (_, segment) => segments.push(segment),
+            }
+        }
+        walk_segments_mut(self, segments);
+    }
+}
+
+struct DropEmptyDecorations;
+
+impl<'a> VisitorMut<'a> for DropEmptyDecorations {
+    fn visit_segments_mut(&mut self, segments: &mut Vec<Segment<'a>>) {
+        // Segments are walked first, so decorations that become empty are
+        // also removed.
+        walk_segments_mut(self, segments);
+        segments.retain(|segment| match *segment {
+            Segment::Decorated { ref text, .. } => !is_empty(text),
+            _ => true,
+        });
+    }
+}
+
+/// Whether segments contain no text or other content.
+fn is_empty(segments: &[Segment]) -> bool {
+    segments.iter().all(|segment| match *segment {
+        Segment::Text(ref s) => s.is_empty(),
+        _ => false,
+    })
+}
+
+/// Whether segments contain only whitespace.
+fn is_blank(segments: &[Segment]) -> bool {
+    segments.iter().all(|segment| match *segment {
+        Segment::Text(ref s) => s.trim().is_empty(),
+        _ => false,
+    })
+}
+
+struct CollapseStyles {
+    /// The decorations enclosing the segments being visited.
+    styles: Vec<DecorationStyle>,
+}
+
+impl CollapseStyles {
+    /// Add segment to segments, or its contents if it is redundant.
+    fn push<'a>(&self, segments: &mut Vec<Segment<'a>>, segment: Segment<'a>) {
+        match segment {
+            Segment::Decorated { style, text } if self.is_redundant(style) => {
+                for segment in text {
+                    self.push(segments, segment);
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    fn is_redundant(&self, style: DecorationStyle) -> bool {
+        if style == DecorationStyle::Subscript || style == DecorationStyle::Superscript {
+            return false;
+        }
+        let enclosing = self
+            .styles
+            .iter()
+            .rev()
+            .find(|s| Tag::from(**s) == Tag::from(style));
+        enclosing == Some(&style)
+    }
+}
+
+impl<'a> VisitorMut<'a> for CollapseStyles {
+    fn visit_segments_mut(&mut self, segments: &mut Vec<Segment<'a>>) {
+        for segment in mem::take(segments) {
+            self.push(segments, segment);
+        }
+        walk_segments_mut(self, segments);
+    }
+
+    fn visit_segment_mut(&mut self, segment: &mut Segment<'a>) {
+        if let Segment::Decorated { style, .. } = *segment {
+            self.styles.push(style);
+            walk_segment_mut(self, segment);
+            self.styles.pop();
+        } else {
+            walk_segment_mut(self, segment);
+        }
+    }
+}
+
+struct DropBlankItems;
+
+impl<'a> VisitorMut<'a> for DropBlankItems {
+    fn visit_segment_mut(&mut self, segment: &mut Segment<'a>) {
+        if let Segment::List { ref mut items, .. } = *segment {
+            items.retain(|item| !is_blank(item));
+        }
+        walk_segment_mut(self, segment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse, DecorationStyle, Segment};
+    use super::*;
+
+    #[test]
+    fn merges_text() {
+        let mut segments = vec![
+            Segment::Text("a".into()),
+            Segment::Text("".into()),
+            Segment::Text(String::from("b").into()),
+            Segment::Decorated {
+                style: DecorationStyle::Bold,
+                text: vec![Segment::Text("c".into()), Segment::Text("d".into())],
+            },
+            Segment::Text("e".into()),
+        ];
+        merge_text(&mut segments);
+        assert_eq!(segments, parse("ab[b]cd[/b]e"));
+    }
+
+    #[test]
+    fn drops_empty_decorations() {
+        let mut segments = parse("a[b][/b][i][u][/u][/i][center] [/center][quote][/quote]");
+        drop_empty_decorations(&mut segments);
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Text("a".into()),
+                Segment::Decorated {
+                    style: DecorationStyle::Center,
+                    text: vec![Segment::Text(" ".into())],
+                },
+                Segment::Quote {
+                    attribution: None,
+                    body: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn collapses_styles() {
+        let mut segments = parse("[i]a[quote][I]b[i]c[/i][/i][/quote][/i]");
+        collapse_styles(&mut segments);
+        merge_text(&mut segments);
+        assert_eq!(segments, parse("[i]a[quote]bc[/quote][/i]"));
+
+        let input = "[color=#ff0000][color=#0000ff][color=#ff0000]x[/color][/color][/color]";
+        let mut segments = parse(input);
+        collapse_styles(&mut segments);
+        assert_eq!(segments, parse(input));
+
+        let mut segments = parse("[s]a[del]b[/del][/s][sup]c[sup]d[/sup][/sup]");
+        collapse_styles(&mut segments);
+        merge_text(&mut segments);
+        assert_eq!(segments, parse("[s]ab[/s][sup]c[sup]d[/sup][/sup]"));
+    }
+
+    #[test]
+    fn drops_blank_items() {
+        let mut segments = parse("[list][*]a[*] \n[*][list=1][*][*]\tb[/list][*][/list]");
+        drop_blank_items(&mut segments);
+        assert_eq!(segments, parse("[list][*]a[*][list=1][*]\tb[/list][/list]"));
+    }
+
+    #[test]
+    fn normalizes() {
+        let mut segments = parse("[b]a[b][/b]b[/b][list][*]c[i][/i]d[*] [/list]");
+        normalize(&mut segments);
+        assert_eq!(segments, parse("[b]ab[/b][list][*]cd[/list]"));
+    }
+}
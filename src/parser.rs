@@ -0,0 +1,1692 @@
+//! Tag-scanning parser.
+//!
+//! A coded segment is a tag whose closing tag can be found after it, skipping
+//! over any coded segments nested inside. Everything else is plain text:
+//! an unclosed `[b]` is text, and so are closing tags that don't match
+//! anything.
+//!
+//! Whether a tag is closed depends only on the input that follows it, so the
+//! scanner works backwards from the end of the input. For every `[` it records
+//! the coded segment beginning there (if any) and, for every kind of closing
+//! tag, the first one reachable from that point without entering a coded
+//! segment. Each opening tag then finds its closing tag with a single lookup,
+//! which bounds the work at a constant amount per `[` for each kind of tag.
+//! A forward pass then assembles the `Segment`s with an explicit stack.
+//!
+//! Segments themselves are recursive, and are dropped, compared and rendered
+//! recursively, so nesting is limited by `Parser::max_depth` to keep deeply
+//! nested input from overflowing the call stack later on.
+
+use super::custom::{self, Body, TagHandler};
+use super::embed::{self, EmbedProvider, Media, Providers};
+use super::span::{Locator, Position, Span, Spans};
+use super::validate::{Diagnostic, DiagnosticKind};
+use super::{
+    code, decoration, heading, list, quote, spoiler, table, url, DecorationStyle, ListStyle,
+    Segment, TableCell,
+};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::mem;
+use std::sync::Arc;
+
+/// Marks a missing position in the scanner's tables.
+const NONE: u32 = u32::MAX;
+
+/// Names of the built-in closing tags.
+///
+/// Each closing tag gets a kind of stopping point, indexed by its position
+/// here; those of custom tags follow.
+const CLOSERS: [&str; 33] = [
+    "b", "i", "u", "center", "color", "size", "code", "img", "list", "quote", "url", "h1", "h2",
+    "h3", "h4", "h5", "h6", "sub", "sup", "strike", "s", "del", "mono", "tt", "youtube", "video",
+    "media", "spoiler", "hide", "table", "tr", "td", "th",
+];
+
+/// The tag closed by each of `CLOSERS`.
+const TAGS: [Tag; 33] = [
+    Tag::Bold,
+    Tag::Italic,
+    Tag::Underline,
+    Tag::Center,
+    Tag::Color,
+    Tag::Size,
+    Tag::Code,
+    Tag::Image,
+    Tag::List,
+    Tag::Quote,
+    Tag::Url,
+    Tag::Heading,
+    Tag::Heading,
+    Tag::Heading,
+    Tag::Heading,
+    Tag::Heading,
+    Tag::Heading,
+    Tag::Subscript,
+    Tag::Superscript,
+    Tag::Strikethrough,
+    Tag::Strikethrough,
+    Tag::Strikethrough,
+    Tag::Monospace,
+    Tag::Monospace,
+    Tag::Embed,
+    Tag::Embed,
+    Tag::Embed,
+    Tag::Spoiler,
+    Tag::Spoiler,
+    Tag::Table,
+    Tag::Table,
+    Tag::Table,
+    Tag::Table,
+];
+
+/// Index of `[/list]` in `CLOSERS`.
+const LIST: usize = 8;
+
+/// Indices of `[/table]`, `[/tr]`, `[/td]` and `[/th]` in `CLOSERS`.
+const TABLE: usize = 29;
+const ROW: usize = 30;
+const DATA: usize = 31;
+const HEADER: usize = 32;
+
+/// The longest body of an embed tag that is given to the providers, which
+/// keeps the work done for each `[` bounded.
+const MAX_EMBED: usize = 2048;
+
+/// Parse a string into a sequence of `Segment`s.
+///
+/// Every tag is recognized, without limits; equivalent to
+/// `Parser::new().parse(input)`.
+pub fn parse(input: &str) -> Vec<Segment<'_>> {
+    Parser::new().parse(input)
+}
+
+/// Parse a string, failing if any markup is malformed.
+///
+/// Equivalent to `Parser::new().parse_strict(input)`.
+pub fn parse_strict(input: &str) -> Result<Vec<Segment<'_>>, Vec<Diagnostic>> {
+    Parser::new().parse_strict(input)
+}
+
+/// Find any malformed markup in a string.
+///
+/// Equivalent to `Parser::new().validate(input)`.
+pub fn validate(input: &str) -> Vec<Diagnostic> {
+    Parser::new().validate(input)
+}
+
+/// Kinds of tag the parser can recognize.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Tag {
+    /// `[b]`
+    Bold,
+    /// `[i]`
+    Italic,
+    /// `[u]`
+    Underline,
+    /// `[center]`
+    Center,
+    /// `[color=...]`
+    Color,
+    /// `[size=...]`
+    Size,
+    /// `[code]`
+    Code,
+    /// `[img]`
+    Image,
+    /// `[list]`, with its `[*]` items.
+    List,
+    /// `[quote]`
+    Quote,
+    /// `[url]`, in any of its forms.
+    Url,
+    /// `[h1]` to `[h6]`
+    Heading,
+    /// `[hr]`
+    Rule,
+    /// `[sub]`
+    Subscript,
+    /// `[sup]`
+    Superscript,
+    /// `[strike]`, `[s]` or `[del]`
+    Strikethrough,
+    /// `[mono]` or `[tt]`
+    Monospace,
+    /// `[youtube]`, `[video]` or `[media]`
+    Embed,
+    /// `[spoiler]` or `[hide]`
+    Spoiler,
+    /// `[table]`, with its `[tr]` rows and `[th]` and `[td]` cells.
+    Table,
+}
+
+impl From<DecorationStyle> for Tag {
+    fn from(style: DecorationStyle) -> Tag {
+        match style {
+            DecorationStyle::Bold => Tag::Bold,
+            DecorationStyle::Italic => Tag::Italic,
+            DecorationStyle::Underline => Tag::Underline,
+            DecorationStyle::Center => Tag::Center,
+            DecorationStyle::Color(..) => Tag::Color,
+            DecorationStyle::Size(_) => Tag::Size,
+            DecorationStyle::Subscript => Tag::Subscript,
+            DecorationStyle::Superscript => Tag::Superscript,
+            DecorationStyle::Strikethrough => Tag::Strikethrough,
+            DecorationStyle::Monospace => Tag::Monospace,
+        }
+    }
+}
+
+/// How deeply coded segments may be nested unless `Parser::max_depth` is
+/// used; deep enough for any real post, shallow enough that working on the
+/// segments recursively can't overflow the stack.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// A configurable parser.
+///
+/// By default every built-in tag is recognized, coded segments may be nested
+/// up to `DEFAULT_MAX_DEPTH` deep, and there is no limit on their number.
+/// Tags that are disabled or exceed a limit are left as text.
+/// Custom tags can be added with `register`, and the sites whose media can be
+/// embedded set with `providers`.
+///
+/// ```
+/// use bbcode::{Parser, Segment, Tag};
+///
+/// let parser = Parser::new().disable(Tag::Image).max_depth(1);
+/// assert_eq!(
+///     parser.parse("[img]cat.gif[/img]"),
+///     vec![Segment::Text("[img]cat.gif[/img]".into())]
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Parser {
+    disabled: HashSet<Tag>,
+    handlers: Vec<Arc<dyn TagHandler>>,
+    providers: Providers,
+    max_depth: usize,
+    max_nodes: usize,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser {
+            disabled: HashSet::new(),
+            handlers: vec![],
+            providers: Providers::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: usize::MAX,
+        }
+    }
+}
+
+impl Parser {
+    /// Construct a parser that recognizes every tag.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop recognizing `tag`, leaving it as text.
+    pub fn disable(mut self, tag: Tag) -> Self {
+        self.disabled.insert(tag);
+        self
+    }
+
+    /// Recognize `tag` again after it was disabled.
+    pub fn enable(mut self, tag: Tag) -> Self {
+        self.disabled.remove(&tag);
+        self
+    }
+
+    /// Return whether `tag` is recognized.
+    pub fn is_enabled(&self, tag: Tag) -> bool {
+        !self.disabled.contains(&tag)
+    }
+
+    /// Recognize a custom tag, which is parsed into `Segment::Custom`.
+    ///
+    /// Custom tags are only tried where no built-in tag matches, so one can
+    /// replace a built-in tag of the same name only if that is disabled. If
+    /// several handlers have the same name, the first one registered wins.
+    ///
+    /// # Panics
+    ///
+    /// If the handler's name is empty or not ASCII alphanumeric.
+    pub fn register<H: TagHandler + 'static>(mut self, handler: H) -> Self {
+        let name = handler.name();
+        assert!(
+            !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric()),
+            "tag name {:?} must be ASCII alphanumeric",
+            name
+        );
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    /// The handler registered for a custom tag, matching its name ignoring
+    /// ASCII case.
+    pub fn handler(&self, name: &str) -> Option<&dyn TagHandler> {
+        self.handlers
+            .iter()
+            .find(|h| h.name().eq_ignore_ascii_case(name))
+            .map(|h| &**h)
+    }
+
+    /// Set the providers of media for embed tags like `[youtube]`, which are
+    /// `Providers::new()` by default.
+    pub fn providers(mut self, providers: Providers) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    /// The provider of embedded media with a name, matching it ignoring ASCII
+    /// case.
+    pub fn provider(&self, name: &str) -> Option<&dyn EmbedProvider> {
+        self.providers.get(name)
+    }
+
+    /// Limit how deeply coded segments may be nested.
+    ///
+    /// Segments at the top level have depth 1, those inside them depth 2 and
+    /// so on. A coded segment deeper than the limit is left as text, along
+    /// with everything inside it. A depth of 0 leaves all input as text.
+    ///
+    /// Raising the limit far beyond `DEFAULT_MAX_DEPTH` lets input nest
+    /// segments deeply enough to overflow the stack when they are dropped or
+    /// rendered.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Limit the number of coded segments (anything but `Segment::Text`).
+    ///
+    /// Coded segments are counted in the order their opening tags appear in
+    /// the input; those past the limit are left as text, along with
+    /// everything inside them. Text segments are not counted, but can never
+    /// be adjacent so there are at most `2 * nodes + 1` of them.
+    pub fn max_nodes(mut self, nodes: usize) -> Self {
+        self.max_nodes = nodes;
+        self
+    }
+
+    /// Parse a string into a sequence of `Segment`s.
+    pub fn parse<'a>(&self, input: &'a str) -> Vec<Segment<'a>> {
+        self.scan(input).build(None, None).0
+    }
+
+    /// Parse a string into a sequence of `Segment`s, along with the `Spans`
+    /// of each.
+    pub fn parse_spanned<'a>(&self, input: &'a str) -> (Vec<Segment<'a>>, Vec<Spans>) {
+        self.scan(input).build(Some(Locator::new(input)), None)
+    }
+
+    /// Parse a string, failing if any markup is malformed.
+    ///
+    /// The markup is malformed if `validate` finds any problems with it.
+    pub fn parse_strict<'a>(&self, input: &'a str) -> Result<Vec<Segment<'a>>, Vec<Diagnostic>> {
+        let mut scanner = self.scan(input);
+        let mut text = vec![];
+        let (segments, _) = scanner.build(None, Some(&mut text));
+        let diagnostics = scanner.diagnose(&text);
+        if diagnostics.is_empty() {
+            Ok(segments)
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    /// Find any malformed markup in a string, in order of location.
+    ///
+    /// This reports tags of the kinds that are enabled which `parse` leaves
+    /// as text, except those over the limits on the output. Text inside
+    /// tags with raw bodies like `[code]` is never reported, nor are tags
+    /// that the parser does not know about at all.
+    ///
+    /// ```
+    /// use bbcode::{validate, DiagnosticKind};
+    ///
+    /// let diagnostics = validate("Ahem [b]hello");
+    /// assert_eq!(diagnostics[0].kind, DiagnosticKind::Unclosed);
+    /// assert_eq!(diagnostics[0].span.start.column, 6);
+    /// ```
+    pub fn validate(&self, input: &str) -> Vec<Diagnostic> {
+        self.parse_strict(input).err().unwrap_or_default()
+    }
+
+    fn scan<'a>(&self, input: &'a str) -> Scanner<'a, '_> {
+        assert!(
+            input.len() < NONE as usize,
+            "input must be smaller than 4 GiB"
+        );
+
+        let mut scanner = Scanner::new(input, self);
+        scanner.scan();
+        scanner
+    }
+}
+
+/// The opening tag of a coded segment.
+#[derive(Debug)]
+enum Head<'a> {
+    Decoration(DecorationStyle),
+    Code,
+    Image,
+    List(ListStyle),
+    Quote(Option<&'a str>),
+    Heading(u8),
+    Rule,
+    /// `[url]`, where the body is both target and text.
+    BareLink,
+    Link(&'a str),
+    Embed {
+        /// The name of the provider.
+        provider: String,
+        media: Media,
+    },
+    Spoiler(Option<&'a str>),
+    Table(Vec<Row>),
+    Custom {
+        /// Index of the handler in the parser.
+        handler: usize,
+        name: &'a str,
+        attrs: Vec<(&'a str, &'a str)>,
+    },
+}
+
+/// A closing tag, list item marker or the start of a row or cell of a table.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Mark {
+    /// Closing tag, by index into `CLOSERS`.
+    Close(usize),
+    /// `[*]`
+    Item,
+    /// `[tr]`
+    Row,
+    /// `[th]` if true, or `[td]`.
+    Cell(bool),
+}
+
+/// A row of a table found in the input.
+#[derive(Debug)]
+struct Row {
+    /// Offset of `[tr]`.
+    open: usize,
+    /// Offset of `[/tr]`.
+    close: usize,
+    cells: Vec<Cell>,
+}
+
+/// A cell of a table found in the input.
+#[derive(Debug)]
+struct Cell {
+    header: bool,
+    /// Offset of `[th]` or `[td]`.
+    open: usize,
+    /// Offset of the start of the body, just past the opening tag.
+    body: usize,
+    /// Offset of the end of the body, where the closing tag begins.
+    close: usize,
+}
+
+/// A coded segment found in the input.
+#[derive(Debug)]
+struct Match<'a> {
+    head: Head<'a>,
+    /// Offset of the start of the body, just past the opening tag.
+    body: usize,
+    /// Offset of the end of the body, where the closing tag begins.
+    close: usize,
+    /// Offset just past the closing tag.
+    end: usize,
+}
+
+impl<'a> Head<'a> {
+    /// The kind of built-in tag, or `None` for custom tags.
+    fn tag(&self) -> Option<Tag> {
+        Some(match *self {
+            Head::Decoration(style) => Tag::from(style),
+            Head::Code => Tag::Code,
+            Head::Image => Tag::Image,
+            Head::List(_) => Tag::List,
+            Head::Quote(_) => Tag::Quote,
+            Head::Heading(_) => Tag::Heading,
+            Head::Rule => Tag::Rule,
+            Head::BareLink | Head::Link(_) => Tag::Url,
+            Head::Embed { .. } => Tag::Embed,
+            Head::Spoiler(_) => Tag::Spoiler,
+            Head::Table(_) => Tag::Table,
+            Head::Custom { .. } => return None,
+        })
+    }
+}
+
+struct Scanner<'a, 'p> {
+    input: &'a str,
+    parser: &'p Parser,
+    /// Names of every closing tag: `CLOSERS`, then those of custom tags.
+    closers: Vec<&'p str>,
+    /// Index of the kind of stopping point for list items, which end at
+    /// either `[*]` or `[/list]`.
+    items_terminal: usize,
+    /// Number of kinds of stopping point: one for each closing tag, plus
+    /// list items.
+    terminals: usize,
+    /// Offset of every `[` in the input.
+    brackets: Vec<u32>,
+    /// For every offset into the input (and its end), the index in
+    /// `brackets` of the first `[` at or after that offset.
+    next_bracket: Vec<u32>,
+    /// The closing tag or item marker at each `[`, if any.
+    marks: Vec<Option<Mark>>,
+    /// The coded segment starting at each `[`, if any.
+    matches: Vec<Option<Match<'a>>>,
+    /// For each `[` and each kind of stopping point, the index of the first
+    /// `[` at or after it where that closing tag appears, skipping over coded
+    /// segments.
+    ///
+    /// Stored by rows of `terminals` entries, one row per `[` plus one for the
+    /// end of the input.
+    stops: Vec<u32>,
+    /// For each `[*]`, the `[/list]` that ends the list it belongs to.
+    items: Vec<u32>,
+    /// For each `[/table]`, and each `[tr]`, cell or closing tag of a row or
+    /// cell, the `[/table]` that ends the table it belongs to, if it can be
+    /// in one.
+    tables: Vec<u32>,
+    lookahead: Lookahead<'a>,
+}
+
+impl<'a, 'p> Scanner<'a, 'p> {
+    fn new(input: &'a str, parser: &'p Parser) -> Self {
+        let bytes = input.as_bytes();
+        let brackets: Vec<u32> = (0..bytes.len())
+            .filter(|&i| bytes[i] == b'[')
+            .map(|i| i as u32)
+            .collect();
+
+        let mut next_bracket = vec![brackets.len() as u32; bytes.len() + 1];
+        let mut next = brackets.len();
+        for i in (0..bytes.len()).rev() {
+            if bytes[i] == b'[' {
+                next -= 1;
+            }
+            next_bracket[i] = next as u32;
+        }
+
+        let mut closers = CLOSERS.to_vec();
+        closers.extend(parser.handlers.iter().map(|h| h.name()));
+        let items_terminal = closers.len();
+        let terminals = items_terminal + 1;
+
+        let n = brackets.len();
+        Scanner {
+            input,
+            parser,
+            closers,
+            items_terminal,
+            terminals,
+            brackets,
+            next_bracket,
+            marks: Vec::with_capacity(n),
+            matches: Vec::with_capacity(n),
+            stops: vec![NONE; (n + 1) * terminals],
+            items: vec![NONE; n],
+            tables: vec![NONE; n],
+            lookahead: Lookahead::new(input),
+        }
+    }
+
+    fn bracket(&self, idx: u32) -> usize {
+        self.brackets[idx as usize] as usize
+    }
+
+    /// The first stopping point for `terminal` at or after the `[` at `idx`.
+    fn stop(&self, terminal: usize, idx: u32) -> u32 {
+        self.stops[idx as usize * self.terminals + terminal]
+    }
+
+    /// The index in `closers` of the closing tag called `name`.
+    fn closer(&self, name: &str) -> Option<usize> {
+        self.closers
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+    }
+
+    /// Fill in the tables, from the end of the input to the start.
+    fn scan(&mut self) {
+        let n = self.brackets.len();
+        self.marks.resize(n, None);
+        self.matches.resize_with(n, || None);
+
+        for i in (0..n).rev() {
+            let offset = self.bracket(i as u32);
+            let mark = self.mark(offset);
+            let found = if mark.is_none() {
+                self.open(offset)
+            } else {
+                None
+            };
+
+            // Where scanning continues after this point, skipping the
+            // entire coded segment if there is one.
+            let next = match found {
+                Some(ref m) => self.next_bracket[m.end] as usize,
+                None => i + 1,
+            };
+            let (row, items) = (i * self.terminals, self.items_terminal);
+            self.stops
+                .copy_within(next * self.terminals..(next + 1) * self.terminals, row);
+            match mark {
+                Some(Mark::Close(c)) => {
+                    self.stops[row + c] = i as u32;
+                    if c == LIST {
+                        self.stops[row + items] = i as u32;
+                    }
+                }
+                Some(Mark::Item) => self.stops[row + items] = i as u32,
+                Some(Mark::Row) | Some(Mark::Cell(_)) | None => {}
+            }
+
+            if mark == Some(Mark::Item) {
+                let after = self.next_bracket[offset + "[*]".len()];
+                let stop = self.stop(items, after);
+                self.items[i] = match stop {
+                    NONE => NONE,
+                    s if self.marks[s as usize] == Some(Mark::Close(LIST)) => s,
+                    s => self.items[s as usize],
+                };
+            }
+            self.tables[i] = match mark {
+                Some(Mark::Close(TABLE)) => i as u32,
+                Some(Mark::Row) => self.table_after(offset + "[tr]".len(), true),
+                Some(Mark::Cell(header)) => {
+                    let closer = if header { HEADER } else { DATA };
+                    match self.stop(closer, self.next_bracket[offset + "[td]".len()]) {
+                        NONE => NONE,
+                        stop => self.tables[stop as usize],
+                    }
+                }
+                Some(Mark::Close(DATA)) | Some(Mark::Close(HEADER)) => {
+                    self.table_after(offset + "[/td]".len(), true)
+                }
+                Some(Mark::Close(ROW)) => self.table_after(offset + "[/tr]".len(), false),
+                _ => NONE,
+            };
+
+            if let Some(ref m) = found {
+                trace!("coded segment at {}: {:?}", offset, m);
+            }
+            self.marks[i] = mark;
+            self.matches[i] = found;
+        }
+    }
+
+    /// Find the coded segment beginning at `offset`, if there is one.
+    fn open(&mut self, offset: usize) -> Option<Match<'a>> {
+        let s = &self.input[offset..];
+        let body = |rest: &str| offset + s.len() - rest.len();
+
+        if let Ok((rest, (style, closer))) = decoration::head(s) {
+            if let Some(m) = self.nested(Head::Decoration(style), body(rest), closer) {
+                return Some(m);
+            }
+        }
+        if let Ok((rest, _)) = code::head(s) {
+            if let Some(m) = self.verbatim(Head::Code, body(rest), "code") {
+                return Some(m);
+            }
+        }
+        if let Ok((rest, _)) = image_head(s) {
+            if let Some(m) = self.verbatim(Head::Image, body(rest), "img") {
+                return Some(m);
+            }
+        }
+        if let Ok((rest, style)) = list::listhead(s) {
+            if let Some(m) = self.list(Head::List(style), body(rest)) {
+                return Some(m);
+            }
+        }
+        if let Ok((rest, attribution)) = quote::qhead(s) {
+            if let Some(m) = self.nested(Head::Quote(attribution), body(rest), "quote") {
+                return Some(m);
+            }
+        }
+        if let Ok((rest, level)) = heading::head(s) {
+            let closer = heading::closer(level);
+            if let Some(m) = self.nested(Head::Heading(level), body(rest), closer) {
+                return Some(m);
+            }
+        }
+        if let Ok((rest, _)) = heading::rule(s) {
+            if self.allows(&Head::Rule) {
+                let body = body(rest);
+                return Some(Match {
+                    head: Head::Rule,
+                    body,
+                    close: body,
+                    end: body,
+                });
+            }
+        }
+        if let Ok((rest, (closer, provider))) = embed::head(s) {
+            if let Some(m) = self.embed(provider, body(rest), closer) {
+                return Some(m);
+            }
+        }
+        if let Ok((rest, closer)) = spoiler::head(s) {
+            if let Some((title, body)) = self.title(body(rest)) {
+                if let Some(m) = self.nested(Head::Spoiler(title), body, closer) {
+                    return Some(m);
+                }
+            }
+        }
+        if let Ok((rest, _)) = table::head(s) {
+            if let Some(m) = self.table(body(rest)) {
+                return Some(m);
+            }
+        }
+
+        if let Ok((rest, _)) = url::bare_head(s) {
+            if let Some(m) = self.verbatim(Head::BareLink, body(rest), "url") {
+                return Some(m);
+            }
+        }
+        if let Ok((rest, _)) = url::quoted_head(s) {
+            let target = body(rest);
+            if let Some(end) = self.lookahead.find("\"]", target) {
+                let link = Head::Link(&self.input[target..end]);
+                if let Some(m) = self.nested(link, end + 2, "url") {
+                    return Some(m);
+                }
+            }
+        }
+        if let Ok((rest, _)) = url::unquoted_head(s) {
+            let target = body(rest);
+            if let Some(end) = self.lookahead.find("]", target) {
+                let link = Head::Link(&self.input[target..end]);
+                if let Some(m) = self.nested(link, end + 1, "url") {
+                    return Some(m);
+                }
+            }
+        }
+
+        if self.parser.handlers.is_empty() {
+            return None;
+        }
+        let (name, attrs, body) = custom::head(self.input, offset, &mut self.lookahead)?;
+        let parser = self.parser;
+        let handler = parser
+            .handlers
+            .iter()
+            .position(|h| h.name().eq_ignore_ascii_case(name))?;
+        if !parser.handlers[handler].accepts(&attrs) {
+            return None;
+        }
+
+        let head = Head::Custom {
+            handler,
+            name,
+            attrs,
+        };
+        match parser.handlers[handler].body() {
+            Body::Nested => self.nested(head, body, parser.handlers[handler].name()),
+            Body::Verbatim => self.verbatim(head, body, parser.handlers[handler].name()),
+            Body::Empty => Some(Match {
+                head,
+                body,
+                close: body,
+                end: body,
+            }),
+        }
+    }
+
+    /// Whether a coded segment with this opening tag is allowed.
+    fn allows(&self, head: &Head) -> bool {
+        head.tag().is_none_or(|tag| self.parser.is_enabled(tag))
+    }
+
+    /// A tag with a body of segments, ending with the closing tag `name`.
+    fn nested(&self, head: Head<'a>, body: usize, name: &str) -> Option<Match<'a>> {
+        if !self.allows(&head) {
+            return None;
+        }
+        let closer = self.closer(name)?;
+        match self.stop(closer, self.next_bracket[body]) {
+            NONE => None,
+            stop => {
+                let close = self.bracket(stop);
+                Some(Match {
+                    head,
+                    body,
+                    close,
+                    end: close + name.len() + "[/]".len(),
+                })
+            }
+        }
+    }
+
+    /// A tag with a raw body, ending at the first closing tag `name`.
+    fn verbatim(&mut self, head: Head<'a>, body: usize, name: &str) -> Option<Match<'a>> {
+        if !self.allows(&head) {
+            return None;
+        }
+        let closer = format!("[/{}]", name);
+        self.lookahead.find(&closer, body).map(|close| Match {
+            head,
+            body,
+            close,
+            end: close + closer.len(),
+        })
+    }
+
+    /// Embedded media, ending at the first closing tag `name`, if a provider
+    /// recognizes the body.
+    fn embed(&mut self, provider: Option<&str>, body: usize, name: &str) -> Option<Match<'a>> {
+        if !self.parser.is_enabled(Tag::Embed) {
+            return None;
+        }
+        let closer = format!("[/{}]", name);
+        let close = self.lookahead.find(&closer, body)?;
+        if close - body > MAX_EMBED {
+            return None;
+        }
+        let (provider, media) = self
+            .parser
+            .providers
+            .find(provider, &self.input[body..close])?;
+        Some(Match {
+            head: Head::Embed {
+                provider: provider.name().to_owned(),
+                media,
+            },
+            body,
+            close,
+            end: close + closer.len(),
+        })
+    }
+
+    /// The rest of an opening tag with an optional value, like a spoiler,
+    /// beginning at `offset`: `]`, `="value"]` or `=value]`. Gives the value
+    /// and the offset of the body after the tag.
+    ///
+    /// As in `[url="..."]`, a quoted value ends at the first `"]`, or if
+    /// there is none is taken to be unquoted.
+    fn title(&mut self, offset: usize) -> Option<(Option<&'a str>, usize)> {
+        let input = self.input;
+        let rest = &input[offset..];
+        if rest.starts_with(']') {
+            return Some((None, offset + 1));
+        }
+        if !rest.starts_with('=') {
+            return None;
+        }
+        if rest.starts_with("=\"") {
+            if let Some(end) = self.lookahead.find("\"]", offset + 2) {
+                return Some((Some(&input[offset + 2..end]), end + 2));
+            }
+        }
+        let end = self.lookahead.find("]", offset + 1)?;
+        Some((Some(&input[offset + 1..end]), end + 1))
+    }
+
+    /// A list, which must begin with an item or end immediately.
+    fn list(&self, head: Head<'a>, body: usize) -> Option<Match<'a>> {
+        if !self.allows(&head) {
+            return None;
+        }
+        let first = self.next_bracket[body] as usize;
+        if first == self.brackets.len() || self.bracket(first as u32) != body {
+            return None;
+        }
+
+        let close = match self.marks[first] {
+            Some(Mark::Close(LIST)) => body,
+            Some(Mark::Item) if self.items[first] != NONE => self.bracket(self.items[first]),
+            _ => return None,
+        };
+        Some(Match {
+            head,
+            body,
+            close,
+            end: close + "[/list]".len(),
+        })
+    }
+
+    /// The index of the first `[` at or after `offset`, if there is only
+    /// whitespace before it.
+    fn after_space(&self, offset: usize) -> u32 {
+        let next = self.next_bracket[offset];
+        if next as usize == self.brackets.len() {
+            return NONE;
+        }
+        match self.input[offset..self.bracket(next)].trim() {
+            "" => next,
+            _ => NONE,
+        }
+    }
+
+    /// The `[/table]` ending a table that goes on at `offset`, after a row
+    /// or the opening tag if not `in_row`, or else after a cell or the start
+    /// of a row.
+    fn table_after(&self, offset: usize, in_row: bool) -> u32 {
+        let next = self.after_space(offset);
+        if next == NONE {
+            return NONE;
+        }
+        match (self.marks[next as usize], in_row) {
+            (Some(Mark::Row), false)
+            | (Some(Mark::Close(TABLE)), false)
+            | (Some(Mark::Cell(_)), true)
+            | (Some(Mark::Close(ROW)), true) => self.tables[next as usize],
+            _ => NONE,
+        }
+    }
+
+    /// A table, which must hold only rows of cells, and whitespace between
+    /// them.
+    fn table(&self, body: usize) -> Option<Match<'a>> {
+        if !self.parser.is_enabled(Tag::Table) {
+            return None;
+        }
+        let close = match self.table_after(body, false) {
+            NONE => return None,
+            close => self.bracket(close),
+        };
+
+        let mut rows = vec![];
+        let mut next = self.after_space(body);
+        while self.marks[next as usize] == Some(Mark::Row) {
+            let open = self.bracket(next);
+            let mut cells = vec![];
+            next = self.after_space(open + "[tr]".len());
+            while let Some(Mark::Cell(header)) = self.marks[next as usize] {
+                let open = self.bracket(next);
+                let body = open + "[td]".len();
+                let closer = if header { HEADER } else { DATA };
+                let close = self.bracket(self.stop(closer, self.next_bracket[body]));
+                cells.push(Cell {
+                    header,
+                    open,
+                    body,
+                    close,
+                });
+                next = self.after_space(close + "[/td]".len());
+            }
+            let row_close = self.bracket(next);
+            rows.push(Row {
+                open,
+                close: row_close,
+                cells,
+            });
+            next = self.after_space(row_close + "[/tr]".len());
+        }
+        Some(Match {
+            head: Head::Table(rows),
+            body,
+            close,
+            end: close + "[/table]".len(),
+        })
+    }
+
+    /// The next cell of the table being built in `frame`, completing the
+    /// rows before it, or `None` if every cell is complete.
+    fn next_cell<'m>(
+        &'m self,
+        frame: &mut Frame<'a>,
+        locator: &mut Option<Locator>,
+    ) -> Option<&'m Cell> {
+        let rows = match self.matches[frame.open?] {
+            Some(Match {
+                head: Head::Table(ref rows),
+                ..
+            }) => rows,
+            _ => unreachable!("frame is not a table"),
+        };
+        loop {
+            if frame.in_item {
+                let row = &rows[frame.rows.len() - 1];
+                let done = frame.rows.last().map_or(0, Vec::len);
+                if let Some(cell) = row.cells.get(done) {
+                    return Some(cell);
+                }
+                frame.in_item = false;
+                if let Some(ref mut locator) = *locator {
+                    let open = frame.item_marker.unwrap();
+                    let close = locator.span(row.close, row.close + "[/tr]".len());
+                    frame.item_spans.push(Spans {
+                        span: Span {
+                            start: open.start,
+                            end: close.end,
+                        },
+                        open: Some(open),
+                        close: Some(close),
+                        children: mem::take(&mut frame.spans),
+                    });
+                }
+            }
+            let row = rows.get(frame.rows.len())?;
+            frame.rows.push(vec![]);
+            frame.in_item = true;
+            frame.item_marker = locator
+                .as_mut()
+                .map(|l| l.span(row.open, row.open + "[tr]".len()));
+        }
+    }
+
+    /// Assemble the segments found by `scan`, and their spans if given a
+    /// `Locator`.
+    ///
+    /// If given `text`, the index of every `[` left in text is added to it.
+    fn build(
+        &self,
+        mut locator: Option<Locator>,
+        mut text: Option<&mut Vec<u32>>,
+    ) -> (Vec<Segment<'a>>, Vec<Spans>) {
+        let mut stack = vec![Frame::new(None, 0, self.input.len())];
+        let mut idx = self.next_bracket[0] as usize;
+        let mut nodes = 0;
+        // Frames for the cells of tables, which are not coded segments.
+        let mut cells = 0;
+
+        loop {
+            // Depth of any coded segment beginning in this frame.
+            let depth = stack.len() - cells;
+            let frame = stack.last_mut().unwrap();
+            if frame.is_table {
+                if let Some(cell) = self.next_cell(frame, &mut locator) {
+                    let mut inner = Frame::new(frame.open, cell.body, cell.close);
+                    inner.cell = Some(cell.header);
+                    inner.open_tag = locator.as_mut().map(|l| l.span(cell.open, cell.body));
+                    stack.push(inner);
+                    cells += 1;
+                    idx = self.next_bracket[cell.body] as usize;
+                    continue;
+                }
+            } else if idx < self.brackets.len() && self.bracket(idx as u32) < frame.close {
+                let offset = self.bracket(idx as u32);
+                match self.matches[idx] {
+                    Some(ref m)
+                        if depth > self.parser.max_depth || nodes == self.parser.max_nodes =>
+                    {
+                        // Over a limit, so the whole thing remains text.
+                        idx = self.next_bracket[m.end] as usize;
+                    }
+                    Some(ref m) if self.is_leaf(m) => {
+                        nodes += 1;
+                        frame.text_until(self.input, offset, &mut locator);
+                        if let Some(ref mut locator) = locator {
+                            frame.spans.push(self.leaf_spans(m, offset, locator));
+                        }
+                        frame.segments.push(self.leaf(m));
+                        frame.text = m.end;
+                        idx = self.next_bracket[m.end] as usize;
+                    }
+                    Some(ref m) => {
+                        nodes += 1;
+                        frame.text_until(self.input, offset, &mut locator);
+                        let mut inner = Frame::new(Some(idx), m.body, m.close);
+                        inner.is_list = matches!(m.head, Head::List(_));
+                        if let Head::Table(_) = m.head {
+                            // There is only whitespace outside its cells.
+                            inner.is_table = true;
+                            inner.text = m.close;
+                        }
+                        inner.open_tag = locator.as_mut().map(|l| l.span(offset, m.body));
+                        stack.push(inner);
+                        idx = self.next_bracket[m.body] as usize;
+                    }
+                    None if frame.is_list && self.marks[idx] == Some(Mark::Item) => {
+                        frame.text_until(self.input, offset, &mut locator);
+                        frame.next_item(offset, true, &mut locator);
+                        frame.text = offset + "[*]".len();
+                        idx += 1;
+                    }
+                    None => {
+                        if let Some(ref mut text) = text {
+                            text.push(idx as u32);
+                        }
+                        idx += 1
+                    }
+                }
+                continue;
+            }
+
+            let mut frame = stack.pop().unwrap();
+            frame.text_until(self.input, frame.close, &mut locator);
+            if frame.is_list {
+                frame.next_item(frame.close, false, &mut locator);
+            }
+            if let Some(header) = frame.cell {
+                cells -= 1;
+                let table = stack.last_mut().unwrap();
+                if let Some(ref mut locator) = locator {
+                    let open = frame.open_tag.unwrap();
+                    let close = locator.span(frame.close, frame.close + "[/td]".len());
+                    table.spans.push(Spans {
+                        span: Span {
+                            start: open.start,
+                            end: close.end,
+                        },
+                        open: Some(open),
+                        close: Some(close),
+                        children: frame.spans,
+                    });
+                }
+                table.rows.last_mut().unwrap().push(TableCell {
+                    header,
+                    body: frame.segments,
+                });
+                continue;
+            }
+            let m = match frame.open {
+                None => return (frame.segments, frame.spans),
+                Some(i) => self.matches[i].as_ref().unwrap(),
+            };
+
+            let parent = stack.last_mut().unwrap();
+            if let Some(ref mut locator) = locator {
+                let open = frame.open_tag.unwrap();
+                let close = locator.span(m.close, m.end);
+                parent.spans.push(Spans {
+                    span: Span {
+                        start: open.start,
+                        end: close.end,
+                    },
+                    open: Some(open),
+                    close: Some(close),
+                    children: mem::take(if frame.is_list || frame.is_table {
+                        &mut frame.item_spans
+                    } else {
+                        &mut frame.spans
+                    }),
+                });
+            }
+            parent.segments.push(self.node(m, frame));
+            parent.text = m.end;
+            idx = self.next_bracket[m.end] as usize;
+        }
+    }
+
+    /// Explain why the tags at the given `[`s were left as text.
+    fn diagnose(&mut self, text: &[u32]) -> Vec<Diagnostic> {
+        // Closing tags of each kind, and the opening tag (if any) each one
+        // would have closed.
+        let mut closes = vec![vec![]; self.closers.len()];
+        let mut failures = vec![];
+        for &idx in text {
+            match self.marks[idx as usize] {
+                Some(Mark::Close(c)) if self.closes_enabled(c) => closes[c].push((idx, None)),
+                Some(_) => {}
+                None => failures.extend(self.failure(self.bracket(idx))),
+            }
+        }
+
+        // Pair each opening tag with the first closing tag after it that
+        // isn't already taken.
+        let mut next = vec![0; closes.len()];
+        for (i, failure) in failures.iter_mut().enumerate() {
+            let c = match failure.closer {
+                Some(c) => c,
+                None => continue,
+            };
+            let closes = &mut closes[c];
+            while next[c] < closes.len() && self.bracket(closes[next[c]].0) < failure.start {
+                next[c] += 1;
+            }
+            if let Some(close) = closes.get_mut(next[c]) {
+                close.1 = Some(i);
+                failure.related = Some(self.bracket(close.0));
+                next[c] += 1;
+            }
+        }
+
+        let mut found = vec![];
+        for failure in failures {
+            let related = failure.related.map(|r| {
+                (
+                    r,
+                    r + self.closers[failure.closer.unwrap()].len() + "[/]".len(),
+                )
+            });
+            let kind = failure.kind.unwrap_or(match related {
+                Some(_) => DiagnosticKind::Misnested,
+                None => DiagnosticKind::Unclosed,
+            });
+            found.push((kind, (failure.start, failure.end), related));
+        }
+        for (c, closes) in closes.iter().enumerate() {
+            for &(idx, _) in closes.iter().filter(|close| close.1.is_none()) {
+                let start = self.bracket(idx);
+                let end = start + self.closers[c].len() + "[/]".len();
+                found.push((DiagnosticKind::StrayClose, (start, end), None));
+            }
+        }
+        found.sort_by_key(|&(_, (start, _), _)| start);
+
+        // Find every position in order, so it takes linear time.
+        let mut offsets: Vec<usize> = found
+            .iter()
+            .flat_map(|&(_, (start, end), related)| {
+                let (r_start, r_end) = related.unwrap_or((start, end));
+                vec![start, end, r_start, r_end]
+            })
+            .collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        let mut locator = Locator::new(self.input);
+        let positions: Vec<Position> = offsets.iter().map(|&o| locator.locate(o)).collect();
+        let span = |(start, end): (usize, usize)| {
+            let at = |offset| positions[offsets.binary_search(&offset).unwrap()];
+            Span {
+                start: at(start),
+                end: at(end),
+            }
+        };
+
+        found
+            .into_iter()
+            .map(|(kind, tag, related)| Diagnostic {
+                kind,
+                span: span(tag),
+                related: related.map(span),
+            })
+            .collect()
+    }
+
+    /// Whether a closing tag belongs to any enabled tag.
+    fn closes_enabled(&self, closer: usize) -> bool {
+        match TAGS.get(closer) {
+            Some(&tag) if self.parser.is_enabled(tag) => true,
+            _ => self
+                .parser
+                .handlers
+                .iter()
+                .any(|h| h.name().eq_ignore_ascii_case(self.closers[closer])),
+        }
+    }
+
+    /// Recognize an opening tag at `offset` which was left as text.
+    fn failure(&mut self, offset: usize) -> Option<Failure> {
+        let s = &self.input[offset..];
+        let end = |rest: &str| offset + s.len() - rest.len();
+        let parser = self.parser;
+        let mut kind = None;
+
+        let (tag, closer, tag_end) = if let Ok((rest, (style, closer))) = decoration::head(s) {
+            (Some(Tag::from(style)), Some(closer), end(rest))
+        } else if starts_with_no_case(s, "[color=") {
+            kind = Some(DiagnosticKind::InvalidColor);
+            let tag_end = self.lookahead.find("]", offset)? + 1;
+            (Some(Tag::Color), Some("color"), tag_end)
+        } else if starts_with_no_case(s, "[size=") {
+            kind = Some(DiagnosticKind::InvalidSize);
+            let tag_end = self.lookahead.find("]", offset)? + 1;
+            (Some(Tag::Size), Some("size"), tag_end)
+        } else if let Ok((rest, _)) = code::head(s) {
+            (Some(Tag::Code), Some("code"), end(rest))
+        } else if let Ok((rest, _)) = image_head(s) {
+            (Some(Tag::Image), Some("img"), end(rest))
+        } else if let Ok((rest, _)) = list::listhead(s) {
+            let body = end(rest);
+            if self.stop(LIST, self.next_bracket[body]) != NONE {
+                kind = Some(DiagnosticKind::TextBeforeItem);
+            }
+            (Some(Tag::List), Some("list"), body)
+        } else if let Ok((rest, _)) = quote::qhead(s) {
+            (Some(Tag::Quote), Some("quote"), end(rest))
+        } else if let Ok((rest, level)) = heading::head(s) {
+            (Some(Tag::Heading), Some(heading::closer(level)), end(rest))
+        } else if let Ok((rest, _)) = heading::rule(s) {
+            (Some(Tag::Rule), None, end(rest))
+        } else if let Ok((rest, (closer, _))) = embed::head(s) {
+            if self
+                .lookahead
+                .find(&format!("[/{}]", closer), offset)
+                .is_some()
+            {
+                kind = Some(DiagnosticKind::UnknownMedia);
+            }
+            (Some(Tag::Embed), Some(closer), end(rest))
+        } else if let Ok((rest, closer)) = spoiler::head(s) {
+            let (_, tag_end) = self.title(end(rest))?;
+            (Some(Tag::Spoiler), Some(closer), tag_end)
+        } else if let Ok((rest, _)) = table::head(s) {
+            let body = end(rest);
+            if self.stop(TABLE, self.next_bracket[body]) != NONE {
+                kind = Some(DiagnosticKind::MalformedTable);
+            }
+            (Some(Tag::Table), Some("table"), body)
+        } else if let Ok((rest, _)) = url::bare_head(s) {
+            (Some(Tag::Url), Some("url"), end(rest))
+        } else if let Ok((rest, _)) = url::unquoted_head(s) {
+            // Either form of `[url=...]`; a quoted target ends at `"]`, and
+            // any other at the first `]`.
+            let target = end(rest);
+            let tag_end = match self.lookahead.find("\"]", target) {
+                Some(quote) if url::quoted_head(s).is_ok() => quote + 2,
+                _ => self.lookahead.find("]", target)? + 1,
+            };
+            (Some(Tag::Url), Some("url"), tag_end)
+        } else {
+            if parser.handlers.is_empty() {
+                return None;
+            }
+            let (name, attrs, tag_end) = custom::head(self.input, offset, &mut self.lookahead)?;
+            let handler = parser.handler(name)?;
+            if !handler.accepts(&attrs) {
+                kind = Some(DiagnosticKind::RejectedAttributes);
+            }
+            let closer = match handler.body() {
+                Body::Empty => None,
+                _ => Some(handler.name()),
+            };
+            (None, closer, tag_end)
+        };
+
+        if tag.is_some_and(|tag| !parser.is_enabled(tag)) || (kind.is_none() && closer.is_none()) {
+            return None;
+        }
+        Some(Failure {
+            kind,
+            start: offset,
+            end: tag_end,
+            closer: closer.and_then(|name| self.closer(name)),
+            related: None,
+        })
+    }
+
+    /// Determine whether the input at a `[` is a closing tag or item marker.
+    fn mark(&self, offset: usize) -> Option<Mark> {
+        let bytes = &self.input.as_bytes()[offset..];
+        if bytes.starts_with(b"[*]") {
+            return Some(Mark::Item);
+        }
+        if self.parser.is_enabled(Tag::Table) {
+            let s = &self.input[offset..];
+            if starts_with_no_case(s, "[tr]") {
+                return Some(Mark::Row);
+            } else if starts_with_no_case(s, "[td]") {
+                return Some(Mark::Cell(false));
+            } else if starts_with_no_case(s, "[th]") {
+                return Some(Mark::Cell(true));
+            }
+        }
+        if !bytes.starts_with(b"[/") {
+            return None;
+        }
+
+        let name_len = bytes[2..]
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric())
+            .count();
+        if bytes.get(2 + name_len) != Some(&b']') {
+            return None;
+        }
+        let name = &self.input[offset + 2..offset + 2 + name_len];
+        self.closer(name).map(Mark::Close)
+    }
+
+    /// Whether a coded segment has a raw body (or none), rather than a
+    /// sequence of segments.
+    fn is_leaf(&self, m: &Match) -> bool {
+        match m.head {
+            Head::Code | Head::Image | Head::BareLink | Head::Rule | Head::Embed { .. } => true,
+            Head::Custom { handler, .. } => self.parser.handlers[handler].body() != Body::Nested,
+            _ => false,
+        }
+    }
+
+    /// Construct the segment for a coded segment with a raw body.
+    fn leaf(&self, m: &Match<'a>) -> Segment<'a> {
+        let body = &self.input[m.body..m.close];
+        match m.head {
+            Head::Code => Segment::Code(body.into()),
+            Head::Image => Segment::Image { src: body.into() },
+            Head::Rule => Segment::Rule,
+            Head::Embed {
+                ref provider,
+                ref media,
+            } => Segment::Embed {
+                provider: provider.clone().into(),
+                id: media.id.clone().into(),
+                params: media
+                    .params
+                    .iter()
+                    .map(|(n, v)| (n.clone().into(), v.clone().into()))
+                    .collect(),
+            },
+            Head::BareLink => Segment::Link {
+                target: body.into(),
+                text: vec![Segment::Text(body.into())],
+            },
+            Head::Custom {
+                name, ref attrs, ..
+            } => Segment::Custom {
+                name: name.into(),
+                attrs: attrs.iter().map(|&(n, v)| (n.into(), v.into())).collect(),
+                body: if body.is_empty() {
+                    vec![]
+                } else {
+                    vec![Segment::Text(body.into())]
+                },
+            },
+            _ => unreachable!("{:?} does not have a raw body", m.head),
+        }
+    }
+
+    /// Find the spans of a coded segment with a raw body, beginning at
+    /// `offset`.
+    fn leaf_spans(&self, m: &Match<'a>, offset: usize, locator: &mut Locator) -> Spans {
+        let open = locator.span(offset, m.body);
+        let body = locator.span(m.body, m.close);
+        let close = if m.end > m.close {
+            Some(locator.span(m.close, m.end))
+        } else {
+            None
+        };
+        let has_text = match m.head {
+            Head::BareLink => true,
+            Head::Custom { .. } => m.close > m.body,
+            _ => false,
+        };
+        Spans {
+            span: Span {
+                start: open.start,
+                end: close.unwrap_or(body).end,
+            },
+            open: Some(open),
+            close,
+            children: if has_text {
+                vec![Spans {
+                    span: body,
+                    open: None,
+                    close: None,
+                    children: vec![],
+                }]
+            } else {
+                vec![]
+            },
+        }
+    }
+
+    /// Construct the segment for a coded segment with a nested body.
+    fn node(&self, m: &Match<'a>, frame: Frame<'a>) -> Segment<'a> {
+        match m.head {
+            Head::Decoration(style) => Segment::Decorated {
+                style,
+                text: frame.segments,
+            },
+            Head::Quote(attribution) => Segment::Quote {
+                attribution: attribution.map(Cow::Borrowed),
+                body: frame.segments,
+            },
+            Head::Link(target) => Segment::Link {
+                target: target.into(),
+                text: frame.segments,
+            },
+            Head::Heading(level) => Segment::Heading {
+                level,
+                text: frame.segments,
+            },
+            Head::Spoiler(title) => Segment::Spoiler {
+                title: title.map(Cow::Borrowed),
+                body: frame.segments,
+            },
+            Head::List(style) => Segment::List {
+                style,
+                items: frame.items,
+            },
+            Head::Table(_) => Segment::Table { rows: frame.rows },
+            Head::Custom {
+                name, ref attrs, ..
+            } => Segment::Custom {
+                name: name.into(),
+                attrs: attrs.iter().map(|&(n, v)| (n.into(), v.into())).collect(),
+                body: frame.segments,
+            },
+            _ => unreachable!("{:?} does not have a nested body", m.head),
+        }
+    }
+}
+
+/// An opening tag left as text, found by `Scanner::diagnose`.
+struct Failure {
+    /// What went wrong, if it is not down to the closing tag.
+    kind: Option<DiagnosticKind>,
+    start: usize,
+    end: usize,
+    /// The kind of closing tag that would close it, if it has one.
+    closer: Option<usize>,
+    /// Offset of the closing tag it would be closed by, if any.
+    related: Option<usize>,
+}
+
+/// The body of a coded segment under construction.
+struct Frame<'a> {
+    /// Index of the `[` where the coded segment begins; `None` at top level.
+    open: Option<usize>,
+    is_list: bool,
+    is_table: bool,
+    /// Whether this holds a cell of the table at `open`, and if so whether
+    /// it is a header cell.
+    cell: Option<bool>,
+    /// Whether `segments` holds the contents of a list item, or the last of
+    /// `rows` is incomplete.
+    in_item: bool,
+    segments: Vec<Segment<'a>>,
+    /// Complete items, if this is a list.
+    items: Vec<Vec<Segment<'a>>>,
+    /// Rows of cells, if this is a table.
+    rows: Vec<Vec<TableCell<'a>>>,
+    /// Offset of the start of any text not yet added to `segments`.
+    text: usize,
+    /// Offset of the end of the body.
+    close: usize,
+    /// Spans of `segments`, if wanted.
+    spans: Vec<Spans>,
+    /// Spans of complete `items`, or `rows`.
+    item_spans: Vec<Spans>,
+    /// The opening tag, if spans are wanted.
+    open_tag: Option<Span>,
+    /// The marker of the current list item, or opening tag of the current
+    /// row, if spans are wanted.
+    item_marker: Option<Span>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(open: Option<usize>, body: usize, close: usize) -> Self {
+        Frame {
+            open,
+            is_list: false,
+            is_table: false,
+            cell: None,
+            in_item: false,
+            segments: vec![],
+            items: vec![],
+            rows: vec![],
+            text: body,
+            close,
+            spans: vec![],
+            item_spans: vec![],
+            open_tag: None,
+            item_marker: None,
+        }
+    }
+
+    /// Emit any text from the last point up to `end`.
+    fn text_until(&mut self, input: &'a str, end: usize, locator: &mut Option<Locator>) {
+        if end > self.text {
+            self.segments
+                .push(Segment::Text(input[self.text..end].into()));
+            if let Some(ref mut locator) = *locator {
+                self.spans.push(Spans {
+                    span: locator.span(self.text, end),
+                    open: None,
+                    close: None,
+                    children: vec![],
+                });
+            }
+        }
+        self.text = end;
+    }
+
+    /// Complete the current list item (if any) where the next one or the end
+    /// of the list begins at `offset`, and start a new one if `marker`.
+    fn next_item(&mut self, offset: usize, marker: bool, locator: &mut Option<Locator>) {
+        let end = locator.as_mut().map(|l| l.locate(offset));
+        if self.in_item {
+            self.items.push(mem::take(&mut self.segments));
+            if let (Some(marker), Some(end)) = (self.item_marker, end) {
+                self.item_spans.push(Spans {
+                    span: Span {
+                        start: marker.start,
+                        end,
+                    },
+                    open: Some(marker),
+                    close: None,
+                    children: mem::take(&mut self.spans),
+                });
+            }
+        }
+        self.in_item = marker;
+        if marker {
+            self.item_marker = locator
+                .as_mut()
+                .map(|l| l.span(offset, offset + "[*]".len()));
+        }
+    }
+}
+
+/// Lazily-built tables of where strings next occur in the input.
+///
+/// Searching from scratch for the end of every candidate tag could take
+/// quadratic time, so each string's table is built once in linear time and
+/// looked up after that.
+pub struct Lookahead<'a> {
+    input: &'a str,
+    tables: Vec<(String, Vec<u32>)>,
+}
+
+impl<'a> Lookahead<'a> {
+    fn new(input: &'a str) -> Self {
+        Lookahead {
+            input,
+            tables: vec![],
+        }
+    }
+
+    /// Find the first occurrence of `needle` (ignoring ASCII case) at or
+    /// after `from`.
+    pub fn find(&mut self, needle: &str, from: usize) -> Option<usize> {
+        let table = match self.tables.iter().position(|(n, _)| n == needle) {
+            Some(i) => &self.tables[i].1,
+            None => {
+                let table = Self::build(self.input.as_bytes(), needle.as_bytes());
+                self.tables.push((needle.to_owned(), table));
+                &self.tables.last().unwrap().1
+            }
+        };
+
+        match table[from] {
+            NONE => None,
+            i => Some(i as usize),
+        }
+    }
+
+    fn build(haystack: &[u8], needle: &[u8]) -> Vec<u32> {
+        let mut table = vec![NONE; haystack.len() + 1];
+        for i in (0..haystack.len()).rev() {
+            let here = haystack[i..].len() >= needle.len()
+                && haystack[i..i + needle.len()].eq_ignore_ascii_case(needle);
+            table[i] = if here { i as u32 } else { table[i + 1] };
+        }
+        table
+    }
+}
+
+fn starts_with_no_case(s: &str, prefix: &str) -> bool {
+    s.len() >= prefix.len() && s.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+// Recognizes `[img]`; the body is the image source up to `[/img]`.
+named!(image_head(&str) -> &str, tag_no_case!("[img]"));
+
+#[test]
+fn disabled_tags_are_text() {
+    let parser = Parser::new().disable(Tag::Italic).disable(Tag::Url);
+    assert_eq!(
+        parser.parse("[b][i]x[/b][/i] [url]y[/url]"),
+        vec![
+            Segment::Decorated {
+                style: DecorationStyle::Bold,
+                text: vec![Segment::Text("[i]x".into())],
+            },
+            Segment::Text("[/i] [url]y[/url]".into()),
+        ]
+    );
+    assert_eq!(
+        parser.enable(Tag::Url).parse("[url]y[/url]"),
+        vec![Segment::Link {
+            target: "y".into(),
+            text: vec![Segment::Text("y".into())],
+        }]
+    );
+}
+
+#[test]
+fn depth_limit() {
+    let parser = Parser::new().max_depth(2);
+    assert_eq!(
+        parser.parse("[list][*][b][i]x[/i][/b][/list]"),
+        vec![Segment::List {
+            style: ListStyle::Unordered,
+            items: vec![vec![Segment::Decorated {
+                style: DecorationStyle::Bold,
+                text: vec![Segment::Text("[i]x[/i]".into())],
+            }]],
+        }]
+    );
+    assert_eq!(
+        Parser::new().max_depth(0).parse("a[b]b[/b]c"),
+        vec![Segment::Text("a[b]b[/b]c".into())]
+    );
+}
+
+#[test]
+fn default_depth_limit() {
+    let n = 300_000;
+    let input = "[b]".repeat(n) + &"[/b]".repeat(n);
+    let segments = parse(&input);
+    let mut depth = 0;
+    let mut level = &segments;
+    while let [Segment::Decorated { ref text, .. }] = level[..] {
+        depth += 1;
+        level = text;
+    }
+    assert_eq!(depth, DEFAULT_MAX_DEPTH);
+    let rest = n - depth;
+    let text = "[b]".repeat(rest) + &"[/b]".repeat(rest);
+    assert_eq!(level[..], [Segment::Text(text.into())]);
+}
+
+#[test]
+fn node_limit() {
+    let parser = Parser::new().max_nodes(2);
+    assert_eq!(
+        parser.parse("[b][i]x[/i][u]y[/u][/b][code]z[/code]"),
+        vec![
+            Segment::Decorated {
+                style: DecorationStyle::Bold,
+                text: vec![
+                    Segment::Decorated {
+                        style: DecorationStyle::Italic,
+                        text: vec![Segment::Text("x".into())],
+                    },
+                    Segment::Text("[u]y[/u]".into()),
+                ],
+            },
+            Segment::Text("[code]z[/code]".into()),
+        ]
+    );
+}
@@ -1,4 +1,22 @@
-use super::{DecorationStyle, ListStyle, Segment};
+use std::borrow::Cow;
+
+use super::toc::{self, Anchors};
+use super::{
+    DecorationStyle, ListStyle, OwnedSegment, Providers, RejectedUrl, Segment, TableCell, UrlPolicy,
+};
+
+mod ansi;
+mod bbcode;
+mod latex;
+mod lines;
+mod markdown;
+mod plain;
+
+pub use self::ansi::{Ansi, ColorDepth};
+pub use self::bbcode::{BbCode, ColorFormat};
+pub use self::latex::Latex;
+pub use self::markdown::Markdown;
+pub use self::plain::PlainText;
 
 pub type Result<E> = std::result::Result<(), E>;
 
@@ -7,44 +25,68 @@ pub trait Renderer {
 
     fn render(&mut self, segments: &Vec<super::Segment>) -> Result<Self::Err> {
         for segment in segments {
-            match segment {
-                Segment::Text(s) => self.text(s)?,
-                Segment::Decorated {
-                    style,
-                    text: segments,
-                } => {
-                    self.decoration_begin(*style)?;
-                    self.render(&segments)?;
-                    self.decoration_end(*style)?
-                }
-                Segment::Quote {
-                    attribution,
-                    body: segments,
-                } => {
-                    self.quote_begin(attribution)?;
-                    self.render(&segments)?;
-                    self.quote_end(attribution)?
-                }
-                Segment::Code(s) => self.code(s)?,
-                Segment::List { style, items } => {
-                    self.list_begin(*style)?;
-                    for item in items {
-                        self.list_item_begin(*style)?;
-                        self.render(item)?;
-                        self.list_item_end(*style)?;
-                    }
-                    self.list_end(*style)?
-                }
-                Segment::Link {
-                    target,
-                    text: segments,
-                } => {
-                    self.link_begin(target)?;
-                    self.render(segments)?;
-                    self.link_end(target)?
+            self.render_segment(segment)?;
+        }
+
+        Ok(())
+    }
+
+    /// Output a single segment.
+    fn render_segment(&mut self, segment: &Segment) -> Result<Self::Err> {
+        match segment {
+            Segment::Text(s) => self.text(s)?,
+            Segment::Decorated {
+                style,
+                text: segments,
+            } => {
+                self.decoration_begin(*style)?;
+                self.render(segments)?;
+                self.decoration_end(*style)?
+            }
+            Segment::Quote {
+                attribution,
+                body: segments,
+            } => {
+                self.quote_begin(&attribution.as_deref())?;
+                self.render(segments)?;
+                self.quote_end(&attribution.as_deref())?
+            }
+            Segment::Code(s) => self.code(s)?,
+            Segment::List { style, items } => {
+                self.list_begin(*style)?;
+                for item in items {
+                    self.list_item_begin(*style)?;
+                    self.render(item)?;
+                    self.list_item_end(*style)?;
                 }
-                Segment::Image { src } => self.image(src)?,
+                self.list_end(*style)?
+            }
+            Segment::Link {
+                target,
+                text: segments,
+            } => {
+                self.link_begin(target)?;
+                self.render(segments)?;
+                self.link_end(target)?
             }
+            Segment::Image { src } => self.image(src)?,
+            Segment::Custom { name, attrs, body } => self.custom(name, attrs, body)?,
+            Segment::Heading {
+                level,
+                text: segments,
+            } => {
+                self.heading_begin(*level)?;
+                self.render(segments)?;
+                self.heading_end(*level)?
+            }
+            Segment::Rule => self.rule()?,
+            Segment::Embed {
+                provider,
+                id,
+                params,
+            } => self.embed(provider, id, params)?,
+            Segment::Spoiler { title, body } => self.spoiler(title.as_deref(), body)?,
+            Segment::Table { rows } => self.table(rows)?,
         }
 
         Ok(())
@@ -75,18 +117,170 @@ pub trait Renderer {
     /// Output the end of a link.
     fn link_end(&mut self, target: &str) -> Result<Self::Err>;
     fn image(&mut self, src: &str) -> Result<Self::Err>;
+    /// Output a custom tag with the given body.
+    ///
+    /// By default custom tags are transparent, rendering only their bodies.
+    fn custom(
+        &mut self,
+        _name: &str,
+        _attrs: &[(Cow<str>, Cow<str>)],
+        body: &Vec<Segment>,
+    ) -> Result<Self::Err> {
+        self.render(body)
+    }
+    /// Output the beginning of a heading of `level`, from 1 to 6.
+    ///
+    /// By default headings are rendered as their text alone.
+    fn heading_begin(&mut self, _level: u8) -> Result<Self::Err> {
+        Ok(())
+    }
+    /// Output the end of a heading.
+    fn heading_end(&mut self, _level: u8) -> Result<Self::Err> {
+        Ok(())
+    }
+    /// Output a horizontal rule, which is left out by default.
+    fn rule(&mut self) -> Result<Self::Err> {
+        Ok(())
+    }
+    /// Output media embedded from another site, which is left out by
+    /// default.
+    fn embed(
+        &mut self,
+        _provider: &str,
+        _id: &str,
+        _params: &[(Cow<str>, Cow<str>)],
+    ) -> Result<Self::Err> {
+        Ok(())
+    }
+    /// Output content hidden until the reader chooses to see it.
+    ///
+    /// By default spoilers are transparent, rendering only their bodies.
+    fn spoiler(&mut self, _title: Option<&str>, body: &Vec<Segment>) -> Result<Self::Err> {
+        self.render(body)
+    }
+    /// Output a table of rows of cells.
+    ///
+    /// By default each row is written as a line of its cells, separated by
+    /// spaces.
+    fn table(&mut self, rows: &[Vec<TableCell>]) -> Result<Self::Err> {
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
+                self.text("\n")?;
+            }
+            for (j, cell) in row.iter().enumerate() {
+                if j > 0 {
+                    self.text(" ")?;
+                }
+                self.render(&cell.body)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How spoilers are written by renderers whose output can't hide them until
+/// the reader chooses to see them.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Redaction {
+    /// Leave spoilers out entirely.
+    Blank,
+    /// Write `[spoiler: Title]`, or `[spoiler]` if untitled, in place of each
+    /// spoiler.
+    Placeholder,
+    /// Write the contents of spoilers as if they weren't hidden, without
+    /// their titles.
+    Keep,
 }
 
+/// The text written in place of a spoiler by `Redaction::Placeholder`.
+fn spoiler_placeholder(title: Option<&str>) -> String {
+    match title {
+        Some(title) if !title.is_empty() => format!("[spoiler: {}]", title),
+        _ => "[spoiler]".to_string(),
+    }
+}
+
+/// A link to the page of some embedded media, for outputs that can't embed
+/// it, or `None` if the provider is unknown.
+fn embed_link(
+    providers: &Providers,
+    provider: &str,
+    id: &str,
+    params: &[(Cow<str>, Cow<str>)],
+) -> Option<OwnedSegment> {
+    let page = providers.get(provider)?.page(id, params);
+    Some(Segment::Link {
+        target: page.clone().into(),
+        text: vec![Segment::Text(page.into())],
+    })
+}
+
+/// Renders segments as HTML.
+///
+/// No input produces markup that can run script: text and attribute values
+/// are always escaped, only a fixed set of tags and attributes is ever
+/// written, and the URLs of links and images must be allowed by a
+/// `UrlPolicy`, which is `UrlPolicy::default()` unless another is given.
+///
+/// Headings are given an `id` by `Anchors`, so they can be linked to from a
+/// table of contents made by `toc` from the same segments. Anchors are
+/// unique across everything rendered with one renderer.
+///
+/// Embedded media is written as a frame holding the player given by its
+/// provider in `Providers`. The built-in providers give players that don't
+/// track viewers until they play the media. If the URL policy rejects a
+/// player, the media is written as a link to its page instead, and media
+/// from unknown providers is left out.
+///
+/// Spoilers are `details` elements, which are collapsed until opened,
+/// summarized by their titles or else by "Spoiler".
+///
+/// Tables are `table` elements, with `th` elements for header cells.
 pub struct SimpleHtml<O>
 where
     O: std::io::Write,
 {
     out: O,
+    urls: UrlPolicy,
+    anchors: Anchors,
+    providers: Providers,
 }
 
 impl<O: std::io::Write> SimpleHtml<O> {
     pub fn new(out: O) -> Self {
-        Self { out }
+        Self {
+            out,
+            urls: UrlPolicy::default(),
+            anchors: Anchors::new(),
+            providers: Providers::new(),
+        }
+    }
+
+    /// Write embedded media with providers, which are `Providers::new()` by
+    /// default.
+    pub fn providers(mut self, providers: Providers) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    /// Check the URLs of links and images with policy.
+    ///
+    /// Policies that allow URLs like `javascript:` make output unsafe.
+    pub fn url_policy(mut self, policy: UrlPolicy) -> Self {
+        self.urls = policy;
+        self
+    }
+
+    /// The URL to write for url, or `None` if the policy rejects it and
+    /// provides no placeholder.
+    fn allowed_url<'u>(&self, url: &'u str) -> Option<&'u str> {
+        if self.urls.allows(url) {
+            return Some(url);
+        }
+        match self.urls.rejection() {
+            RejectedUrl::Placeholder(url) => Some(url),
+            RejectedUrl::Drop | RejectedUrl::Text => None,
+        }
     }
 
     /// Write s to output, replacing each character in escapes with the corresponding
@@ -103,12 +297,7 @@ impl<O: std::io::Write> SimpleHtml<O> {
         debug_assert_eq!(escapes.len(), replacements.len());
         debug_assert!(escapes.iter().all(|c| c.len_utf8() == 1));
 
-        loop {
-            let split = match s.find(escapes) {
-                Some(i) => i,
-                None => break,
-            };
-
+        while let Some(split) = s.find(escapes) {
             let (head, tail) = s.split_at(split);
             // tail is inclusive of the split point and all of the matched
             // chars are one byte in UTF-8, so taking the first byte here
@@ -128,14 +317,48 @@ impl<O: std::io::Write> SimpleHtml<O> {
         // Write remaining data past all replaced entities
         write!(self.out, "{}", s)
     }
+
+    /// Write s as the value of a double-quoted attribute.
+    fn attribute(&mut self, s: &str) -> IoResult<()> {
+        self.write_escaped(
+            s,
+            &['&', '<', '>', '"'],
+            &["&amp;", "&lt;", "&gt;", "&quot;"],
+        )
+    }
 }
 
 use std::io::Result as IoResult;
 
+/// The level of an HTML heading element for a heading of `level`.
+fn heading_level(level: u8) -> u8 {
+    level.clamp(1, 6)
+}
+
 impl<O: std::io::Write> Renderer for SimpleHtml<O> {
     type Err = std::io::Error;
 
-    fn text(&mut self, mut s: &str) -> IoResult<()> {
+    fn render(&mut self, segments: &Vec<Segment>) -> IoResult<()> {
+        for segment in segments {
+            match *segment {
+                Segment::Link { ref target, .. }
+                    if self.urls.rejection() == RejectedUrl::Drop && !self.urls.allows(target) => {}
+                Segment::Heading { level, ref text } => {
+                    let anchor = self.anchors.anchor(&toc::title(text));
+                    write!(self.out, "<h{} id=\"", heading_level(level))?;
+                    self.attribute(&anchor)?;
+                    write!(self.out, "\">")?;
+                    self.render(text)?;
+                    self.heading_end(level)?;
+                }
+                _ => self.render_segment(segment)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn text(&mut self, s: &str) -> IoResult<()> {
         // Escape tags and entities, also replace newlines with explicit
         // line breaks.
         self.write_escaped(
@@ -161,8 +384,12 @@ impl<O: std::io::Write> Renderer for SimpleHtml<O> {
                 )
             }
             Size(s) => {
-                return write!(self.out, r#"<span style="font-size: {}>"#, s);
+                return write!(self.out, r#"<span style="font-size: {}px">"#, s);
             }
+            Subscript => "sub",
+            Superscript => "sup",
+            Strikethrough => "s",
+            Monospace => r#"span style="font-family: monospace""#,
         };
         write!(self.out, "<{}>", tag)
     }
@@ -175,20 +402,25 @@ impl<O: std::io::Write> Renderer for SimpleHtml<O> {
             Italic => "i",
             Underline => "u",
             Center => "div",
-            Color(..) | Size(..) => "span",
+            Color(..) | Size(..) | Monospace => "span",
+            Subscript => "sub",
+            Superscript => "sup",
+            Strikethrough => "s",
         };
-        write!(self.out, "<{}>", tag)
+        write!(self.out, "</{}>", tag)
     }
 
     fn quote_begin(&mut self, attribution: &Option<&str>) -> IoResult<()> {
         if let Some(orig) = attribution {
-            write!(self.out, "<div>{} wrote:</div><div>", orig)
+            write!(self.out, "<div>")?;
+            self.text(orig)?;
+            write!(self.out, " wrote:</div><div>")
         } else {
             write!(self.out, "<div>Quote:</div><div>")
         }
     }
 
-    fn quote_end(&mut self, attribution: &Option<&str>) -> IoResult<()> {
+    fn quote_end(&mut self, _attribution: &Option<&str>) -> IoResult<()> {
         write!(self.out, "</div>")
     }
 
@@ -199,32 +431,278 @@ impl<O: std::io::Write> Renderer for SimpleHtml<O> {
     }
 
     fn list_begin(&mut self, style: ListStyle) -> IoResult<()> {
-        unimplemented!();
+        match style {
+            ListStyle::Unordered => write!(self.out, "<ul>"),
+            ListStyle::Numeric => write!(self.out, "<ol>"),
+            ListStyle::Alphabetic => write!(self.out, r#"<ol type="a">"#),
+        }
     }
 
-    fn list_item_begin(&mut self, style: ListStyle) -> IoResult<()> {
-        unimplemented!();
+    fn list_item_begin(&mut self, _style: ListStyle) -> IoResult<()> {
+        write!(self.out, "<li>")
     }
 
-    fn list_item_end(&mut self, style: ListStyle) -> IoResult<()> {
-        unimplemented!();
+    fn list_item_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        write!(self.out, "</li>")
     }
 
     fn list_end(&mut self, style: ListStyle) -> IoResult<()> {
-        unimplemented!();
+        match style {
+            ListStyle::Unordered => write!(self.out, "</ul>"),
+            ListStyle::Numeric | ListStyle::Alphabetic => write!(self.out, "</ol>"),
+        }
     }
 
     fn link_begin(&mut self, target: &str) -> IoResult<()> {
-        unimplemented!();
+        let target = match self.allowed_url(target) {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        write!(self.out, "<a href=\"")?;
+        self.attribute(target)?;
+        write!(self.out, "\">")
     }
 
     fn link_end(&mut self, target: &str) -> IoResult<()> {
-        unimplemented!();
+        if self.allowed_url(target).is_none() {
+            return Ok(());
+        }
+        write!(self.out, "</a>")
+    }
+
+    fn heading_begin(&mut self, level: u8) -> IoResult<()> {
+        write!(self.out, "<h{}>", heading_level(level))
+    }
+
+    fn heading_end(&mut self, level: u8) -> IoResult<()> {
+        write!(self.out, "</h{}>", heading_level(level))
+    }
+
+    fn rule(&mut self) -> IoResult<()> {
+        write!(self.out, "<hr>")
     }
 
     fn image(&mut self, src: &str) -> IoResult<()> {
+        let src = match self.allowed_url(src) {
+            Some(src) => src,
+            None if self.urls.rejection() == RejectedUrl::Text => return self.text(src),
+            None => return Ok(()),
+        };
         write!(self.out, "<img src=\"")?;
-        self.write_escaped(src, &['<', '>', '"'], &["&lt;", "&gt;", "&quot;"])?;
-        write!(self.out, ">")
+        self.attribute(src)?;
+        write!(self.out, "\">")
+    }
+
+    fn embed(&mut self, provider: &str, id: &str, params: &[(Cow<str>, Cow<str>)]) -> IoResult<()> {
+        let player = match self.providers.get(provider) {
+            Some(provider) => provider.player(id, params),
+            None => return Ok(()),
+        };
+        if !self.urls.allows(&player) {
+            let link = embed_link(&self.providers, provider, id, params);
+            return self.render(&link.into_iter().collect());
+        }
+        write!(self.out, "<iframe src=\"")?;
+        self.attribute(&player)?;
+        write!(self.out, "\" loading=\"lazy\" allowfullscreen></iframe>")
+    }
+
+    fn spoiler(&mut self, title: Option<&str>, body: &Vec<Segment>) -> IoResult<()> {
+        write!(self.out, "<details><summary>")?;
+        self.text(title.filter(|t| !t.is_empty()).unwrap_or("Spoiler"))?;
+        write!(self.out, "</summary>")?;
+        self.render(body)?;
+        write!(self.out, "</details>")
+    }
+
+    fn table(&mut self, rows: &[Vec<TableCell>]) -> IoResult<()> {
+        write!(self.out, "<table>")?;
+        for row in rows {
+            write!(self.out, "<tr>")?;
+            for cell in row {
+                let tag = if cell.header { "th" } else { "td" };
+                write!(self.out, "<{}>", tag)?;
+                self.render(&cell.body)?;
+                write!(self.out, "</{}>", tag)?;
+            }
+            write!(self.out, "</tr>")?;
+        }
+        write!(self.out, "</table>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse, toc, Providers, UrlPolicy};
+    use super::{Renderer, SimpleHtml};
+
+    fn html(input: &str) -> String {
+        let mut out = vec![];
+        SimpleHtml::new(&mut out).render(&parse(input)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Check that tags in html are balanced and properly nested, attribute
+    /// values are quoted, and `&` only begins entities.
+    fn assert_well_formed(html: &str) {
+        const ENTITIES: [&str; 4] = ["&amp;", "&lt;", "&gt;", "&quot;"];
+        let mut open = vec![];
+        let mut rest = html;
+        while let Some(i) = rest.find(&['<', '>', '&'][..]) {
+            let tail = &rest[i..];
+            if let Some(after) = tail.strip_prefix('&') {
+                let entity = ENTITIES.iter().find(|e| tail.starts_with(*e));
+                assert!(entity.is_some(), "bare & in {:?}", html);
+                rest = after;
+                continue;
+            }
+            assert!(tail.starts_with('<'), "bare > in {:?}", html);
+            let end = tail.find('>').expect("unterminated tag");
+            let tag = &tail[1..end];
+            assert!(!tag.contains('<'), "< in tag {:?} of {:?}", tag, html);
+            assert_eq!(tag.matches('"').count() % 2, 0, "{:?}", html);
+            let name = tag.split(' ').next().unwrap();
+            if let Some(name) = name.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name), "{:?}", html);
+            } else if name != "br" && name != "hr" && name != "img" {
+                open.push(name);
+            }
+            rest = &tail[end + 1..];
+        }
+        assert_eq!(open, Vec::<&str>::new(), "unclosed tags in {:?}", html);
+    }
+
+    #[test]
+    fn decorations() {
+        assert_eq!(
+            html("[b]a[/b][i]b[/i][u]c[/u][center]d[/center]"),
+            r#"<b>a</b><i>b</i><u>c</u><div style="text-align:center">d</div>"#
+        );
+        assert_eq!(
+            html("[color=#f00]a[/color][size=10]b[/size]"),
+            r#"<span style="color: #ff0000">a</span><span style="font-size: 10px">b</span>"#
+        );
+        assert_eq!(
+            html("x[sub]a[/sub][sup]b[/sup][s]c[/s][tt]d[/tt]"),
+            r#"x<sub>a</sub><sup>b</sup><s>c</s><span style="font-family: monospace">d</span>"#
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            html("[list][*]a[*]b[/list]"),
+            "<ul><li>a</li><li>b</li></ul>"
+        );
+        assert_eq!(html("[list=1][*]a[/list]"), "<ol><li>a</li></ol>");
+        assert_eq!(
+            html("[list=a][*][b]a[/b][/list]"),
+            r#"<ol type="a"><li><b>a</b></li></ol>"#
+        );
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            html("[url]http://example.com/?a&b[/url]"),
+            r#"<a href="http://example.com/?a&amp;b">http://example.com/?a&amp;b</a>"#
+        );
+        assert_eq!(
+            html(r#"[url="x"y"]a[/url][img]<">[/img]"#),
+            r#"<a href="x&quot;y">a</a><img src="&lt;&quot;&gt;">"#
+        );
+    }
+
+    #[test]
+    fn headings_and_rules() {
+        assert_eq!(
+            html("[h1]Hi there[/h1][hr][h2][b]Hi[/b] there![/h2]"),
+            r#"<h1 id="hi-there">Hi there</h1><hr><h2 id="hi-there-1"><b>Hi</b> there!</h2>"#
+        );
+
+        let segments = parse("[h1]A[/h1]x[quote][h3]B[/h3][/quote][h2]A[/h2]");
+        let mut out = vec![];
+        SimpleHtml::new(&mut out).render(&segments).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        for entry in toc(&segments) {
+            assert!(html.contains(&format!("id=\"{}\"", entry.anchor)));
+            for child in entry.children {
+                assert!(html.contains(&format!("id=\"{}\"", child.anchor)));
+            }
+        }
+    }
+
+    #[test]
+    fn embeds() {
+        assert_eq!(
+            html("[youtube]https://youtu.be/dQw4w9WgXcQ?t=42[/youtube]"),
+            "<iframe src=\"https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ?start=42\" \
+             loading=\"lazy\" allowfullscreen></iframe>"
+        );
+        assert_eq!(
+            html("[media=vimeo]76979871[/media]"),
+            "<iframe src=\"https://player.vimeo.com/video/76979871?dnt=1\" \
+             loading=\"lazy\" allowfullscreen></iframe>"
+        );
+
+        let segments = parse("[youtube]dQw4w9WgXcQ[/youtube]");
+        let mut out = vec![];
+        SimpleHtml::new(&mut out)
+            .url_policy(UrlPolicy::new().schemes(&["http"]))
+            .render(&segments)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+
+        let mut out = vec![];
+        SimpleHtml::new(&mut out)
+            .providers(Providers::empty())
+            .render(&segments)
+            .unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn spoilers() {
+        assert_eq!(
+            html("[spoiler]a[/spoiler][hide=\"<b>\"][b]c[/b][/hide][spoiler=]d[/spoiler]"),
+            "<details><summary>Spoiler</summary>a</details>\
+             <details><summary>&lt;b&gt;</summary><b>c</b></details>\
+             <details><summary>Spoiler</summary>d</details>"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            html("[table]\n[tr][th]a[/th][th][/th][/tr]\n[tr][td][b]b[/b][/td][td]<c>[/td][/tr]\n[/table]"),
+            "<table><tr><th>a</th><th></th></tr><tr><td><b>b</b></td><td>&lt;c&gt;</td></tr></table>"
+        );
+        assert_eq!(html("[table][/table]"), "<table></table>");
+    }
+
+    #[test]
+    fn every_segment_is_well_formed() {
+        let inputs = [
+            "plain & <simple> \"text\"\nwith lines",
+            "[b][i][u]a[/u][/i][/b][center]b[/center]",
+            "[color=red]a[/color][size=12]b[/size]",
+            "[sub]a[sup]b[/sup][/sub][strike]c[mono]d[/mono][/strike]",
+            "[quote]a[/quote][quote=\"<i>&\"]b[/quote]",
+            "[code]<b>&\n[/code]",
+            "[list][*]a[list=1][*]b[/list][*][list=a][*]c[/list][/list]",
+            "[url]a\"b[/url][url=\"<c>\"]d[/url][url=e&f]g[/url]",
+            "[img]\"><script>[/img]",
+            "[quote=\"x\"][list][*][url=y][b]z[/b][/url][/list][/quote]",
+            "[h1]<a>[/h1][hr][h6][i]\"&\"[/i][/h6]",
+            "[b][youtube]dQw4w9WgXcQ[/youtube][/b][media]vimeo.com/1/a#t=5[/media]",
+            "[spoiler=\"<i>\"][quote]a[/quote][/spoiler][hide][hide]b[/hide][/hide]",
+            "[table][tr][th]<a>[/th][/tr][tr][td][table][tr][td]b[/td][/tr][/table][/td][/tr][/table]",
+        ];
+        for input in &inputs {
+            assert_well_formed(&html(input));
+        }
     }
 }
@@ -0,0 +1,616 @@
+use super::super::{DecorationStyle, ListStyle, Providers, Segment};
+use super::lines::Lines;
+use super::plain::{item_marker, shift};
+use super::{embed_link, Renderer, Result};
+use palette::{Lab, Srgb};
+use std::borrow::Cow;
+use std::io::{Error, Result as IoResult, Write};
+
+/// Renders segments as text for a terminal, styled with ANSI escape codes.
+///
+/// Bold, italic, underlined and struck-through text is styled with SGR
+/// codes, and colored text is colored as closely as the terminal's
+/// `ColorDepth` allows. Subscripts and superscripts are written with Unicode
+/// forms of their digits as `PlainText` writes them, and sizes, centering and
+/// monospace are dropped. Styles never span lines, so output can be
+/// paged or shown a line at a time.
+///
+/// Quotes are written after a line naming who wrote them, with a gutter to
+/// their left. List items are marked with bullets, numbers or letters, and
+/// code is indented by four spaces. Links are OSC 8 hyperlinks, or written
+/// as `text (target)` without `Ansi::hyperlinks`, and images are written as
+/// `[image: src]`. Headings are bold lines of their own, underlined at the
+/// first level, and rules are dim lines. Embedded media is written as a link
+/// to its page.
+///
+/// Control characters in text are replaced with U+FFFD, so that input can't
+/// write escape codes of its own.
+///
+/// ```
+/// use bbcode::parse;
+/// use bbcode::render::{Ansi, Renderer};
+///
+/// let segments = parse("[b]Hi[/b] [color=red]there[/color]\n[quote]Bye[/quote]");
+/// let mut out = vec![];
+/// Ansi::new(&mut out).render(&segments).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "\x1b[1mHi\x1b[0m \x1b[38;2;255;0;0mthere\x1b[0m\n\x1b[2m\u{2502}\x1b[22m Bye\n"
+/// );
+/// ```
+pub struct Ansi<O: Write> {
+    out: O,
+    colors: ColorDepth,
+    hyperlinks: bool,
+    /// Decorations enclosing the current text, outermost first.
+    styles: Vec<DecorationStyle>,
+    /// The target of the enclosing hyperlink, if any.
+    link: Option<String>,
+    lines: Lines,
+    /// Text is written as it is, with its leading whitespace.
+    verbatim: bool,
+    providers: Providers,
+}
+
+/// How many colors a terminal can show.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum ColorDepth {
+    /// Any color, as 24-bit RGB.
+    TrueColor,
+    /// The 256 colors of xterm, of which the color cube and grays are used.
+    Palette256,
+    /// The 16 basic colors, assumed to look as they do in xterm.
+    Palette16,
+    /// No colors at all.
+    Monochrome,
+}
+
+/// The gutter to the left of quoted lines, a faint vertical bar.
+const GUTTER: &str = "\x1b[2m\u{2502}\x1b[22m ";
+
+/// The width of a rule, in columns.
+const RULE_WIDTH: usize = 40;
+
+/// The 16 basic colors as xterm shows them, normal then bright.
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The intensities of each component in the xterm color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl<O: Write> Ansi<O> {
+    pub fn new(out: O) -> Self {
+        Ansi {
+            out,
+            colors: ColorDepth::TrueColor,
+            hyperlinks: true,
+            styles: vec![],
+            link: None,
+            lines: Lines::new(),
+            verbatim: false,
+            providers: Providers::new(),
+        }
+    }
+
+    /// Choose how colors are written; `ColorDepth::TrueColor` by default.
+    pub fn colors(mut self, colors: ColorDepth) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Choose whether links are hyperlinks, which they are by default.
+    ///
+    /// Terminals that don't support OSC 8 hyperlinks usually show only
+    /// their text, hiding where they lead.
+    pub fn hyperlinks(mut self, hyperlinks: bool) -> Self {
+        self.hyperlinks = hyperlinks;
+        self
+    }
+
+    /// Find the pages of embedded media with providers, which are
+    /// `Providers::new()` by default.
+    pub fn providers(mut self, providers: Providers) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    /// The SGR parameters for a style, if it has any.
+    fn parameters(&self, style: DecorationStyle) -> Option<String> {
+        match style {
+            DecorationStyle::Bold => Some("1".to_string()),
+            DecorationStyle::Italic => Some("3".to_string()),
+            DecorationStyle::Underline => Some("4".to_string()),
+            DecorationStyle::Strikethrough => Some("9".to_string()),
+            DecorationStyle::Color(r, g, b) => color_parameters(self.colors, (r, g, b)),
+            DecorationStyle::Center
+            | DecorationStyle::Size(_)
+            | DecorationStyle::Subscript
+            | DecorationStyle::Superscript
+            | DecorationStyle::Monospace => None,
+        }
+    }
+
+    /// Write the styles of all enclosing decorations, if there are any.
+    fn write_styles(&mut self) -> IoResult<()> {
+        let parameters = self
+            .styles
+            .iter()
+            .filter_map(|&style| self.parameters(style))
+            .collect::<Vec<_>>();
+        if !parameters.is_empty() {
+            write!(self.out, "\x1b[{}m", parameters.join(";"))?;
+        }
+        Ok(())
+    }
+
+    /// Whether any enclosing decoration has written a style.
+    fn styled(&self) -> bool {
+        self.styles
+            .iter()
+            .any(|&style| self.parameters(style).is_some())
+    }
+
+    /// Write the prefix of the current line, then restore the styles and
+    /// hyperlink of the text.
+    fn start_line(&mut self, trailing_space: bool) -> IoResult<()> {
+        let prefix = self.lines.start(trailing_space);
+        self.out.write_all(prefix.as_bytes())?;
+
+        self.write_styles()?;
+        if let Some(ref target) = self.link {
+            write!(self.out, "\x1b]8;;{}\x1b\\", target)?;
+        }
+        Ok(())
+    }
+
+    /// End the current line, and the styles and hyperlink on it.
+    fn newline(&mut self) -> IoResult<()> {
+        if !self.lines.started {
+            self.start_line(false)?;
+        }
+        if self.link.is_some() {
+            self.out.write_all(b"\x1b]8;;\x1b\\")?;
+        }
+        if self.styled() {
+            self.out.write_all(b"\x1b[0m")?;
+        }
+        self.out.write_all(b"\n")?;
+        self.lines.started = false;
+        Ok(())
+    }
+
+    /// End the current line if anything has been written to it, as at the
+    /// start or end of a block.
+    fn break_line(&mut self) -> IoResult<()> {
+        if self.lines.started {
+            self.newline()?;
+        }
+        Ok(())
+    }
+
+    /// Write text which may span several lines.
+    fn write_text(&mut self, s: &str) -> IoResult<()> {
+        for (i, line) in self.lines.split(s).enumerate() {
+            if i > 0 {
+                self.newline()?;
+            }
+            self.write_line(line)?;
+        }
+        Ok(())
+    }
+
+    /// Write text within a line.
+    fn write_line(&mut self, mut line: &str) -> IoResult<()> {
+        if self.lines.trim && !self.verbatim {
+            line = line.trim_start();
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+        if !self.lines.started {
+            self.start_line(true)?;
+        }
+        let line = line
+            .chars()
+            .map(|c| {
+                if c.is_control() && c != '\t' {
+                    '\u{FFFD}'
+                } else {
+                    c
+                }
+            })
+            .collect::<String>();
+        self.out.write_all(line.as_bytes())?;
+        self.lines.trim = false;
+        Ok(())
+    }
+
+    /// Begin a block whose lines are prefixed.
+    fn push_prefix(&mut self, first: String, rest: String) -> IoResult<()> {
+        self.break_line()?;
+        self.lines.push_prefix(first, rest);
+        Ok(())
+    }
+
+    /// End a block begun by `push_prefix`.
+    fn pop_prefix(&mut self) -> IoResult<()> {
+        if self.lines.ends_line() {
+            self.newline()?;
+        }
+        self.lines.pop_prefix();
+        Ok(())
+    }
+}
+
+/// The SGR parameters that set the foreground to a color, if there are
+/// colors at all.
+fn color_parameters(colors: ColorDepth, color: (u8, u8, u8)) -> Option<String> {
+    match colors {
+        ColorDepth::TrueColor => Some(format!("38;2;{};{};{}", color.0, color.1, color.2)),
+        ColorDepth::Palette256 => {
+            let n = nearest((16..=255).map(|n| (n, palette_color(n))), color);
+            Some(format!("38;5;{}", n))
+        }
+        ColorDepth::Palette16 => {
+            let n = nearest((0..16).map(|n| (n, BASIC_COLORS[n as usize])), color);
+            Some(if n < 8 {
+                format!("3{}", n)
+            } else {
+                format!("9{}", n - 8)
+            })
+        }
+        ColorDepth::Monochrome => None,
+    }
+}
+
+/// The color of a palette entry from the xterm color cube or its grays.
+fn palette_color(n: u8) -> (u8, u8, u8) {
+    if n >= 232 {
+        let level = 8 + 10 * (n - 232);
+        return (level, level, level);
+    }
+    let n = (n - 16) as usize;
+    (
+        CUBE_LEVELS[n / 36],
+        CUBE_LEVELS[n / 6 % 6],
+        CUBE_LEVELS[n % 6],
+    )
+}
+
+/// The number of the candidate that looks most like a color.
+fn nearest<I>(candidates: I, color: (u8, u8, u8)) -> u8
+where
+    I: Iterator<Item = (u8, (u8, u8, u8))>,
+{
+    // Distance in CIELAB approximates how different colors look.
+    fn lab((r, g, b): (u8, u8, u8)) -> Lab {
+        Srgb::new(r, g, b).into_format::<f32>().into()
+    }
+    let target = lab(color);
+    let distance = |c| {
+        let c = lab(c);
+        (c.l - target.l).powi(2) + (c.a - target.a).powi(2) + (c.b - target.b).powi(2)
+    };
+    candidates
+        .map(|(n, c)| (n, distance(c)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("there are always candidates")
+        .0
+}
+
+/// Encode a URL for an OSC 8 hyperlink, in which control characters would
+/// end or escape it.
+fn hyperlink_target(target: &str) -> String {
+    let mut encoded = String::with_capacity(target.len());
+    for c in target.chars() {
+        if c.is_control() {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+impl<O: Write> Renderer for Ansi<O> {
+    type Err = Error;
+
+    fn render(&mut self, segments: &Vec<Segment>) -> Result<Self::Err> {
+        for segment in segments {
+            match *segment {
+                // The text of `[url]` is its target, so it need not be
+                // written twice.
+                Segment::Link {
+                    ref target,
+                    ref text,
+                } if !self.hyperlinks
+                    && matches!(text[..], [Segment::Text(ref t)] if t == target) =>
+                {
+                    self.write_text(target)?
+                }
+                _ => self.render_segment(segment)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn text(&mut self, s: &str) -> IoResult<()> {
+        let s = shift(&self.styles, s);
+        self.write_text(&s)
+    }
+
+    fn decoration_begin(&mut self, style: DecorationStyle) -> IoResult<()> {
+        self.styles.push(style);
+        if let Some(parameters) = self.parameters(style) {
+            if self.lines.started {
+                write!(self.out, "\x1b[{}m", parameters)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decoration_end(&mut self, style: DecorationStyle) -> IoResult<()> {
+        self.styles.pop();
+        // There is no undoing a single style, so reset them all and restore
+        // those that remain.
+        if self.lines.started && self.parameters(style).is_some() {
+            self.out.write_all(b"\x1b[0m")?;
+            self.write_styles()?;
+        }
+        Ok(())
+    }
+
+    fn quote_begin(&mut self, attribution: &Option<&str>) -> IoResult<()> {
+        self.break_line()?;
+        if let Some(name) = *attribution {
+            self.lines.skip_newline = false;
+            self.write_text(name)?;
+            self.write_text(" wrote:")?;
+        }
+        self.push_prefix(GUTTER.to_string(), GUTTER.to_string())
+    }
+
+    fn quote_end(&mut self, _attribution: &Option<&str>) -> IoResult<()> {
+        self.pop_prefix()
+    }
+
+    fn code(&mut self, s: &str) -> IoResult<()> {
+        self.push_prefix("    ".to_string(), "    ".to_string())?;
+        self.verbatim = true;
+        let s = s.strip_suffix('\n').unwrap_or(s);
+        self.write_text(s)?;
+        self.verbatim = false;
+        self.pop_prefix()
+    }
+
+    fn list_begin(&mut self, _style: ListStyle) -> IoResult<()> {
+        self.break_line()?;
+        self.lines.list_begin();
+        Ok(())
+    }
+
+    fn list_item_begin(&mut self, style: ListStyle) -> IoResult<()> {
+        let n = self.lines.next_item();
+        let marker = match style {
+            ListStyle::Unordered => "\u{2022} ".to_string(),
+            _ => item_marker(style, n),
+        };
+        let indent = " ".repeat(marker.chars().count());
+        self.push_prefix(marker, indent)
+    }
+
+    fn list_item_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        self.pop_prefix()
+    }
+
+    fn list_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        self.lines.list_end();
+        self.lines.skip_newline = true;
+        Ok(())
+    }
+
+    fn link_begin(&mut self, target: &str) -> IoResult<()> {
+        if self.hyperlinks {
+            let target = hyperlink_target(target);
+            if self.lines.started {
+                write!(self.out, "\x1b]8;;{}\x1b\\", target)?;
+            }
+            self.link = Some(target);
+        }
+        Ok(())
+    }
+
+    fn link_end(&mut self, target: &str) -> IoResult<()> {
+        if self.hyperlinks {
+            if self.lines.started {
+                self.out.write_all(b"\x1b]8;;\x1b\\")?;
+            }
+            self.link = None;
+            Ok(())
+        } else {
+            self.write_text(&format!(" ({})", target))
+        }
+    }
+
+    fn heading_begin(&mut self, level: u8) -> IoResult<()> {
+        self.push_prefix(String::new(), String::new())?;
+        self.decoration_begin(DecorationStyle::Bold)?;
+        if level <= 1 {
+            self.decoration_begin(DecorationStyle::Underline)?;
+        }
+        Ok(())
+    }
+
+    fn heading_end(&mut self, level: u8) -> IoResult<()> {
+        // Ending the line ends its styles too.
+        self.pop_prefix()?;
+        self.styles.pop();
+        if level <= 1 {
+            self.styles.pop();
+        }
+        Ok(())
+    }
+
+    fn rule(&mut self) -> IoResult<()> {
+        self.break_line()?;
+        self.start_line(true)?;
+        write!(self.out, "\x1b[2m{}\x1b[22m", "\u{2500}".repeat(RULE_WIDTH))?;
+        self.newline()?;
+        self.lines.skip_newline = true;
+        Ok(())
+    }
+
+    fn image(&mut self, src: &str) -> IoResult<()> {
+        self.link_begin(src)?;
+        self.write_text(&format!("[image: {}]", src))?;
+        if self.hyperlinks {
+            self.link_end(src)?;
+        }
+        Ok(())
+    }
+
+    fn embed(&mut self, provider: &str, id: &str, params: &[(Cow<str>, Cow<str>)]) -> IoResult<()> {
+        let link = embed_link(&self.providers, provider, id, params);
+        self.render(&link.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::parse;
+    use super::super::Renderer;
+    use super::{Ansi, ColorDepth};
+
+    fn ansi(input: &str) -> String {
+        let mut out = vec![];
+        Ansi::new(&mut out).render(&parse(input)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn colored(colors: ColorDepth, input: &str) -> String {
+        let mut out = vec![];
+        Ansi::new(&mut out)
+            .colors(colors)
+            .render(&parse(input))
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn decorations() {
+        assert_eq!(
+            ansi("[b]a [i]b[/i] [u]c[/u][/b] [size=10][center]d[/center][/size]"),
+            "\x1b[1ma \x1b[3mb\x1b[0m\x1b[1m \x1b[4mc\x1b[0m\x1b[1m\x1b[0m d"
+        );
+        assert_eq!(ansi("[b]a\nb[/b]"), "\x1b[1ma\x1b[0m\n\x1b[1mb\x1b[0m");
+        assert_eq!(ansi("a\x1b[31mb\x07\tc"), "a\u{FFFD}[31mb\u{FFFD}\tc");
+        assert_eq!(
+            ansi("[s]a[/s] x[sup]2[/sup] [mono]b[sub]1[/sub][/mono]"),
+            "\x1b[9ma\x1b[0m x² b₁"
+        );
+    }
+
+    #[test]
+    fn colors() {
+        let input = "[color=red]a[/color][color=#008080]b[/color]";
+        assert_eq!(
+            colored(ColorDepth::TrueColor, input),
+            "\x1b[38;2;255;0;0ma\x1b[0m\x1b[38;2;0;128;128mb\x1b[0m"
+        );
+        assert_eq!(
+            colored(ColorDepth::Palette256, input),
+            "\x1b[38;5;196ma\x1b[0m\x1b[38;5;30mb\x1b[0m"
+        );
+        assert_eq!(
+            colored(ColorDepth::Palette16, input),
+            "\x1b[91ma\x1b[0m\x1b[36mb\x1b[0m"
+        );
+        assert_eq!(colored(ColorDepth::Monochrome, input), "ab");
+        assert_eq!(
+            colored(ColorDepth::Palette256, "[color=#777]a[/color]"),
+            "\x1b[38;5;243ma\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn quotes_and_lists() {
+        assert_eq!(
+            ansi("[quote=\"A\"]\n[b]One\nTwo[/b]\n[quote]Nested[/quote]\n[/quote]\nAfter"),
+            "A wrote:\n\
+             \x1b[2m\u{2502}\x1b[22m \x1b[1mOne\x1b[0m\n\
+             \x1b[2m\u{2502}\x1b[22m \x1b[1mTwo\x1b[0m\n\
+             \x1b[2m\u{2502}\x1b[22m \x1b[2m\u{2502}\x1b[22m Nested\n\
+             After"
+        );
+        assert_eq!(
+            ansi("[list][*] One\n[*]Two[list=a][*]x[*]y[/list][/list][list=1][*]z[/list]"),
+            "\u{2022} One\n\u{2022} Two\n  a. x\n  b. y\n1. z\n"
+        );
+        assert_eq!(
+            ansi("Run:[code]\ncargo test\n  --all\n[/code]"),
+            "Run:\n    cargo test\n      --all\n"
+        );
+    }
+
+    #[test]
+    fn headings_and_rules() {
+        let rule = format!("\x1b[2m{}\x1b[22m", "\u{2500}".repeat(40));
+        assert_eq!(
+            ansi("a[h1]b[/h1]\n[h2]c\nd[/h2][hr]e"),
+            format!(
+                "a\n\x1b[1;4mb\x1b[0m\n\x1b[1mc\x1b[0m\n\x1b[1md\x1b[0m\n{}\ne",
+                rule
+            )
+        );
+        assert_eq!(
+            ansi("[quote][hr][/quote]"),
+            format!("\x1b[2m\u{2502}\x1b[22m {}\n", rule)
+        );
+    }
+
+    #[test]
+    fn links_and_images() {
+        assert_eq!(
+            ansi("[url=http://a]the [b]site[/b][/url] [url]http://b\x1b[/url] [img]c.png[/img]"),
+            "\x1b]8;;http://a\x1b\\the \x1b[1msite\x1b[0m\x1b]8;;\x1b\\ \
+             \x1b]8;;http://b%1B\x1b\\http://b\u{FFFD}\x1b]8;;\x1b\\ \
+             \x1b]8;;c.png\x1b\\[image: c.png]\x1b]8;;\x1b\\"
+        );
+        assert_eq!(
+            ansi("[url=http://a]x\ny[/url]"),
+            "\x1b]8;;http://a\x1b\\x\x1b]8;;\x1b\\\n\x1b]8;;http://a\x1b\\y\x1b]8;;\x1b\\"
+        );
+
+        let mut out = vec![];
+        Ansi::new(&mut out)
+            .hyperlinks(false)
+            .render(&parse(
+                "[url=http://a]the site[/url] or [url]http://b[/url] [img]c[/img]",
+            ))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "the site (http://a) or http://b [image: c]"
+        );
+    }
+}
@@ -0,0 +1,660 @@
+use super::super::visit::{walk_segment, Visitor};
+use super::super::{decoration, Body, DecorationStyle, ListStyle, Parser, Segment, TableCell};
+use super::{Renderer, Result};
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
+/// Renders segments back to bbcode, in a canonical form.
+///
+/// Tags are written in lowercase, colors as `#rrggbb` and link targets and
+/// spoiler titles in quotes where possible: `[url="..."]`. Parsing the
+/// output gives back the same segments as were rendered, for any segments
+/// returned by `parse`, or by a `Parser` given to `BbCode::parser` if there
+/// are custom tags.
+///
+/// Segments built in other ways must look like those returned by `parse`
+/// for that to hold: text segments may not be empty or adjacent to each
+/// other, and their text must not contain anything that would be parsed as
+/// a tag. Segments that cannot be written at all, like code containing
+/// `[/code]`, are rejected with an error of kind `InvalidData`.
+///
+/// A spoiler is written with `[spoiler]` or `[hide]`, whichever its text has
+/// no unfinished tags that would pair up with; the rare spoiler whose text
+/// has such tags of both names is rejected too.
+///
+/// Embedded media is written as `[media=name]id[/media]` where that is
+/// parsed back to the same media, and otherwise as the URL of its page, so
+/// its provider must be known to the parser.
+///
+/// ```
+/// use bbcode::parse;
+/// use bbcode::render::{BbCode, Renderer};
+///
+/// let segments = parse("[URL=example.com][color=red]Hi[/color][/URL]");
+/// let mut out = vec![];
+/// BbCode::new(&mut out).render(&segments).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     r#"[url="example.com"][color=#ff0000]Hi[/color][/url]"#
+/// );
+/// ```
+pub struct BbCode<O: Write> {
+    out: Output<O>,
+    parser: Parser,
+    colors: ColorFormat,
+}
+
+/// How colors are written by `BbCode`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum ColorFormat {
+    /// `[color=#ff0000]`
+    Hex,
+    /// `[color=red]`, for colors that have a name; others as `Hex`.
+    Named,
+}
+
+impl<O: Write> BbCode<O> {
+    pub fn new(out: O) -> Self {
+        BbCode {
+            out: Output {
+                inner: out,
+                last: 0,
+                quote_open: false,
+                titles_open: vec![],
+                quoted_titles_open: vec![],
+            },
+            parser: Parser::new(),
+            colors: ColorFormat::Hex,
+        }
+    }
+
+    /// Choose how to write colors; `ColorFormat::Hex` by default.
+    pub fn colors(mut self, colors: ColorFormat) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Write custom tags and embedded media the way `parser` expects them.
+    ///
+    /// Tags with no body according to their `TagHandler` have no closing
+    /// tag; all others are assumed to have nested bodies.
+    pub fn parser(mut self, parser: Parser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Write an attribute value of a custom tag, which can be written
+    /// without quotes if `bare`; the value for the tag itself may contain
+    /// `[` when quoted.
+    fn attr_value(&mut self, value: &str, bare: bool, own: bool) -> IoResult<()> {
+        let can_quote = !value.contains('"') && (own || !value.contains('['));
+        if bare && (self.out.quote_open || !can_quote) {
+            write!(self.out, "={}", value)
+        } else if can_quote {
+            write!(self.out, "=\"{}\"", value)
+        } else {
+            Err(invalid("attribute value cannot be quoted"))
+        }
+    }
+}
+
+/// Output which keeps track of whether `[url="` (or another tag with a quoted
+/// value, like `[spoiler="`) was written in text and hasn't been followed by
+/// `"]`.
+///
+/// Any `"]` written after that would be taken as the end of its target, so
+/// values are only quoted there if the input they came from must have been
+/// quoted too.
+///
+/// It also keeps track of the names of any `[spoiler=` or `[hide=` in text
+/// that hasn't been followed by `]`, since the next `]` would end its title,
+/// and of any `[spoiler="` or `[hide="` that hasn't been followed by `"]`.
+struct Output<O> {
+    inner: O,
+    last: u8,
+    quote_open: bool,
+    titles_open: Vec<&'static str>,
+    quoted_titles_open: Vec<&'static str>,
+}
+
+impl<O: Write> Write for Output<O> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let n = self.inner.write(buf)?;
+        for &b in &buf[..n] {
+            if self.last == b'"' && b == b']' {
+                self.quote_open = false;
+                self.quoted_titles_open.clear();
+            }
+            if b == b']' {
+                self.titles_open.clear();
+            }
+            self.last = b;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush()
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Find the last occurrence of `needle` in `s`, ignoring ASCII case.
+fn rfind_no_case(s: &str, needle: &str) -> Option<usize> {
+    s.as_bytes()
+        .windows(needle.len())
+        .rposition(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Whether `s` contains `needle`, ignoring ASCII case.
+fn contains_no_case(s: &str, needle: &str) -> bool {
+    rfind_no_case(s, needle).is_some()
+}
+
+/// Whether text anywhere in `segments` contains `tag`, ignoring ASCII case.
+fn mentions(segments: &[Segment], tag: &str) -> bool {
+    struct Mentions<'t> {
+        tag: &'t str,
+        found: bool,
+    }
+
+    impl<'s, 't> Visitor<'s> for Mentions<'t> {
+        fn visit_segment(&mut self, segment: &'s Segment<'s>) {
+            match *segment {
+                Segment::Text(ref s) => self.found |= contains_no_case(s, self.tag),
+                _ => walk_segment(self, segment),
+            }
+        }
+    }
+
+    let mut mentions = Mentions { tag, found: false };
+    mentions.visit_segments(segments);
+    mentions.found
+}
+
+/// Whether tags called `name` written around `segments` would pair with
+/// each other: no text in them opens a tag of that name, which could take
+/// the closing tag, and none outside nested segments closes one.
+fn can_enclose(segments: &[Segment], name: &str) -> bool {
+    let closer = format!("[/{}]", name);
+    !mentions(segments, &format!("[{}]", name))
+        && !mentions(segments, &format!("[{}=", name))
+        && !segments
+            .iter()
+            .any(|segment| matches!(*segment, Segment::Text(ref s) if contains_no_case(s, &closer)))
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+impl<O: Write> Renderer for BbCode<O> {
+    type Err = Error;
+
+    fn render(&mut self, segments: &Vec<Segment>) -> Result<Self::Err> {
+        for segment in segments {
+            match *segment {
+                // The body of `[url]` is not parsed, so text that looks like
+                // tags can only be written there.
+                Segment::Link {
+                    ref target,
+                    ref text,
+                } if matches!(text[..], [Segment::Text(ref t)] if t == target) => {
+                    if contains_no_case(target, "[/url]") {
+                        return Err(invalid("link target cannot contain [/url]"));
+                    }
+                    write!(self.out, "[url]{}[/url]", target)?
+                }
+                // A decoration whose text has its usual tag in it, which would
+                // pair up with the tags written around it, is written with an
+                // alias instead.
+                Segment::Decorated { style, ref text } if !decoration::names(style).is_empty() => {
+                    let names = decoration::names(style);
+                    let name = names
+                        .iter()
+                        .cloned()
+                        .find(|name| can_enclose(text, name))
+                        .unwrap_or(names[0]);
+                    write!(self.out, "[{}]", name)?;
+                    self.render(text)?;
+                    write!(self.out, "[/{}]", name)?
+                }
+                _ => self.render_segment(segment)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn text(&mut self, s: &str) -> IoResult<()> {
+        write!(self.out, "{}", s)?;
+        let quoted = ["[url=\"", "[spoiler=\"", "[hide=\""]
+            .iter()
+            .filter_map(|open| rfind_no_case(s, open).map(|i| i + open.len()))
+            .max();
+        if let Some(i) = quoted {
+            if !s[i..].contains("\"]") {
+                self.out.quote_open = true;
+            }
+        }
+        let rest = &s[s.rfind(']').map_or(0, |i| i + 1)..];
+        for &name in &["spoiler", "hide"] {
+            let open = |open: &Vec<&str>| open.contains(&name);
+            if !open(&self.out.titles_open) && contains_no_case(rest, &format!("[{}=", name)) {
+                self.out.titles_open.push(name);
+            }
+            let quoted = format!("[{}=\"", name);
+            let unfinished =
+                rfind_no_case(s, &quoted).is_some_and(|i| !s[i + quoted.len()..].contains("\"]"));
+            if !open(&self.out.quoted_titles_open) && unfinished {
+                self.out.quoted_titles_open.push(name);
+            }
+        }
+        Ok(())
+    }
+
+    fn decoration_begin(&mut self, style: DecorationStyle) -> IoResult<()> {
+        match style {
+            DecorationStyle::Color(r, g, b) => {
+                let name = match self.colors {
+                    ColorFormat::Named => decoration::color_name(r, g, b),
+                    ColorFormat::Hex => None,
+                };
+                match name {
+                    Some(name) => write!(self.out, "[color={}]", name),
+                    None => write!(self.out, "[color=#{:02x}{:02x}{:02x}]", r, g, b),
+                }
+            }
+            DecorationStyle::Size(size) if !decoration::is_valid_size(size.get()) => {
+                Err(invalid("font size is out of range"))
+            }
+            DecorationStyle::Size(size) => write!(self.out, "[size={}]", size),
+            _ => write!(self.out, "[{}]", decoration::closer(style)),
+        }
+    }
+
+    fn decoration_end(&mut self, style: DecorationStyle) -> IoResult<()> {
+        write!(self.out, "[/{}]", decoration::closer(style))
+    }
+
+    fn quote_begin(&mut self, attribution: &Option<&str>) -> IoResult<()> {
+        match *attribution {
+            Some(a) if a.contains('"') => Err(invalid("attribution cannot contain '\"'")),
+            Some(a) => write!(self.out, "[quote=\"{}\"]", a),
+            None => write!(self.out, "[quote]"),
+        }
+    }
+
+    fn quote_end(&mut self, _attribution: &Option<&str>) -> IoResult<()> {
+        write!(self.out, "[/quote]")
+    }
+
+    fn code(&mut self, s: &str) -> IoResult<()> {
+        if contains_no_case(s, "[/code]") {
+            return Err(invalid("code cannot contain [/code]"));
+        }
+        write!(self.out, "[code]{}[/code]", s)
+    }
+
+    fn list_begin(&mut self, style: ListStyle) -> IoResult<()> {
+        match style {
+            ListStyle::Unordered => write!(self.out, "[list]"),
+            ListStyle::Numeric => write!(self.out, "[list=1]"),
+            ListStyle::Alphabetic => write!(self.out, "[list=a]"),
+        }
+    }
+
+    fn list_item_begin(&mut self, _style: ListStyle) -> IoResult<()> {
+        write!(self.out, "[*]")
+    }
+
+    fn list_item_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn list_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        write!(self.out, "[/list]")
+    }
+
+    fn link_begin(&mut self, target: &str) -> IoResult<()> {
+        if !target.contains(']') && self.out.quote_open {
+            write!(self.out, "[url={}]", target)
+        } else if target.contains("\"]") {
+            Err(invalid("link target cannot contain '\"]'"))
+        } else {
+            write!(self.out, "[url=\"{}\"]", target)
+        }
+    }
+
+    fn link_end(&mut self, _target: &str) -> IoResult<()> {
+        write!(self.out, "[/url]")
+    }
+
+    fn heading_begin(&mut self, level: u8) -> IoResult<()> {
+        if !(1..=6).contains(&level) {
+            return Err(invalid("heading level is out of range"));
+        }
+        write!(self.out, "[h{}]", level)
+    }
+
+    fn heading_end(&mut self, level: u8) -> IoResult<()> {
+        write!(self.out, "[/h{}]", level)
+    }
+
+    fn rule(&mut self) -> IoResult<()> {
+        write!(self.out, "[hr]")
+    }
+
+    fn image(&mut self, src: &str) -> IoResult<()> {
+        if contains_no_case(src, "[/img]") {
+            return Err(invalid("image source cannot contain [/img]"));
+        }
+        write!(self.out, "[img]{}[/img]", src)
+    }
+
+    fn custom(
+        &mut self,
+        name: &str,
+        attrs: &[(Cow<str>, Cow<str>)],
+        body: &Vec<Segment>,
+    ) -> Result<Self::Err> {
+        if !is_name(name) {
+            return Err(invalid("tag name must be ASCII alphanumeric"));
+        }
+        write!(self.out, "[{}", name)?;
+        for (i, (key, value)) in attrs.iter().enumerate() {
+            if key.is_empty() && i == 0 {
+                // Runs to the first `]` unless quoted, which it must be if
+                // other attributes follow.
+                let bare = attrs.len() == 1 && !value.starts_with('"') && !value.contains(']');
+                self.attr_value(value, bare, true)?;
+            } else if is_name(key) {
+                let bare = !value.is_empty()
+                    && !value.contains(|c: char| {
+                        c.is_ascii_whitespace() || c == ']' || c == '[' || c == '"'
+                    });
+                write!(self.out, " {}", key)?;
+                self.attr_value(value, bare, false)?;
+            } else {
+                return Err(invalid("attribute names must be ASCII alphanumeric"));
+            }
+        }
+        write!(self.out, "]")?;
+
+        let body_kind = self.parser.handler(name).map(|h| h.body());
+        match (body_kind, &body[..]) {
+            (Some(Body::Empty), []) => Ok(()),
+            (Some(Body::Empty), _) => Err(invalid("tag cannot have a body")),
+            (Some(Body::Verbatim), body) => {
+                let closer = format!("[/{}]", name);
+                match body {
+                    [] => {}
+                    [Segment::Text(s)] if !contains_no_case(s, &closer) => self.text(s)?,
+                    _ => return Err(invalid("tag body must be plain text")),
+                }
+                write!(self.out, "{}", closer)
+            }
+            _ => {
+                self.render(body)?;
+                write!(self.out, "[/{}]", name)
+            }
+        }
+    }
+
+    fn embed(&mut self, provider: &str, id: &str, params: &[(Cow<str>, Cow<str>)]) -> IoResult<()> {
+        let named = match self.parser.provider(provider) {
+            Some(named) => named,
+            None => return Err(invalid("unknown provider of embedded media")),
+        };
+        let page = named.page(id, params);
+        let forms = [
+            format!("[media={}]{}[/media]", named.name(), id),
+            format!("[media]{}[/media]", page),
+            format!("[media={}]{}[/media]", named.name(), page),
+        ];
+        let expected = [Segment::Embed {
+            provider: provider.into(),
+            id: id.into(),
+            params: params.to_vec(),
+        }];
+        match forms
+            .iter()
+            .find(|form| self.parser.parse(form) == expected)
+        {
+            Some(form) => write!(self.out, "{}", form),
+            None => Err(invalid("embedded media cannot be written")),
+        }
+    }
+
+    fn spoiler(&mut self, title: Option<&str>, body: &Vec<Segment>) -> Result<Self::Err> {
+        let head = match title {
+            None => "]".to_owned(),
+            Some(title) if !title.contains(']') && self.out.quote_open => format!("={}]", title),
+            Some(title) if title.contains("\"]") => {
+                return Err(invalid("spoiler title cannot contain '\"]'"))
+            }
+            Some(title) => format!("=\"{}\"]", title),
+        };
+        let quoted = head.ends_with("\"]");
+        let mut inner = BbCode {
+            out: Output {
+                inner: vec![],
+                last: b']',
+                quote_open: self.out.quote_open && !quoted,
+                titles_open: vec![],
+                quoted_titles_open: if quoted {
+                    vec![]
+                } else {
+                    self.out.quoted_titles_open.clone()
+                },
+            },
+            parser: self.parser.clone(),
+            colors: self.colors,
+        };
+        inner.render(body)?;
+        let text = String::from_utf8(inner.out.inner).expect("bbcode is written as text");
+
+        // A spoiler parsed from `[hide]` may come after text with an
+        // unfinished `[spoiler=`, whose title the next `]` would end, or
+        // `[spoiler="`, whose title a `"]` in this spoiler would end. Or it
+        // may have `[spoiler]` in its text. Those would pair up with the tags
+        // written around it.
+        let ends_quote = quoted || text.contains("\"]");
+        let name = ["spoiler", "hide"]
+            .iter()
+            .cloned()
+            .filter(|name| !self.out.titles_open.contains(name))
+            .filter(|name| !ends_quote || !self.out.quoted_titles_open.contains(name))
+            .find(|&name| {
+                if can_enclose(body, name) {
+                    return true;
+                }
+                // Whether an unfinished tag in the text takes the closing tag
+                // depends on what follows it, so parse the spoiler to see.
+                let spoiler = format!("[{}{}{}[/{}]", name, head, text, name);
+                match self.parser.parse(&spoiler)[..] {
+                    [Segment::Spoiler {
+                        title: ref t,
+                        body: ref b,
+                    }] => t.as_deref() == title && b == body,
+                    _ => false,
+                }
+            })
+            .ok_or_else(|| invalid("spoiler text has tags that would pair with either name"))?;
+
+        write!(self.out, "[{}{}", name, head)?;
+        self.out.inner.write_all(text.as_bytes())?;
+        self.out.last = inner.out.last;
+        self.out.quote_open = inner.out.quote_open;
+        self.out.titles_open = inner.out.titles_open;
+        self.out.quoted_titles_open = inner.out.quoted_titles_open;
+        write!(self.out, "[/{}]", name)
+    }
+
+    fn table(&mut self, rows: &[Vec<TableCell>]) -> Result<Self::Err> {
+        write!(self.out, "[table]")?;
+        for row in rows {
+            write!(self.out, "[tr]")?;
+            for cell in row {
+                let tag = if cell.header { "th" } else { "td" };
+                write!(self.out, "[{}]", tag)?;
+                self.render(&cell.body)?;
+                write!(self.out, "[/{}]", tag)?;
+            }
+            write!(self.out, "[/tr]")?;
+        }
+        write!(self.out, "[/table]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{parse, Body, DecorationStyle, Parser, Segment, TagHandler};
+    use super::super::Renderer;
+    use super::BbCode;
+    use std::num::NonZeroU8;
+
+    fn to_bbcode(segments: &Vec<Segment>) -> ::std::io::Result<String> {
+        let mut out = vec![];
+        BbCode::new(&mut out).render(segments)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn round_trip() {
+        for input in &[
+            "plain [/b] text [b]",
+            "[B]a[I]b[/i][/b][u][/u][center]c[/center][size=12]d[/size]",
+            "[color=#81F]e[/color][color=White]f[/color]",
+            "[quote]g[/quote][QUOTE=\"Bob\"][quote=\"x]y\"]h[/quote][/quote]",
+            "[code][b]i[/code][img]j.png[/img]",
+            "[list][*]k[*][list=1][*][list=a][/list][/list][*][/list]",
+            "[url]l[/url][url=m]n[/url][url=\"o\"]p[/url][url=q\"]r[/url][url=\"s]\"]t[/url]",
+            "[b][i]unclosed[/b] [url=[b]x[/b]]y[/url] [list] [*] [/list]",
+            "[url=\"] [url=\"a]b[/url]",
+            "[quote=\"c [url=\"d\"]e[/url]",
+            "[H1]a[/h1][hr][HR][h6][b]b[/b]\n[/h6][h2]c[/h3]",
+            "[sub]a[sup]b[/sup][/sub][S]c[/s][del]d[/del][tt]e[/TT][mono]f[/strike]",
+            "[youtube]youtu.be/dQw4w9WgXcQ?t=5[/youtube][video] vimeo.com/1/ab [/video]",
+            "[media=YouTube]dQw4w9WgXcQ[/media][media]x[/media][b][youtube]x[/b][/youtube]",
+            "[spoiler]a[/spoiler][HIDE=b c]d[/hide][spoiler=\"e]\"]f[/spoiler][hide=\"g]h[/hide]",
+            "[url=\"] [spoiler=i]j[/spoiler] [hide=\"k[/hide]",
+            "[hide][spoiler]l[/hide][spoiler=[hide]m[/hide][hide][/spoiler][/hide]",
+            "[hide][spoiler=\"[h2][hide=[/hide] [spoiler][hide=[spoiler=[/spoiler]",
+            "[TABLE]\n [tr] [th]a[/TH][td][/td][/tr][tr][/tr][/table][table][/table]",
+            "[table][tr][td][table][tr][th][b]b[/th][/tr][/table][/td][/tr][/table] [td]c[/td]",
+            "[tt][mono][url=[*][/tt]|",
+            "[mono][tt][/mono][/b][/tt][tr] ",
+            "[s][strike]a[/s][del][/strike][s][/del] [TT][/mono][mono][/tt]",
+        ] {
+            let segments = parse(input);
+            let output = to_bbcode(&segments).unwrap();
+            assert_eq!(parse(&output), segments, "{:?} became {:?}", input, output);
+        }
+    }
+
+    #[test]
+    fn canonical_tags() {
+        assert_eq!(
+            to_bbcode(&parse(
+                "[QUOTE=\"A\"][Color=red][LIST=a][*]x[/LIST][/color][/Quote]"
+            ))
+            .unwrap(),
+            "[quote=\"A\"][color=#ff0000][list=a][*]x[/list][/color][/quote]"
+        );
+        assert_eq!(
+            to_bbcode(&parse("[s]a[/s][DEL]b[/del][tt]c[/tt]")).unwrap(),
+            "[strike]a[/strike][strike]b[/strike][mono]c[/mono]"
+        );
+        assert_eq!(
+            to_bbcode(&parse(
+                "[youtube]https://www.youtube.com/watch?v=dQw4w9WgXcQ[/youtube]\
+                 [video]https://youtu.be/dQw4w9WgXcQ?t=5[/video]"
+            ))
+            .unwrap(),
+            "[media=youtube]dQw4w9WgXcQ[/media]\
+             [media]https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=5s[/media]"
+        );
+    }
+
+    #[test]
+    fn custom_tags() {
+        struct Tag(&'static str, Body);
+        impl TagHandler for Tag {
+            fn name(&self) -> &str {
+                self.0
+            }
+            fn body(&self) -> Body {
+                self.1
+            }
+        }
+
+        let parser = Parser::new()
+            .register(Tag("br", Body::Empty))
+            .register(Tag("dice", Body::Verbatim))
+            .register(Tag("box", Body::Nested));
+        let input = "[br][dice=\"2d6\" a=\"b c\"][i][/dice][box=x]y]z[/box][box a=]w[/box]";
+        let segments = parser.parse(input);
+
+        let mut out = vec![];
+        BbCode::new(&mut out)
+            .parser(parser.clone())
+            .render(&segments)
+            .unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert_eq!(
+            output,
+            "[br][dice=\"2d6\" a=\"b c\"][i][/dice][box=\"x\"]y]z[/box][box a=\"\"]w[/box]"
+        );
+        assert_eq!(parser.parse(&output), segments);
+    }
+
+    #[test]
+    fn rejects_unrepresentable_segments() {
+        for segment in [
+            Segment::Code("[/CODE]".into()),
+            Segment::Image {
+                src: "[/img]".into(),
+            },
+            Segment::Link {
+                target: "\"]".into(),
+                text: vec![],
+            },
+            Segment::Quote {
+                attribution: Some("\"".into()),
+                body: vec![],
+            },
+            Segment::Decorated {
+                style: DecorationStyle::Size(NonZeroU8::new(99).unwrap()),
+                text: vec![],
+            },
+            Segment::Heading {
+                level: 7,
+                text: vec![],
+            },
+            Segment::Embed {
+                provider: "dailymotion".into(),
+                id: "x7tgad0".into(),
+                params: vec![],
+            },
+            Segment::Embed {
+                provider: "youtube".into(),
+                id: "[/media]".into(),
+                params: vec![],
+            },
+            Segment::Spoiler {
+                title: Some("\"]".into()),
+                body: vec![],
+            },
+            Segment::Spoiler {
+                title: None,
+                body: vec![Segment::Text("[spoiler][hide]".into())],
+            },
+        ] {
+            assert!(to_bbcode(&vec![segment]).is_err());
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use super::super::{DecorationStyle, ListStyle, Providers, Segment};
+use super::{embed_link, Renderer, Result};
+use std::borrow::Cow;
+use std::io::{Error, Result as IoResult, Write};
+
+/// Renders segments as LaTeX, to be included in the body of a document.
+///
+/// Bold, italic and underlined text is written with `\textbf`, `\emph` and
+/// `\underline`, subscripts and superscripts with `\textsubscript` and
+/// `\textsuperscript`, and monospace with `\texttt`. Colored text is
+/// written with `\textcolor` from the `xcolor` package, and struck-through
+/// text with `\sout` from the `ulem` package, which the document must load;
+/// `ulem` should be loaded with the `normalem` option, so that `\emph` is
+/// still italic. Sizes are taken to be in points
+/// and written as the nearest size command of a 10pt document, like
+/// `\large`. Underlined text can't be broken across lines, as it is boxed.
+///
+/// Quotes are `quote` environments, beginning with who wrote them. Lists
+/// are `itemize` or `enumerate` environments, labelled with numbers or
+/// letters according to their style, and code is `verbatim`; where that
+/// can't be used, as inside the argument of a command, code is written with
+/// `\texttt` instead. Links are followed by a footnote giving their target,
+/// images are written as `[image: src]`, and embedded media as the URL of
+/// its page.
+///
+/// Headings are unnumbered sectioning commands, from `\section*` for the
+/// first level down to `\subparagraph*` for the last two, or bold text where
+/// they can't be used. Rules are rules across the width of the text.
+///
+/// Line breaks are written as `\newline`, and blank lines end paragraphs.
+///
+/// ```
+/// use bbcode::parse;
+/// use bbcode::render::{Latex, Renderer};
+///
+/// let segments = parse("[b]50%[/b] off\n[list=a][*]this[*][i]that[/i][/list]");
+/// let mut out = vec![];
+/// Latex::new(&mut out).render(&segments).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "\\textbf{50\\%} off\n\
+///      \\begin{enumerate}\\renewcommand{\\labelenumi}{\\alph{enumi}.}\n\
+///      \\item this\n\
+///      \\item \\emph{that}\n\
+///      \\end{enumerate}\n"
+/// );
+/// ```
+pub struct Latex<O: Write> {
+    out: O,
+    /// Line breaks in the text since anything else was written.
+    newlines: usize,
+    /// Whether anything has been written in the current paragraph.
+    started: bool,
+    /// Whether the output is at the start of a line.
+    line_start: bool,
+    /// The number of commands whose argument encloses the current text.
+    arguments: usize,
+    /// The number of enclosing `enumerate` environments.
+    enumerates: usize,
+    /// The number of enclosing headings.
+    headings: usize,
+    providers: Providers,
+}
+
+/// The size commands of a 10pt document with their sizes in points.
+const SIZES: [(&str, f32); 10] = [
+    ("tiny", 5.0),
+    ("scriptsize", 7.0),
+    ("footnotesize", 8.0),
+    ("small", 9.0),
+    ("normalsize", 10.0),
+    ("large", 12.0),
+    ("Large", 14.4),
+    ("LARGE", 17.28),
+    ("huge", 20.74),
+    ("Huge", 24.88),
+];
+
+/// The sectioning commands for each level of heading.
+const SECTIONS: [&str; 6] = [
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+    "subparagraph",
+];
+
+/// The counters of nested `enumerate` environments, outermost first.
+const COUNTERS: [&str; 4] = ["enumi", "enumii", "enumiii", "enumiv"];
+
+/// Escape text so LaTeX typesets it as it is.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '<' => escaped.push_str("\\textless{}"),
+            '>' => escaped.push_str("\\textgreater{}"),
+            '|' => escaped.push_str("\\textbar{}"),
+            // Brackets could be taken as the optional argument of a command
+            // like `\item` or `\\` before them.
+            '[' => escaped.push_str("{[}"),
+            ']' => escaped.push_str("{]}"),
+            // `--` would be a ligature, an en dash.
+            '-' if chars.peek() == Some(&'-') => escaped.push_str("-{}"),
+            '\t' => escaped.push(' '),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The size command nearest to a size in points.
+fn size_command(size: u8) -> &'static str {
+    let size = f32::from(size);
+    SIZES
+        .iter()
+        .min_by(|a, b| (a.1 - size).abs().total_cmp(&(b.1 - size).abs()))
+        .expect("there are sizes")
+        .0
+}
+
+impl<O: Write> Latex<O> {
+    pub fn new(out: O) -> Self {
+        Latex {
+            out,
+            newlines: 0,
+            started: false,
+            line_start: true,
+            arguments: 0,
+            enumerates: 0,
+            headings: 0,
+            providers: Providers::new(),
+        }
+    }
+
+    /// Find the pages of embedded media with providers, which are
+    /// `Providers::new()` by default.
+    pub fn providers(mut self, providers: Providers) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    fn write(&mut self, s: &str) -> IoResult<()> {
+        if !s.is_empty() {
+            self.out.write_all(s.as_bytes())?;
+            self.line_start = s.ends_with('\n');
+        }
+        Ok(())
+    }
+
+    /// Write the line breaks in the text since anything else was written.
+    ///
+    /// One is a `\newline`, unless nothing has been written for it to end;
+    /// more end the paragraph.
+    fn write_newlines(&mut self) -> IoResult<()> {
+        let newlines = self.newlines;
+        self.newlines = 0;
+        if newlines > 1 {
+            self.write(if self.line_start { "\n" } else { "\n\n" })?;
+            self.started = false;
+        } else if newlines == 1 && self.started {
+            self.write("\\newline\n")?;
+        }
+        Ok(())
+    }
+
+    /// Begin a block, which begins a paragraph of its own.
+    fn begin_block(&mut self, begin: &str) -> IoResult<()> {
+        self.newlines = 0;
+        if !self.line_start {
+            self.write("\n")?;
+        }
+        self.write(begin)?;
+        self.started = false;
+        Ok(())
+    }
+
+    /// End a block begun by `begin_block`.
+    fn end_block(&mut self, end: &str) -> IoResult<()> {
+        self.newlines = 0;
+        if !self.line_start {
+            self.write("\n")?;
+        }
+        self.write(end)?;
+        self.write("\n")?;
+        self.started = false;
+        Ok(())
+    }
+
+    /// Write code with `\texttt`, line by line, for where `verbatim` can't
+    /// be used.
+    fn typewriter(&mut self, s: &str) -> IoResult<()> {
+        self.write_newlines()?;
+        if self.started {
+            self.write("\\newline\n")?;
+        }
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.write("\\newline\n")?;
+            }
+            let line = escape(line.trim_end_matches('\r')).replace(' ', "~");
+            self.write(&format!("\\texttt{{{}}}", line))?;
+        }
+        self.newlines = 1;
+        self.started = true;
+        Ok(())
+    }
+}
+
+impl<O: Write> Renderer for Latex<O> {
+    type Err = Error;
+
+    fn render(&mut self, segments: &Vec<Segment>) -> Result<Self::Err> {
+        for segment in segments {
+            match *segment {
+                // The text of `[url]` is its target, so it need not be
+                // written twice.
+                Segment::Link {
+                    ref target,
+                    ref text,
+                } if matches!(text[..], [Segment::Text(ref t)] if t == target) => {
+                    self.write_newlines()?;
+                    self.write(&format!("\\texttt{{{}}}", escape(target)))?;
+                    self.started = true;
+                }
+                // A list environment must have items.
+                Segment::List { ref items, .. } if items.is_empty() => {}
+                _ => self.render_segment(segment)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn text(&mut self, s: &str) -> IoResult<()> {
+        for (i, line) in s.split('\n').enumerate() {
+            // Headings are a single line.
+            if i > 0 && self.headings > 0 {
+                self.write(" ")?;
+            } else if i > 0 {
+                self.newlines += 1;
+            }
+            let line = escape(line);
+            if !line.is_empty() {
+                self.write_newlines()?;
+                self.write(&line)?;
+                self.started = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn decoration_begin(&mut self, style: DecorationStyle) -> IoResult<()> {
+        match style {
+            DecorationStyle::Bold => self.write("\\textbf{")?,
+            DecorationStyle::Italic => self.write("\\emph{")?,
+            DecorationStyle::Underline => self.write("\\underline{")?,
+            DecorationStyle::Subscript => self.write("\\textsubscript{")?,
+            DecorationStyle::Superscript => self.write("\\textsuperscript{")?,
+            DecorationStyle::Strikethrough => self.write("\\sout{")?,
+            DecorationStyle::Monospace => self.write("\\texttt{")?,
+            DecorationStyle::Color(r, g, b) => {
+                self.write(&format!("\\textcolor[RGB]{{{},{},{}}}{{", r, g, b))?
+            }
+            DecorationStyle::Size(size) => {
+                self.write(&format!("{{\\{} ", size_command(size.get())))?;
+                return Ok(());
+            }
+            DecorationStyle::Center => return self.begin_block("\\begin{center}\n"),
+        }
+        self.arguments += 1;
+        Ok(())
+    }
+
+    fn decoration_end(&mut self, style: DecorationStyle) -> IoResult<()> {
+        match style {
+            DecorationStyle::Center => self.end_block("\\end{center}"),
+            DecorationStyle::Size(_) => self.write("}"),
+            _ => {
+                self.arguments -= 1;
+                self.write("}")
+            }
+        }
+    }
+
+    fn quote_begin(&mut self, attribution: &Option<&str>) -> IoResult<()> {
+        self.begin_block("\\begin{quote}\n")?;
+        if let Some(name) = *attribution {
+            self.write(&format!("\\emph{{{} wrote:}}\\par\n", escape(name)))?;
+        }
+        Ok(())
+    }
+
+    fn quote_end(&mut self, _attribution: &Option<&str>) -> IoResult<()> {
+        self.end_block("\\end{quote}")
+    }
+
+    fn code(&mut self, s: &str) -> IoResult<()> {
+        let s = s.strip_prefix('\n').unwrap_or(s);
+        let s = s.strip_suffix('\n').unwrap_or(s);
+        if self.headings > 0 {
+            self.write_newlines()?;
+            let line = escape(&s.replace('\n', " ")).replace(' ', "~");
+            self.started = true;
+            return self.write(&format!("\\texttt{{{}}}", line));
+        }
+        // `verbatim` ends at the first `\end{verbatim}`, and can't be in an
+        // argument at all.
+        if self.arguments > 0 || s.contains("\\end{verbatim}") {
+            return self.typewriter(s);
+        }
+        self.begin_block("\\begin{verbatim}\n")?;
+        self.write(s)?;
+        self.end_block("\\end{verbatim}")
+    }
+
+    fn list_begin(&mut self, style: ListStyle) -> IoResult<()> {
+        let label = match style {
+            ListStyle::Unordered => return self.begin_block("\\begin{itemize}\n"),
+            ListStyle::Numeric => "arabic",
+            ListStyle::Alphabetic => "alph",
+        };
+        self.enumerates += 1;
+        let counter = COUNTERS[self.enumerates.min(COUNTERS.len()) - 1];
+        self.begin_block(&format!(
+            "\\begin{{enumerate}}\\renewcommand{{\\label{c}}}{{\\{l}{{{c}}}.}}\n",
+            c = counter,
+            l = label
+        ))
+    }
+
+    fn list_item_begin(&mut self, _style: ListStyle) -> IoResult<()> {
+        self.newlines = 0;
+        if !self.line_start {
+            self.write("\n")?;
+        }
+        self.write("\\item ")?;
+        self.started = false;
+        Ok(())
+    }
+
+    fn list_item_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn list_end(&mut self, style: ListStyle) -> IoResult<()> {
+        if style == ListStyle::Unordered {
+            self.end_block("\\end{itemize}")
+        } else {
+            self.enumerates -= 1;
+            self.end_block("\\end{enumerate}")
+        }
+    }
+
+    fn link_begin(&mut self, _target: &str) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn link_end(&mut self, target: &str) -> IoResult<()> {
+        self.write(&format!("\\footnote{{\\texttt{{{}}}}}", escape(target)))
+    }
+
+    fn image(&mut self, src: &str) -> IoResult<()> {
+        self.text(&format!("[image: {}]", src))
+    }
+
+    fn heading_begin(&mut self, level: u8) -> IoResult<()> {
+        // Sectioning commands can't be in the argument of another command.
+        if self.arguments > 0 || self.headings > 0 {
+            self.write_newlines()?;
+            self.write("\\textbf{")?;
+        } else {
+            let section = SECTIONS[usize::from(level.clamp(1, 6)) - 1];
+            self.begin_block(&format!("\\{}*{{", section))?;
+        }
+        self.arguments += 1;
+        self.headings += 1;
+        Ok(())
+    }
+
+    fn heading_end(&mut self, _level: u8) -> IoResult<()> {
+        self.arguments -= 1;
+        self.headings -= 1;
+        if self.arguments > 0 || self.headings > 0 {
+            return self.write("}");
+        }
+        self.newlines = 0;
+        self.write("}\n")?;
+        self.started = false;
+        Ok(())
+    }
+
+    fn rule(&mut self) -> IoResult<()> {
+        let rule = "\\noindent\\rule{\\linewidth}{0.4pt}";
+        if self.arguments > 0 {
+            self.write_newlines()?;
+            self.started = true;
+            return self.write(rule);
+        }
+        self.begin_block(&format!("\\par{}\\par\n", rule))?;
+        Ok(())
+    }
+
+    fn embed(&mut self, provider: &str, id: &str, params: &[(Cow<str>, Cow<str>)]) -> IoResult<()> {
+        let link = embed_link(&self.providers, provider, id, params);
+        self.render(&link.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::parse;
+    use super::super::Renderer;
+    use super::Latex;
+
+    fn latex(input: &str) -> String {
+        let mut out = vec![];
+        Latex::new(&mut out).render(&parse(input)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(
+            latex("\\{}$&#%_^~<>|[x]--y-z\x07"),
+            "\\textbackslash{}\\{\\}\\$\\&\\#\\%\\_\\textasciicircum{}\\textasciitilde{}\
+             \\textless{}\\textgreater{}\\textbar{}{[}x{]}-{}-y-z"
+        );
+        assert_eq!(latex("a\nb\n\nc\n\n\nd\n"), "a\\newline\nb\n\nc\n\nd");
+    }
+
+    #[test]
+    fn decorations() {
+        assert_eq!(
+            latex("[b]a[/b][i]b[/i][u]c[/u][color=#ff8000]d[/color]"),
+            "\\textbf{a}\\emph{b}\\underline{c}\\textcolor[RGB]{255,128,0}{d}"
+        );
+        assert_eq!(
+            latex("[size=2]a[/size][size=10]b[/size][size=14]c[/size][size=29]d[/size]"),
+            "{\\tiny a}{\\normalsize b}{\\Large c}{\\Huge d}"
+        );
+        assert_eq!(
+            latex("a[center]b[/center]c"),
+            "a\n\\begin{center}\nb\n\\end{center}\nc"
+        );
+        assert_eq!(
+            latex("H[sub]2[/sub]O x[sup]2[/sup] [s]a[/s] [tt]b_c[/tt]"),
+            "H\\textsubscript{2}O x\\textsuperscript{2} \\sout{a} \\texttt{b\\_c}"
+        );
+    }
+
+    #[test]
+    fn quotes_and_code() {
+        assert_eq!(
+            latex("[quote=\"A_1\"]\nOne\n\nTwo\n[quote]Nested[/quote]\n[/quote]\nAfter"),
+            "\\begin{quote}\n\\emph{A\\_1 wrote:}\\par\nOne\n\nTwo\n\
+             \\begin{quote}\nNested\n\\end{quote}\n\\end{quote}\nAfter"
+        );
+        assert_eq!(
+            latex("Run:[code]\ncargo test\n  --all\n[/code]"),
+            "Run:\n\\begin{verbatim}\ncargo test\n  --all\n\\end{verbatim}\n"
+        );
+        assert_eq!(
+            latex("[b]Run:[code]a {b}\n  c[/code][/b]"),
+            "\\textbf{Run:\\newline\n\\texttt{a~\\{b\\}}\\newline\n\\texttt{~~c}}"
+        );
+        assert_eq!(
+            latex("[code]\\end{verbatim}[/code]"),
+            "\\texttt{\\textbackslash{}end\\{verbatim\\}}"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            latex("[list][*] One\n[*]Two[list=a][*]x[*][list=1][*]y[/list][/list][/list][list][/list]"),
+            "\\begin{itemize}\n\
+             \\item  One\n\
+             \\item Two\n\
+             \\begin{enumerate}\\renewcommand{\\labelenumi}{\\alph{enumi}.}\n\
+             \\item x\n\
+             \\item \n\
+             \\begin{enumerate}\\renewcommand{\\labelenumii}{\\arabic{enumii}.}\n\
+             \\item y\n\
+             \\end{enumerate}\n\
+             \\end{enumerate}\n\
+             \\end{itemize}\n"
+        );
+    }
+
+    #[test]
+    fn headings_and_rules() {
+        assert_eq!(
+            latex("a[h1]b_1[/h1]\nc[h3]d\ne[code]f[/code][/h3][h6]g[/h6][hr]h"),
+            "a\n\\section*{b\\_1}\nc\n\\subsubsection*{d e\\texttt{f}}\n\
+             \\subparagraph*{g}\n\\par\\noindent\\rule{\\linewidth}{0.4pt}\\par\nh"
+        );
+        assert_eq!(
+            latex("[b][h2]a[/h2][hr][/b]"),
+            "\\textbf{\\textbf{a}\\noindent\\rule{\\linewidth}{0.4pt}}"
+        );
+    }
+
+    #[test]
+    fn links_and_images() {
+        assert_eq!(
+            latex("[url=http://a/#b]the site[/url] or [url]http://c/~d[/url] [img]e_f.png[/img]"),
+            "the site\\footnote{\\texttt{http://a/\\#b}} or \
+             \\texttt{http://c/\\textasciitilde{}d} {[}image: e\\_f.png{]}"
+        );
+        assert_eq!(
+            latex("[media=vimeo]76979871[/media]"),
+            "\\texttt{https://vimeo.com/76979871}"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Laying out text in lines, for renderers of text like `PlainText`.
+
+/// The layout of the lines being written: the prefixes of lines inside
+/// blocks like quotes and list items, and the state of the current line.
+///
+/// Renderers write the lines themselves, asking this for each line's prefix
+/// and telling it when lines end.
+pub struct Lines {
+    /// Prefixes of lines, from the outermost block in.
+    prefixes: Vec<Prefix>,
+    /// The number of the current item in each enclosing list.
+    items: Vec<usize>,
+    /// Whether anything, even the prefix, has been written to this line.
+    pub started: bool,
+    /// Leading whitespace is dropped, as at the start of a list item.
+    pub trim: bool,
+    /// A line break in the next text is dropped, as it would only end the
+    /// line that a block already ended.
+    pub skip_newline: bool,
+}
+
+/// The prefix of lines inside a block.
+struct Prefix {
+    /// The prefix of the first line.
+    first: String,
+    /// The prefix of following lines.
+    rest: String,
+    used: bool,
+}
+
+impl Lines {
+    pub fn new() -> Self {
+        Lines {
+            prefixes: vec![],
+            items: vec![],
+            started: false,
+            trim: false,
+            skip_newline: false,
+        }
+    }
+
+    /// Start the current line, returning its prefix, with any trailing
+    /// whitespace only if `trailing_space`.
+    pub fn start(&mut self, trailing_space: bool) -> String {
+        let mut prefix = String::new();
+        for p in &mut self.prefixes {
+            prefix.push_str(if p.used { &p.rest } else { &p.first });
+            p.used = true;
+        }
+        if !trailing_space {
+            prefix.truncate(prefix.trim_end().len());
+        }
+        self.started = true;
+        prefix
+    }
+
+    /// The lines of text, which end where it has line breaks. A line break
+    /// at its start is dropped if it would only end a line already ended.
+    pub fn split<'s>(&mut self, mut s: &'s str) -> impl Iterator<Item = &'s str> {
+        if self.skip_newline {
+            self.skip_newline = false;
+            s = s
+                .strip_prefix("\r\n")
+                .or_else(|| s.strip_prefix('\n'))
+                .unwrap_or(s);
+        }
+        s.split('\n').map(|line| line.trim_end_matches('\r'))
+    }
+
+    /// The length of the prefix of the current line.
+    pub fn prefix_len(&self) -> usize {
+        self.prefixes.iter().map(|p| p.rest.chars().count()).sum()
+    }
+
+    /// Begin a block whose lines are prefixed, once the line before it has
+    /// been ended.
+    pub fn push_prefix(&mut self, first: String, rest: String) {
+        self.prefixes.push(Prefix {
+            first,
+            rest,
+            used: false,
+        });
+        self.trim = true;
+        self.skip_newline = true;
+    }
+
+    /// Whether the current line must be ended before the block begun by
+    /// `push_prefix` is ended. An empty block still gets a line, so an empty
+    /// item has its marker.
+    pub fn ends_line(&self) -> bool {
+        self.started || self.prefixes.last().is_some_and(|p| !p.used)
+    }
+
+    /// End a block begun by `push_prefix`, once its last line has been
+    /// ended.
+    pub fn pop_prefix(&mut self) {
+        self.prefixes.pop();
+        self.trim = false;
+        self.skip_newline = true;
+    }
+
+    /// Begin a list, whose items are numbered from 1.
+    pub fn list_begin(&mut self) {
+        self.items.push(0);
+    }
+
+    /// The number of the next item of the innermost list.
+    pub fn next_item(&mut self) -> usize {
+        let n = self.items.last_mut().expect("item outside list");
+        *n += 1;
+        *n
+    }
+
+    /// End a list begun by `list_begin`.
+    pub fn list_end(&mut self) {
+        self.items.pop();
+    }
+}
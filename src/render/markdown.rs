@@ -0,0 +1,1143 @@
+use super::super::{DecorationStyle, ListStyle, Providers, Segment, TableCell};
+use super::{embed_link, spoiler_placeholder, Redaction, Renderer, Result};
+use std::borrow::Cow;
+use std::io::{Error, Result as IoResult, Write};
+use std::mem;
+
+/// Renders segments as CommonMark.
+///
+/// Bold and italic text is written with `**` and `*`, quotes and lists as
+/// Markdown blocks, code as fenced blocks, and links and images with the
+/// usual `[text](target)` and `![](src)` syntax. Links whose text is their
+/// target are written as autolinks like `<https://example.com>` where
+/// possible. Quote attributions are written as a line before the quote, and
+/// alphabetic lists are numbered, as Markdown has neither. Headings are
+/// written with `#` on a single line, with code in them as code spans, and
+/// rules as `___`. Embedded media is written as a link to its page, and
+/// spoilers according to a `Redaction`.
+///
+/// Tables are written as pipe tables, the GitHub extension. Their first row
+/// is the header row if it holds only header cells, and is otherwise left
+/// empty, with other header cells written in bold. Each cell is a single
+/// line, so code in one is a code span and lines of blocks in one are joined
+/// by `<br>`, or by spaces without HTML.
+///
+/// Monospaced text is written as a code span where it is only text on a
+/// single line.
+///
+/// Text is escaped so it is never taken as markup, and line breaks in it are
+/// kept as hard line breaks. Markdown has no underlining, strikethrough,
+/// subscripts, superscripts, colors, sizes or centering, so they are written
+/// as inline HTML unless `Markdown::html` is used to drop them, leaving their
+/// text; so is monospaced text that can't be a code span.
+///
+/// ```
+/// use bbcode::parse;
+/// use bbcode::render::{Markdown, Renderer};
+///
+/// let segments = parse(
+///     "[b]2*3[/b] is [url=https://example.com/six]six[/url]\n\
+///      [list][*]one[*][u]two[/u][/list]",
+/// );
+/// let mut out = vec![];
+/// Markdown::new(&mut out).render(&segments).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "**2\\*3** is [six](https://example.com/six)\n\n- one\n- <u>two</u>\n"
+/// );
+/// ```
+pub struct Markdown<O: Write> {
+    out: O,
+    html: bool,
+    /// Prefixes of lines, from the outermost block in.
+    prefixes: Vec<Prefix>,
+    /// Each enclosing list, innermost last.
+    lists: Vec<List>,
+    /// A list that just ended with nothing written since; another list of
+    /// the same kind must be marked differently to be separate from it.
+    after_list: Option<List>,
+    /// Markup around inline content, outermost first.
+    open: Vec<Delimiter>,
+    /// Delimiters which have been closed but not yet written, so one that is
+    /// opened again straight away can stay open instead.
+    closing: Vec<Delimiter>,
+    /// The number of enclosing links.
+    links: usize,
+    /// The number of enclosing headings.
+    headings: usize,
+    /// Everything is written on one line, as in a table cell.
+    inline: bool,
+    /// The number of nested calls to `render`.
+    depth: usize,
+    /// Whether the prefix of this line has been written.
+    started: bool,
+    /// Whether nothing but the prefix has been written to this line.
+    line_start: bool,
+    /// The last character of content written, if it has been held back in
+    /// case it needs to be written as a character reference.
+    held: Option<char>,
+    /// The last character written.
+    last: Option<char>,
+    /// Nothing has been written since a block began.
+    fresh: bool,
+    /// What to write between the last content and the next.
+    separator: Separator,
+    /// Whitespace to be written before the next content.
+    spaces: String,
+    providers: Providers,
+    spoilers: Redaction,
+}
+
+/// The prefix of lines inside a block.
+struct Prefix {
+    /// The prefix of the first line.
+    first: String,
+    /// The prefix of following lines.
+    rest: String,
+    used: bool,
+    item: bool,
+}
+
+struct List {
+    style: ListStyle,
+    items: usize,
+    /// Use the alternative marker, `*` or `)`.
+    alternate: bool,
+}
+
+#[derive(PartialEq, Eq)]
+struct Delimiter {
+    open: String,
+    close: String,
+    written: bool,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Separator {
+    Nothing,
+    Spaces,
+    LineBreak,
+    Paragraph,
+}
+
+impl<O: Write> Markdown<O> {
+    pub fn new(out: O) -> Self {
+        Markdown {
+            out,
+            html: true,
+            prefixes: vec![],
+            lists: vec![],
+            after_list: None,
+            open: vec![],
+            closing: vec![],
+            links: 0,
+            headings: 0,
+            inline: false,
+            depth: 0,
+            started: false,
+            line_start: false,
+            held: None,
+            last: None,
+            fresh: true,
+            separator: Separator::Nothing,
+            spaces: String::new(),
+            providers: Providers::new(),
+            spoilers: Redaction::Placeholder,
+        }
+    }
+
+    /// Write decorations that Markdown lacks as HTML; true by default.
+    ///
+    /// If false, only their text is written.
+    pub fn html(mut self, html: bool) -> Self {
+        self.html = html;
+        self
+    }
+
+    /// Find the pages of embedded media with providers, which are
+    /// `Providers::new()` by default.
+    pub fn providers(mut self, providers: Providers) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    /// Choose how spoilers are written; `Redaction::Placeholder` by default.
+    pub fn spoilers(mut self, redaction: Redaction) -> Self {
+        self.spoilers = redaction;
+        self
+    }
+
+    fn write(&mut self, s: &str) -> IoResult<()> {
+        if let Some(c) = self.held.take() {
+            write!(self.out, "{}", c)?;
+            self.last = Some(c);
+        }
+        if let Some(c) = s.chars().next_back() {
+            self.last = Some(c);
+        }
+        self.out.write_all(s.as_bytes())
+    }
+
+    /// Write the prefix of the current line.
+    fn start_line(&mut self, trailing_space: bool) -> IoResult<()> {
+        let mut prefix = String::new();
+        for p in &mut self.prefixes {
+            prefix.push_str(if p.used { &p.rest } else { &p.first });
+            p.used = true;
+        }
+        let prefix = if trailing_space {
+            &prefix[..]
+        } else {
+            prefix.trim_end()
+        };
+        let prefix = prefix.to_string();
+        self.write(&prefix)?;
+        self.started = true;
+        self.line_start = true;
+        Ok(())
+    }
+
+    /// End the current line, if anything has been written to it.
+    fn end_line(&mut self) -> IoResult<()> {
+        if self.started {
+            self.write("\n")?;
+            self.started = false;
+        }
+        Ok(())
+    }
+
+    /// Write a line with only the prefix on it.
+    fn blank_line(&mut self) -> IoResult<()> {
+        self.end_line()?;
+        self.start_line(false)?;
+        self.end_line()
+    }
+
+    /// Write a whole line, like the fence of a code block.
+    fn line(&mut self, s: &str) -> IoResult<()> {
+        self.end_line()?;
+        if s.is_empty() {
+            self.start_line(false)?;
+        } else {
+            self.start_line(true)?;
+            self.write(s)?;
+        }
+        self.end_line()
+    }
+
+    /// Write the delimiters that have been closed.
+    fn write_closing(&mut self) -> IoResult<()> {
+        for delimiter in mem::take(&mut self.closing) {
+            self.write(&delimiter.close)?;
+        }
+        Ok(())
+    }
+
+    /// Close every delimiter, as inline markup cannot span blocks; they are
+    /// opened again before any more content.
+    fn close_all(&mut self) -> IoResult<()> {
+        self.write_closing()?;
+        for i in (0..self.open.len()).rev() {
+            if self.open[i].written {
+                let close = mem::take(&mut self.open[i].close);
+                self.write(&close)?;
+                self.open[i].close = close;
+                self.open[i].written = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the separator and delimiters that come before content
+    /// beginning with next.
+    ///
+    /// Returns whether next must be written as a character reference, for
+    /// the delimiters before it to be recognized.
+    fn before_content(&mut self, next: char) -> IoResult<bool> {
+        let mut encode_next = false;
+        if self.fresh {
+            self.write_closing()?;
+        } else {
+            match self.separator {
+                Separator::Nothing => {
+                    let markup: Vec<(&str, bool)> = self
+                        .closing
+                        .iter()
+                        .map(|d| (&d.close[..], false))
+                        .chain(
+                            self.open
+                                .iter()
+                                .filter(|d| !d.written)
+                                .map(|d| (&d.open[..], true)),
+                        )
+                        .collect();
+                    let prev = if self.line_start {
+                        None
+                    } else {
+                        self.held.or(self.last)
+                    };
+                    let (encode_prev, encode) = flanking(prev, &markup, Some(next));
+                    if encode_prev {
+                        if let Some(c) = self.held.take() {
+                            write!(self.out, "&#{};", c as u32)?;
+                        }
+                    }
+                    encode_next = encode;
+                    self.write_closing()?;
+                }
+                Separator::Spaces => {
+                    self.write_closing()?;
+                    let spaces = mem::take(&mut self.spaces);
+                    self.write(&spaces)?;
+                }
+                Separator::LineBreak => {
+                    self.write_closing()?;
+                    self.write("\\")?;
+                    self.end_line()?;
+                }
+                Separator::Paragraph => {
+                    self.close_all()?;
+                    self.blank_line()?;
+                }
+            }
+        }
+        self.separator = Separator::Nothing;
+        self.spaces.clear();
+        self.fresh = false;
+        self.after_list = None;
+
+        if !self.started {
+            self.start_line(true)?;
+        }
+        for i in 0..self.open.len() {
+            if !self.open[i].written {
+                let open = mem::take(&mut self.open[i].open);
+                self.write(&open)?;
+                self.open[i].open = open;
+                self.open[i].written = true;
+                self.line_start = false;
+            }
+        }
+        Ok(encode_next)
+    }
+
+    /// Write inline content that is already escaped and cannot begin with
+    /// a letter or digit.
+    fn content(&mut self, s: &str) -> IoResult<()> {
+        self.before_content(s.chars().next().unwrap_or(' '))?;
+        self.write(s)?;
+        self.line_start = false;
+        Ok(())
+    }
+
+    /// Whether a backtick was the last thing written, so a code span
+    /// written straight after it would begin with a longer run of them.
+    fn after_backtick(&self) -> bool {
+        self.separator == Separator::Nothing
+            && !self.fresh
+            && self.closing.is_empty()
+            && self.held.or(self.last) == Some('`')
+    }
+
+    /// Write a code span containing s, which must be a single line.
+    fn code_span(&mut self, s: &str) -> IoResult<()> {
+        if s.is_empty() {
+            return Ok(());
+        }
+        let ticks = "`".repeat(longest_run(s, '`') + 1);
+        // A space at each end keeps backticks in the code apart from those
+        // around it, and is removed from both, as is one from code that
+        // begins and ends with spaces.
+        let pad = if s.starts_with('`')
+            || s.ends_with('`')
+            || (s.starts_with(' ') && s.ends_with(' ') && !s.trim_start_matches(' ').is_empty())
+        {
+            " "
+        } else {
+            ""
+        };
+        self.content(&format!("{t}{p}{s}{p}{t}", t = ticks, p = pad, s = s))
+    }
+
+    fn open(&mut self, open: String, close: String) {
+        // Emphasis inside the same emphasis changes nothing, but `**` for
+        // two of `*` would be taken as strong emphasis.
+        let redundant = open.starts_with('*') && self.open.iter().any(|d| d.open == open);
+        let (open, close) = if redundant {
+            (String::new(), String::new())
+        } else {
+            (open, close)
+        };
+        let delimiter = Delimiter {
+            open,
+            close,
+            written: true,
+        };
+        if self.closing.last() == Some(&delimiter) {
+            self.closing.pop();
+            self.open.push(delimiter);
+        } else {
+            self.open.push(Delimiter {
+                written: false,
+                ..delimiter
+            });
+        }
+    }
+
+    fn close(&mut self) {
+        let delimiter = self.open.pop().expect("closed delimiter was never opened");
+        // Delimiters that were never written enclose nothing.
+        if delimiter.written {
+            self.closing.push(delimiter);
+        }
+    }
+
+    /// End the paragraph or block before a new block.
+    fn begin_block(&mut self) -> IoResult<()> {
+        if !self.fresh {
+            self.close_all()?;
+            self.end_line()?;
+            // A list item can go straight from its text into a block, and
+            // stays a tight list if it does.
+            let in_item = self.prefixes.last().is_some_and(|p| p.item);
+            if !in_item || self.separator == Separator::Paragraph {
+                self.blank_line()?;
+            }
+        }
+        self.separator = Separator::Nothing;
+        self.spaces.clear();
+        Ok(())
+    }
+
+    /// After a block, anything more needs a new paragraph.
+    fn end_block(&mut self) {
+        self.fresh = false;
+        self.separator = Separator::Paragraph;
+        self.spaces.clear();
+    }
+
+    /// Begin a block whose lines are prefixed.
+    fn push_prefix(&mut self, first: String, rest: String, item: bool) {
+        self.prefixes.push(Prefix {
+            first,
+            rest,
+            used: false,
+            item,
+        });
+        self.fresh = true;
+    }
+
+    /// End a block begun by `push_prefix`.
+    fn pop_prefix(&mut self) -> IoResult<()> {
+        self.close_all()?;
+        // An empty block still needs a line to exist at all.
+        if self.fresh && !self.started {
+            self.start_line(false)?;
+        }
+        self.end_line()?;
+        self.prefixes.pop();
+        Ok(())
+    }
+
+    /// The text of a table cell, on one line, in bold if `bold`.
+    fn cell(&self, cell: &TableCell, bold: bool) -> IoResult<String> {
+        let mut out = vec![];
+        {
+            let mut inner = Markdown::new(&mut out)
+                .html(self.html)
+                .providers(self.providers.clone())
+                .spoilers(self.spoilers);
+            inner.inline = true;
+            if bold {
+                inner.render(&vec![Segment::Decorated {
+                    style: DecorationStyle::Bold,
+                    text: cell.body.clone(),
+                }])?;
+            } else {
+                inner.render(&cell.body)?;
+            }
+        }
+        let text = String::from_utf8(out).expect("rendered invalid UTF-8");
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let text = lines.join(if self.html { "<br>" } else { " " });
+        Ok(text.replace('|', "\\|"))
+    }
+
+    fn write_text(&mut self, s: &str) -> IoResult<()> {
+        let mut lines = s.split('\n').peekable();
+        let mut first = true;
+        while let Some(line) = lines.next() {
+            if !first && !self.fresh {
+                self.separator = match self.separator {
+                    Separator::Nothing | Separator::Spaces => Separator::LineBreak,
+                    Separator::LineBreak | Separator::Paragraph => Separator::Paragraph,
+                };
+                self.spaces.clear();
+            }
+            first = false;
+
+            let mut rest = line.trim_end_matches('\r');
+            while !rest.is_empty() {
+                let is_space = rest.starts_with(char::is_whitespace);
+                let end = rest
+                    .find(|c: char| c.is_whitespace() != is_space)
+                    .unwrap_or(rest.len());
+                let (token, tail) = rest.split_at(end);
+                if !is_space {
+                    let first = token.chars().next().unwrap();
+                    let encode = self.before_content(first)?;
+                    let last = tail.is_empty() && lines.peek().is_none();
+                    let mut escaped = escape(token, self.line_start, last, self.headings > 0);
+                    if encode {
+                        escaped = format!("&#{};{}", first as u32, &escaped[first.len_utf8()..]);
+                    }
+                    // The last character may need to be a character
+                    // reference, depending on what follows it.
+                    match escaped.pop() {
+                        Some(c) if c.is_alphanumeric() => {
+                            self.write(&escaped)?;
+                            self.held = Some(c);
+                        }
+                        Some(c) => {
+                            escaped.push(c);
+                            self.write(&escaped)?;
+                        }
+                        None => {}
+                    }
+                    self.line_start = false;
+                } else if !self.fresh {
+                    // Closing delimiters belong to the text before the space.
+                    self.write_closing()?;
+                    if let Separator::Nothing | Separator::Spaces = self.separator {
+                        self.separator = Separator::Spaces;
+                        self.spaces.push_str(token);
+                    }
+                }
+                rest = tail;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether c is neither whitespace nor punctuation, as CommonMark decides
+/// whether `*` can open or close emphasis; symbols count as punctuation.
+fn is_word(c: Option<char>) -> bool {
+    c.is_some_and(char::is_alphanumeric)
+}
+
+fn is_punctuation(c: Option<char>) -> bool {
+    c.is_some_and(|c| !c.is_alphanumeric() && !c.is_whitespace())
+}
+
+/// Check that emphasis delimiters in markup can open and close emphasis,
+/// where markup is written between prev and next as pieces which are
+/// opening delimiters if true.
+///
+/// `*` can only open emphasis if it is not between a letter or digit and
+/// punctuation, and only close it if it is not between punctuation and a
+/// letter or digit. Returns whether prev and next must be written as
+/// character references, which count as punctuation, to fix that.
+fn flanking(prev: Option<char>, markup: &[(&str, bool)], next: Option<char>) -> (bool, bool) {
+    let pieces: Vec<_> = markup.iter().filter(|p| !p.0.is_empty()).collect();
+    let is_stars = |p: &&&(&str, bool)| p.0.bytes().all(|b| b == b'*');
+    let lead = pieces.iter().take_while(is_stars).count();
+    let trail = pieces.iter().rev().take_while(is_stars).count();
+    if lead == 0 && trail == 0 {
+        return (false, false);
+    }
+
+    // The characters after the first run of `*` and before the last.
+    let (after_lead, before_trail) = if lead == pieces.len() {
+        (next, prev)
+    } else {
+        (
+            pieces[lead].0.chars().next(),
+            pieces[pieces.len() - trail - 1].0.chars().next_back(),
+        )
+    };
+    let opens = pieces[..lead].iter().any(|p| p.1);
+    let closes = pieces[pieces.len() - trail..].iter().any(|p| !p.1);
+    (
+        opens && is_word(prev) && is_punctuation(after_lead),
+        closes && is_punctuation(before_trail) && is_word(next),
+    )
+}
+
+/// Escape a word of text, which comes first on its line if `line_start`,
+/// may be followed by markup if `last`, and is in a heading if `heading`.
+fn escape(word: &str, line_start: bool, last: bool, heading: bool) -> String {
+    let mut escaped = String::with_capacity(word.len());
+    for (i, c) in word.char_indices() {
+        let escape = match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '~' => true,
+            // Entity and numeric character references.
+            '&' => word[i + 1..].starts_with(|c: char| c == '#' || c.is_ascii_alphanumeric()),
+            // `!` followed by a link would make it an image.
+            '!' => last && i + 1 == word.len(),
+            // Headings, list items and setext heading underlines; `#` could
+            // also end a heading.
+            '#' => heading || (line_start && i == 0),
+            '-' | '+' | '=' => line_start && i == 0,
+            // Ordered list items.
+            '.' | ')' => line_start && i > 0 && word[..i].bytes().all(|b| b.is_ascii_digit()),
+            _ => false,
+        };
+        if escape {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether url can be written as an autolink, `<url>`.
+fn is_autolink(url: &str) -> bool {
+    let scheme = match url.find(':') {
+        Some(i) => &url[..i],
+        None => return false,
+    };
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'.' || b == b'-')
+        && !url
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '<' || c == '>')
+}
+
+/// Write url as the destination of a link or image.
+fn destination(url: &str) -> String {
+    let mut dest = String::with_capacity(url.len());
+    for c in url.chars() {
+        match c {
+            '\\' | '(' | ')' | '<' | '>' => {
+                dest.push('\\');
+                dest.push(c);
+            }
+            c if c.is_whitespace() || c.is_control() => {
+                let mut buf = [0; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    dest.push_str(&format!("%{:02X}", b));
+                }
+            }
+            c => dest.push(c),
+        }
+    }
+    dest
+}
+
+/// Whether any of segments is written as a block, other than code.
+fn has_block(segments: &[Segment]) -> bool {
+    segments.iter().any(|segment| match *segment {
+        Segment::Decorated {
+            style: DecorationStyle::Center,
+            ..
+        } => true,
+        Segment::Decorated { ref text, .. } | Segment::Link { ref text, .. } => has_block(text),
+        Segment::Custom { ref body, .. } | Segment::Spoiler { ref body, .. } => has_block(body),
+        Segment::Quote { .. }
+        | Segment::List { .. }
+        | Segment::Heading { .. }
+        | Segment::Rule
+        | Segment::Table { .. } => true,
+        Segment::Text(_) | Segment::Code(_) | Segment::Image { .. } | Segment::Embed { .. } => {
+            false
+        }
+    })
+}
+
+/// The longest run of `c` in s.
+fn longest_run(s: &str, c: char) -> usize {
+    s.split(|x| x != c)
+        .map(|run| run.len() / c.len_utf8())
+        .max()
+        .unwrap_or(0)
+}
+
+impl<O: Write> Renderer for Markdown<O> {
+    type Err = Error;
+
+    fn render(&mut self, segments: &Vec<Segment>) -> Result<Self::Err> {
+        self.depth += 1;
+        for segment in segments {
+            match *segment {
+                Segment::Link {
+                    ref target,
+                    ref text,
+                } if self.links == 0
+                    && is_autolink(target)
+                    && matches!(text[..], [Segment::Text(ref t)] if t == target) =>
+                {
+                    self.content(&format!("<{}>", target))?
+                }
+                Segment::Decorated {
+                    style: DecorationStyle::Monospace,
+                    ref text,
+                } if !self.after_backtick()
+                    && matches!(text[..], [Segment::Text(ref t)] if !t.contains('\n')) =>
+                {
+                    if let [Segment::Text(ref t)] = text[..] {
+                        self.code_span(t)?
+                    }
+                }
+                // A heading is a single line, so one with blocks inside is
+                // written as bold text instead.
+                Segment::Heading { ref text, .. } if has_block(text) => {
+                    self.begin_block()?;
+                    self.fresh = true;
+                    self.decoration_begin(DecorationStyle::Bold)?;
+                    self.render(text)?;
+                    self.decoration_end(DecorationStyle::Bold)?;
+                    self.end_block();
+                }
+                _ => self.render_segment(segment)?,
+            }
+        }
+        self.depth -= 1;
+
+        if self.depth == 0 {
+            // Nothing else may follow, so write everything held back.
+            self.write_closing()?;
+            self.write("")?;
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, s: &str) -> IoResult<()> {
+        if self.headings > 0 || self.inline {
+            // A heading is a single line.
+            self.write_text(&s.replace('\n', " "))
+        } else {
+            self.write_text(s)
+        }
+    }
+
+    fn decoration_begin(&mut self, style: DecorationStyle) -> IoResult<()> {
+        use DecorationStyle::*;
+
+        let (open, close) = match style {
+            Bold => ("**".to_string(), "**"),
+            Italic => ("*".to_string(), "*"),
+            _ if !self.html => (String::new(), ""),
+            Underline => ("<u>".to_string(), "</u>"),
+            Color(r, g, b) => (
+                format!(r#"<span style="color: #{:02x}{:02x}{:02x}">"#, r, g, b),
+                "</span>",
+            ),
+            Size(s) => (format!(r#"<span style="font-size: {}px">"#, s), "</span>"),
+            Subscript => ("<sub>".to_string(), "</sub>"),
+            Superscript => ("<sup>".to_string(), "</sup>"),
+            Strikethrough => ("<s>".to_string(), "</s>"),
+            Monospace => (
+                r#"<span style="font-family: monospace">"#.to_string(),
+                "</span>",
+            ),
+            Center => {
+                // Markdown in an HTML block is only parsed after a blank
+                // line.
+                self.begin_block()?;
+                self.line(r#"<div align="center">"#)?;
+                self.blank_line()?;
+                self.fresh = true;
+                return Ok(());
+            }
+        };
+        self.open(open, close.to_string());
+        Ok(())
+    }
+
+    fn decoration_end(&mut self, style: DecorationStyle) -> IoResult<()> {
+        if style == DecorationStyle::Center && self.html {
+            self.close_all()?;
+            if !self.fresh {
+                self.blank_line()?;
+            }
+            self.line("</div>")?;
+            self.end_block();
+        } else {
+            self.close();
+        }
+        Ok(())
+    }
+
+    fn quote_begin(&mut self, attribution: &Option<&str>) -> IoResult<()> {
+        if let Some(name) = *attribution {
+            self.begin_block()?;
+            self.fresh = true;
+            self.write_text(name)?;
+            self.write_text(" wrote:")?;
+        }
+        self.begin_block()?;
+        self.push_prefix("> ".to_string(), "> ".to_string(), false);
+        Ok(())
+    }
+
+    fn quote_end(&mut self, _attribution: &Option<&str>) -> IoResult<()> {
+        self.pop_prefix()?;
+        self.end_block();
+        Ok(())
+    }
+
+    fn code(&mut self, s: &str) -> IoResult<()> {
+        if self.headings > 0 || self.inline {
+            return self.code_span(&s.trim_matches('\n').replace('\n', " "));
+        }
+
+        let s = s.strip_prefix('\n').unwrap_or(s);
+        let s = s.strip_suffix('\n').unwrap_or(s);
+        let fence = "`".repeat(std::cmp::max(3, longest_run(s, '`') + 1));
+
+        self.begin_block()?;
+        self.line(&fence)?;
+        for line in s.split('\n') {
+            self.line(line.trim_end_matches('\r'))?;
+        }
+        self.line(&fence)?;
+        self.end_block();
+        Ok(())
+    }
+
+    fn list_begin(&mut self, style: ListStyle) -> IoResult<()> {
+        let ordered = |style| style != ListStyle::Unordered;
+        let alternate = match self.after_list.take() {
+            Some(ref list) if ordered(list.style) == ordered(style) => !list.alternate,
+            _ => false,
+        };
+        self.begin_block()?;
+        self.lists.push(List {
+            style,
+            items: 0,
+            alternate,
+        });
+        self.fresh = true;
+        Ok(())
+    }
+
+    fn list_item_begin(&mut self, _style: ListStyle) -> IoResult<()> {
+        let marker = {
+            let list = self.lists.last_mut().expect("item outside list");
+            list.items += 1;
+            match (list.style, list.alternate) {
+                (ListStyle::Unordered, false) => "- ".to_string(),
+                (ListStyle::Unordered, true) => "* ".to_string(),
+                (_, false) => format!("{}. ", list.items),
+                (_, true) => format!("{}) ", list.items),
+            }
+        };
+        self.close_all()?;
+        self.end_line()?;
+        self.separator = Separator::Nothing;
+        self.spaces.clear();
+        let indent = " ".repeat(marker.len());
+        self.push_prefix(marker, indent, true);
+        Ok(())
+    }
+
+    fn list_item_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        self.pop_prefix()
+    }
+
+    fn list_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        let list = self.lists.pop().expect("list ended twice");
+        if list.items > 0 {
+            self.end_block();
+            self.after_list = Some(list);
+        }
+        Ok(())
+    }
+
+    fn link_begin(&mut self, target: &str) -> IoResult<()> {
+        // Links cannot contain links, so only the outermost is kept.
+        if self.links == 0 {
+            self.open("[".to_string(), format!("]({})", destination(target)));
+        } else {
+            self.open(String::new(), String::new());
+        }
+        self.links += 1;
+        Ok(())
+    }
+
+    fn link_end(&mut self, _target: &str) -> IoResult<()> {
+        self.links -= 1;
+        self.close();
+        Ok(())
+    }
+
+    fn image(&mut self, src: &str) -> IoResult<()> {
+        self.content(&format!("![]({})", destination(src)))
+    }
+
+    fn heading_begin(&mut self, level: u8) -> IoResult<()> {
+        self.begin_block()?;
+        let marker = "#".repeat(usize::from(level.clamp(1, 6)));
+        self.push_prefix(format!("{} ", marker), String::new(), false);
+        self.headings += 1;
+        Ok(())
+    }
+
+    fn heading_end(&mut self, _level: u8) -> IoResult<()> {
+        self.headings -= 1;
+        self.pop_prefix()?;
+        self.end_block();
+        Ok(())
+    }
+
+    fn rule(&mut self) -> IoResult<()> {
+        // Unlike `---` or `***`, `___` can't be taken as a setext heading
+        // underline or list item.
+        self.begin_block()?;
+        self.line("___")?;
+        self.end_block();
+        Ok(())
+    }
+
+    fn embed(&mut self, provider: &str, id: &str, params: &[(Cow<str>, Cow<str>)]) -> IoResult<()> {
+        let link = embed_link(&self.providers, provider, id, params);
+        self.render(&link.into_iter().collect())
+    }
+
+    fn spoiler(&mut self, title: Option<&str>, body: &Vec<Segment>) -> IoResult<()> {
+        match self.spoilers {
+            Redaction::Blank => Ok(()),
+            Redaction::Placeholder => self.text(&spoiler_placeholder(title)),
+            Redaction::Keep => self.render(body),
+        }
+    }
+
+    fn table(&mut self, rows: &[Vec<TableCell>]) -> IoResult<()> {
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return Ok(());
+        }
+        let header = rows[0].iter().all(|cell| cell.header);
+        let mut lines = vec![];
+        if !header {
+            lines.push(vec![String::new(); columns]);
+        }
+        for (i, row) in rows.iter().enumerate() {
+            let mut line = vec![];
+            for cell in row {
+                line.push(self.cell(cell, cell.header && (i > 0 || !header))?);
+            }
+            line.resize(columns, String::new());
+            lines.push(line);
+        }
+        lines.insert(1, vec!["---".to_string(); columns]);
+
+        self.begin_block()?;
+        for cells in lines {
+            self.line(&format!("| {} |", cells.join(" | ")))?;
+        }
+        self.end_block();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate pulldown_cmark;
+
+    use super::super::super::parse;
+    use super::super::{Redaction, Renderer};
+    use super::Markdown;
+
+    fn markdown(input: &str) -> String {
+        let mut out = vec![];
+        Markdown::new(&mut out).render(&parse(input)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn html(markdown: &str) -> String {
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(markdown));
+        html
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(markdown("[b]a[/b] [i] b [/i]c"), "**a**  *b* c");
+        assert_eq!(markdown("[b]a[i]b[/i][/b][b][/b]"), "**a*b***");
+        // Adjacent emphasis is joined, as `**a****b**` is not bold.
+        assert_eq!(markdown("[b]a[/b][b]b[/b]"), "**ab**");
+        assert_eq!(
+            html(&markdown("[b]a[/b][i]b[/i]")),
+            "<p><strong>a</strong><em>b</em></p>\n"
+        );
+        // `*` between punctuation and a letter cannot open or close
+        // emphasis, unless the letter is written as a character reference.
+        assert_eq!(markdown("[b]Note:[/b]x"), "**Note:**&#120;");
+        assert_eq!(markdown("a[i]\"b\"[/i]"), "&#97;*\"b\"*");
+        // Emphasis cannot span paragraphs.
+        assert_eq!(markdown("[b]a\nb\n\nc[/b]"), "**a\\\nb**\n\n**c**");
+    }
+
+    #[test]
+    fn escapes_text() {
+        let input =
+            "*a* _b_ `c` [d](e) <f> &amp; \\ ~g~ # h\n# i\n- j\n+ k\n1. l\n2) m\n===\n    n!";
+        let output = markdown(&format!("[b]{}[/b][url=x]y[/url]", input));
+        let html = html(&output);
+        let expected = input
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace("\n    ", "\n")
+            .replace('\n', "<br />\n");
+        assert_eq!(
+            html,
+            format!("<p><strong>{}</strong><a href=\"x\">y</a></p>\n", expected)
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            markdown("a[quote=\"*A*\"]b\n\nc[quote]d[/quote][/quote]e"),
+            "a\n\n\\*A\\* wrote:\n\n> b\n>\n> c\n>\n> > d\n\ne"
+        );
+        assert_eq!(markdown("[quote][/quote]"), ">\n");
+    }
+
+    #[test]
+    fn code() {
+        assert_eq!(
+            markdown("a[code]\nx = *y*\n\n```\n[/code]"),
+            "a\n\n````\nx = *y*\n\n```\n````\n"
+        );
+        assert_eq!(
+            markdown("[quote][code]a\n\nb[/code][/quote]"),
+            "> ```\n> a\n>\n> b\n> ```\n"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            markdown("[list][*]a\nb[*][list=1][*]c[*]d[/list]e[*][/list]"),
+            "- a\\\n  b\n- 1. c\n  2. d\n\n  e\n-\n"
+        );
+        assert_eq!(markdown("[list=a][*]a[/list]"), "1. a\n");
+        // Adjacent lists are marked differently, or they would be one list.
+        assert_eq!(
+            markdown("[list][*]a[/list][list][*]b[/list][list=1][*]c[/list][list=1][*]d[/list]"),
+            "- a\n\n* b\n\n1. c\n\n1) d\n"
+        );
+    }
+
+    #[test]
+    fn links_and_images() {
+        assert_eq!(
+            markdown("[url]http://a[/url] [url=b (c)][b]d[/b][/url][url=e][/url]"),
+            "<http://a> [**d**](b%20\\(c\\))"
+        );
+        assert_eq!(markdown("[url]a b[/url]"), "[a b](a%20b)");
+        assert_eq!(
+            markdown("[url=x]a\n\nb[url]http://y[/url][/url]"),
+            "[a](x)\n\n[bhttp://y](x)"
+        );
+        assert_eq!(markdown("Look![img]a.png[/img]"), "Look\\!![](a.png)");
+        assert_eq!(
+            markdown("[media]vimeo.com/76979871[/media]"),
+            "<https://vimeo.com/76979871>"
+        );
+    }
+
+    #[test]
+    fn spoilers() {
+        let input = "[spoiler=*Who*][b]Snape[/b][/spoiler]";
+        assert_eq!(markdown(input), "\\[spoiler: \\*Who\\*\\]");
+        for &(redaction, expected) in &[(Redaction::Blank, ""), (Redaction::Keep, "**Snape**")] {
+            let mut out = vec![];
+            Markdown::new(&mut out)
+                .spoilers(redaction)
+                .render(&parse(input))
+                .unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn headings_and_rules() {
+        assert_eq!(
+            markdown("a[h1]b[/h1]\nc[h3]#d# [code]`e[/code]\nf[/h3][hr][list][*]g[hr][/list]"),
+            "a\n\n# b\n\nc\n\n### \\#d\\# `` `e `` f\n\n___\n\n- g\n  ___\n"
+        );
+        assert_eq!(markdown("[h1]a[quote]b[/quote][/h1]"), "**a**\n\n> **b**\n");
+        assert_eq!(
+            html(&markdown("[h2]a #[/h2][hr][h6][/h6][list][*]b[hr][/list]")),
+            "<h2>a #</h2>\n<hr />\n<h6></h6>\n<ul>\n<li>b\n<hr />\n</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            markdown("a[table][tr][th]x|y[/th][th][/th][/tr][tr][td][b]b[/b]\nc[/td][th]d[/th][/tr][/table]e"),
+            "a\n\n| x\\|y |  |\n| --- | --- |\n| **b** c | **d** |\n\ne"
+        );
+        assert_eq!(
+            markdown("[table][tr][td][code]a|b[/code][list][*]c[/list][/td][/tr][tr][/tr][/table]"),
+            "|  |\n| --- |\n| `a\\|b`<br>- c |\n|  |\n"
+        );
+        assert_eq!(markdown("[table][/table]"), "");
+
+        let mut html = String::new();
+        let markdown = markdown("[table][tr][th]a[/th][/tr][tr][td]`|`[/td][/tr][/table]");
+        let parser =
+            pulldown_cmark::Parser::new_ext(&markdown, pulldown_cmark::Options::ENABLE_TABLES);
+        pulldown_cmark::html::push_html(&mut html, parser);
+        assert_eq!(
+            html,
+            "<table><thead><tr><th>a</th></tr></thead><tbody>\n<tr><td>`|`</td></tr>\n</tbody></table>\n"
+        );
+    }
+
+    #[test]
+    fn html_fallback() {
+        let input = "[u]a[/u] [color=red]b[/color] [size=12]c[/size][center]d[/center]";
+        assert_eq!(
+            markdown(input),
+            "<u>a</u> <span style=\"color: #ff0000\">b</span> \
+             <span style=\"font-size: 12px\">c</span>\n\n\
+             <div align=\"center\">\n\nd\n\n</div>\n"
+        );
+
+        let mut out = vec![];
+        Markdown::new(&mut out)
+            .html(false)
+            .render(&parse(input))
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a b cd");
+
+        assert_eq!(
+            markdown("[s]a[/s][sub]b[/sub][sup]c[/sup][tt][b]d[/b][/tt]"),
+            "<s>a</s><sub>b</sub><sup>c</sup>\
+             <span style=\"font-family: monospace\">**d**</span>"
+        );
+    }
+
+    #[test]
+    fn code_spans() {
+        assert_eq!(markdown("a [mono]*b*[/mono] c"), "a `*b*` c");
+        assert_eq!(markdown("[tt]``a`[/tt]"), "``` ``a` ```");
+        assert_eq!(markdown("[tt] a [/tt] [tt]  [/tt]"), "`  a  ` `  `");
+        assert_eq!(
+            markdown("[tt]a[/tt][tt]b[/tt]"),
+            "`a`<span style=\"font-family: monospace\">b</span>"
+        );
+        assert_eq!(
+            html(&markdown("[tt] a [/tt]x[tt]`[/tt]")),
+            "<p><code> a </code>x<code>`</code></p>\n"
+        );
+        assert_eq!(
+            markdown("[tt]a\nb[/tt]"),
+            "<span style=\"font-family: monospace\">a\\\nb</span>"
+        );
+    }
+}
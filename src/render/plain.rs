@@ -0,0 +1,686 @@
+use super::super::{DecorationStyle, ListStyle, Providers, Segment, TableCell};
+use super::lines::Lines;
+use super::{embed_link, spoiler_placeholder, Redaction, Renderer, Result};
+use std::borrow::Cow;
+use std::io::{Error, Result as IoResult, Write};
+
+/// Renders segments as readable plain text.
+///
+/// Decorations are dropped, leaving their text, except that struck-through
+/// text has U+0336 combined with each character, and subscripts and
+/// superscripts are written with the Unicode forms of digits and `+-=()`,
+/// like `²`. Quotes are written on lines
+/// of their own prefixed with `> `, after a line naming who wrote them.
+/// List items are marked with `-`, numbers or letters according to their
+/// style, with the lines of each item indented to match. Code is indented by
+/// four spaces, links are written as `text (target)` and images as
+/// `[image: src]`. Headings are written on lines of their own, underlined
+/// with `=` or `-` at the first two levels, and rules as a line of `-`.
+/// Embedded media is written as the URL of its page, and spoilers according
+/// to a `Redaction`. Tables are drawn with `|`, `-` and `+` in columns as
+/// wide as their widest cells, with a line of `=` under a first row of
+/// header cells.
+///
+/// Text is written as it is by default; with `PlainText::wrap`, lines are
+/// wrapped at spaces to fit a width. Code and tables are never wrapped.
+///
+/// ```
+/// use bbcode::parse;
+/// use bbcode::render::{PlainText, Renderer};
+///
+/// let segments = parse(
+///     "[quote=\"Al\"]Look at [url=example.com]this[/url]![/quote]\n\
+///      [list=1][*]one\n[*][b]two[/b][/list]",
+/// );
+/// let mut out = vec![];
+/// PlainText::new(&mut out).render(&segments).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "Al wrote:\n> Look at this (example.com)!\n1. one\n2. two\n"
+/// );
+/// ```
+pub struct PlainText<O: Write> {
+    out: O,
+    width: Option<usize>,
+    /// Decorations enclosing the current text, outermost first.
+    styles: Vec<DecorationStyle>,
+    lines: Lines,
+    /// Characters written to this line, including the prefix.
+    column: usize,
+    /// Whitespace to be written before the next word, unless the line ends
+    /// or wraps first.
+    spaces: String,
+    /// Text is written as it is, without wrapping.
+    verbatim: bool,
+    /// The width of the longest line so far of a heading to be underlined.
+    heading: Option<usize>,
+    providers: Providers,
+    spoilers: Redaction,
+}
+
+/// The width of a rule, unless lines are wrapped narrower.
+const RULE_WIDTH: usize = 40;
+
+/// The combining character striking through the one before it.
+const STRIKE: char = '\u{336}';
+
+/// Characters with superscript forms, and those forms.
+const SUPERSCRIPTS: [(char, char); 15] = [
+    ('0', '⁰'),
+    ('1', '¹'),
+    ('2', '²'),
+    ('3', '³'),
+    ('4', '⁴'),
+    ('5', '⁵'),
+    ('6', '⁶'),
+    ('7', '⁷'),
+    ('8', '⁸'),
+    ('9', '⁹'),
+    ('+', '⁺'),
+    ('-', '⁻'),
+    ('=', '⁼'),
+    ('(', '⁽'),
+    (')', '⁾'),
+];
+
+/// Characters with subscript forms, and those forms.
+const SUBSCRIPTS: [(char, char); 15] = [
+    ('0', '₀'),
+    ('1', '₁'),
+    ('2', '₂'),
+    ('3', '₃'),
+    ('4', '₄'),
+    ('5', '₅'),
+    ('6', '₆'),
+    ('7', '₇'),
+    ('8', '₈'),
+    ('9', '₉'),
+    ('+', '₊'),
+    ('-', '₋'),
+    ('=', '₌'),
+    ('(', '₍'),
+    (')', '₎'),
+];
+
+impl<O: Write> PlainText<O> {
+    pub fn new(out: O) -> Self {
+        PlainText {
+            out,
+            width: None,
+            styles: vec![],
+            lines: Lines::new(),
+            column: 0,
+            spaces: String::new(),
+            verbatim: false,
+            heading: None,
+            providers: Providers::new(),
+            spoilers: Redaction::Placeholder,
+        }
+    }
+
+    /// Wrap lines at spaces so they are at most width characters long,
+    /// where possible.
+    pub fn wrap(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Find the pages of embedded media with providers, which are
+    /// `Providers::new()` by default.
+    pub fn providers(mut self, providers: Providers) -> Self {
+        self.providers = providers;
+        self
+    }
+
+    /// Choose how spoilers are written; `Redaction::Placeholder` by default.
+    pub fn spoilers(mut self, redaction: Redaction) -> Self {
+        self.spoilers = redaction;
+        self
+    }
+
+    /// Write the prefix of the current line.
+    fn start_line(&mut self, trailing_space: bool) -> IoResult<()> {
+        let prefix = self.lines.start(trailing_space);
+        self.out.write_all(prefix.as_bytes())?;
+        self.column = prefix.chars().count();
+        Ok(())
+    }
+
+    /// End the current line.
+    fn newline(&mut self) -> IoResult<()> {
+        if !self.lines.started {
+            self.start_line(false)?;
+        }
+        if let Some(width) = self.heading {
+            let line = self.column.saturating_sub(self.lines.prefix_len());
+            self.heading = Some(width.max(line));
+        }
+        self.out.write_all(b"\n")?;
+        self.lines.started = false;
+        self.column = 0;
+        self.spaces.clear();
+        Ok(())
+    }
+
+    /// End the current line if anything has been written to it, as at the
+    /// start or end of a block.
+    fn break_line(&mut self) -> IoResult<()> {
+        if self.lines.started {
+            self.newline()?;
+        }
+        self.spaces.clear();
+        Ok(())
+    }
+
+    /// Write text which may span several lines.
+    fn write_text(&mut self, s: &str) -> IoResult<()> {
+        for (i, line) in self.lines.split(s).enumerate() {
+            if i > 0 {
+                self.newline()?;
+            }
+            self.write_line(line)?;
+        }
+        Ok(())
+    }
+
+    /// Write text within a line.
+    fn write_line(&mut self, line: &str) -> IoResult<()> {
+        if self.verbatim {
+            if !line.is_empty() {
+                if !self.lines.started {
+                    self.start_line(true)?;
+                }
+                self.out.write_all(line.as_bytes())?;
+                self.column += line.chars().count();
+            }
+            return Ok(());
+        }
+
+        let mut rest = line;
+        while !rest.is_empty() {
+            let is_space = rest.starts_with(char::is_whitespace);
+            let end = rest
+                .find(|c: char| c.is_whitespace() != is_space)
+                .unwrap_or(rest.len());
+            let (token, tail) = rest.split_at(end);
+            if !is_space {
+                self.write_word(token)?;
+            } else if !self.lines.trim {
+                self.spaces.push_str(token);
+            }
+            rest = tail;
+        }
+        Ok(())
+    }
+
+    /// Write a word, wrapping the line first if it doesn't fit.
+    fn write_word(&mut self, word: &str) -> IoResult<()> {
+        let len = self.spaces.chars().count() + width(word);
+        if let Some(width) = self.width {
+            let has_words = self.lines.started && self.column > self.lines.prefix_len();
+            if has_words && self.column + len > width {
+                self.newline()?;
+            }
+        }
+        if !self.lines.started {
+            self.start_line(true)?;
+        }
+        write!(self.out, "{}{}", self.spaces, word)?;
+        self.column += self.spaces.chars().count() + width(word);
+        self.spaces.clear();
+        self.lines.trim = false;
+        Ok(())
+    }
+
+    /// Begin a block whose lines are prefixed.
+    fn push_prefix(&mut self, first: String, rest: String) -> IoResult<()> {
+        self.break_line()?;
+        self.lines.push_prefix(first, rest);
+        Ok(())
+    }
+
+    /// End a block begun by `push_prefix`.
+    fn pop_prefix(&mut self) -> IoResult<()> {
+        if self.lines.ends_line() {
+            self.newline()?;
+        }
+        self.spaces.clear();
+        self.lines.pop_prefix();
+        Ok(())
+    }
+
+    /// Write a whole line of its own, like a rule.
+    fn write_rule(&mut self, rule: &str) -> IoResult<()> {
+        self.write_rules(&[rule.to_string()])
+    }
+
+    /// Write whole lines of their own, like the lines of a table.
+    fn write_rules(&mut self, rules: &[String]) -> IoResult<()> {
+        self.break_line()?;
+        for rule in rules {
+            self.start_line(true)?;
+            self.out.write_all(rule.as_bytes())?;
+            self.column += width(rule);
+            self.newline()?;
+        }
+        self.lines.skip_newline = true;
+        Ok(())
+    }
+
+    /// The lines of the body of a table cell, written with the same
+    /// decorations, providers and redaction.
+    fn cell_lines(&self, body: &Vec<Segment>) -> IoResult<Vec<String>> {
+        let mut out = vec![];
+        {
+            let mut cell = PlainText::new(&mut out)
+                .providers(self.providers.clone())
+                .spoilers(self.spoilers);
+            cell.styles = self.styles.clone();
+            cell.render(body)?;
+        }
+        let text = String::from_utf8(out).expect("rendered invalid UTF-8");
+        let mut lines: Vec<String> = text.lines().map(|l| l.trim_end().to_string()).collect();
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        Ok(lines)
+    }
+}
+
+/// The lines drawing a table of cells, each given as its lines of text.
+fn draw_table(cells: &[Vec<Vec<String>>], header: bool) -> Vec<String> {
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for row in cells {
+        for (width, lines) in widths.iter_mut().zip(row) {
+            *width = lines
+                .iter()
+                .map(|l| self::width(l))
+                .fold(*width, usize::max);
+        }
+    }
+    let border = |fill: &str| {
+        widths
+            .iter()
+            .fold("+".to_string(), |line, &w| line + &fill.repeat(w + 2) + "+")
+    };
+
+    let mut lines = vec![border("-")];
+    for (i, row) in cells.iter().enumerate() {
+        let height = row.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        for n in 0..height {
+            let mut line = "|".to_string();
+            for (c, &w) in widths.iter().enumerate() {
+                let text = row.get(c).and_then(|l| l.get(n)).map_or("", |l| &l[..]);
+                line.push(' ');
+                line.push_str(text);
+                line.push_str(&" ".repeat(w - width(text) + 1));
+                line.push('|');
+            }
+            lines.push(line);
+        }
+        lines.push(border(if i == 0 && header { "=" } else { "-" }));
+    }
+    lines
+}
+
+/// The number of columns taken by a word, which may be struck through.
+fn width(word: &str) -> usize {
+    word.chars().filter(|&c| c != STRIKE).count()
+}
+
+/// Text raised or lowered by the innermost superscript or subscript among
+/// the decorations `styles`, if there is one; only digits and `+-=()` have
+/// forms for both.
+pub fn shift<'s>(styles: &[DecorationStyle], s: &'s str) -> Cow<'s, str> {
+    let forms: &[(char, char)] = match styles.iter().rev().find(|style| {
+        **style == DecorationStyle::Superscript || **style == DecorationStyle::Subscript
+    }) {
+        Some(DecorationStyle::Superscript) => &SUPERSCRIPTS,
+        Some(_) => &SUBSCRIPTS,
+        None => return Cow::Borrowed(s),
+    };
+    s.chars()
+        .map(|c| {
+            forms
+                .iter()
+                .find(|form| form.0 == c)
+                .map_or(c, |form| form.1)
+        })
+        .collect()
+}
+
+/// Strike through every character of s other than whitespace.
+fn strike(s: &str) -> String {
+    let mut struck = String::with_capacity(s.len() * 3);
+    for c in s.chars() {
+        struck.push(c);
+        if !c.is_whitespace() {
+            struck.push(STRIKE);
+        }
+    }
+    struck
+}
+
+/// The marker of the nth item of a list, counting from 1.
+pub fn item_marker(style: ListStyle, n: usize) -> String {
+    match style {
+        ListStyle::Unordered => "- ".to_string(),
+        ListStyle::Numeric => format!("{}. ", n),
+        ListStyle::Alphabetic => {
+            // a to z, then aa to zz and so on.
+            let mut letters = vec![];
+            let mut n = n;
+            while n > 0 {
+                n -= 1;
+                letters.push(b'a' + (n % 26) as u8);
+                n /= 26;
+            }
+            letters.reverse();
+            format!("{}. ", String::from_utf8(letters).unwrap())
+        }
+    }
+}
+
+impl<O: Write> Renderer for PlainText<O> {
+    type Err = Error;
+
+    fn render(&mut self, segments: &Vec<Segment>) -> Result<Self::Err> {
+        for segment in segments {
+            match *segment {
+                // The text of `[url]` is its target, so it need not be
+                // written twice.
+                Segment::Link {
+                    ref target,
+                    ref text,
+                } if matches!(text[..], [Segment::Text(ref t)] if t == target) => {
+                    self.write_text(target)?
+                }
+                _ => self.render_segment(segment)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn text(&mut self, s: &str) -> IoResult<()> {
+        let s = shift(&self.styles, s);
+        if self.styles.contains(&DecorationStyle::Strikethrough) {
+            self.write_text(&strike(&s))
+        } else {
+            self.write_text(&s)
+        }
+    }
+
+    fn decoration_begin(&mut self, style: DecorationStyle) -> IoResult<()> {
+        self.styles.push(style);
+        Ok(())
+    }
+
+    fn decoration_end(&mut self, _style: DecorationStyle) -> IoResult<()> {
+        self.styles.pop();
+        Ok(())
+    }
+
+    fn quote_begin(&mut self, attribution: &Option<&str>) -> IoResult<()> {
+        self.break_line()?;
+        if let Some(name) = *attribution {
+            self.lines.skip_newline = false;
+            self.write_text(name)?;
+            self.write_text(" wrote:")?;
+        }
+        self.push_prefix("> ".to_string(), "> ".to_string())
+    }
+
+    fn quote_end(&mut self, _attribution: &Option<&str>) -> IoResult<()> {
+        self.pop_prefix()
+    }
+
+    fn code(&mut self, s: &str) -> IoResult<()> {
+        self.push_prefix("    ".to_string(), "    ".to_string())?;
+        self.verbatim = true;
+        let s = s.strip_suffix('\n').unwrap_or(s);
+        self.write_text(s)?;
+        self.verbatim = false;
+        self.pop_prefix()
+    }
+
+    fn list_begin(&mut self, _style: ListStyle) -> IoResult<()> {
+        self.break_line()?;
+        self.lines.list_begin();
+        Ok(())
+    }
+
+    fn list_item_begin(&mut self, style: ListStyle) -> IoResult<()> {
+        let marker = item_marker(style, self.lines.next_item());
+        let indent = " ".repeat(marker.chars().count());
+        self.push_prefix(marker, indent)
+    }
+
+    fn list_item_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        self.pop_prefix()
+    }
+
+    fn list_end(&mut self, _style: ListStyle) -> IoResult<()> {
+        self.lines.list_end();
+        self.lines.skip_newline = true;
+        Ok(())
+    }
+
+    fn link_begin(&mut self, _target: &str) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn link_end(&mut self, target: &str) -> IoResult<()> {
+        self.spaces.push(' ');
+        self.write_text(&format!("({})", target))
+    }
+
+    fn image(&mut self, src: &str) -> IoResult<()> {
+        self.write_text(&format!("[image: {}]", src))
+    }
+
+    fn heading_begin(&mut self, level: u8) -> IoResult<()> {
+        self.push_prefix(String::new(), String::new())?;
+        if level <= 2 {
+            self.heading = Some(0);
+        }
+        Ok(())
+    }
+
+    fn heading_end(&mut self, level: u8) -> IoResult<()> {
+        self.pop_prefix()?;
+        match self.heading.take() {
+            Some(width) if width > 0 => {
+                let underline = if level <= 1 { "=" } else { "-" };
+                self.write_rule(&underline.repeat(width))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn rule(&mut self) -> IoResult<()> {
+        let width = self.width.unwrap_or(RULE_WIDTH).min(RULE_WIDTH);
+        let width = width.saturating_sub(self.lines.prefix_len()).max(3);
+        self.write_rule(&"-".repeat(width))
+    }
+
+    fn embed(&mut self, provider: &str, id: &str, params: &[(Cow<str>, Cow<str>)]) -> IoResult<()> {
+        let link = embed_link(&self.providers, provider, id, params);
+        self.render(&link.into_iter().collect())
+    }
+
+    fn spoiler(&mut self, title: Option<&str>, body: &Vec<Segment>) -> IoResult<()> {
+        match self.spoilers {
+            Redaction::Blank => Ok(()),
+            Redaction::Placeholder => self.write_text(&spoiler_placeholder(title)),
+            Redaction::Keep => self.render(body),
+        }
+    }
+
+    fn table(&mut self, rows: &[Vec<TableCell>]) -> IoResult<()> {
+        if rows.iter().all(Vec::is_empty) {
+            return Ok(());
+        }
+        let mut cells = vec![];
+        for row in rows {
+            let mut lines = vec![];
+            for cell in row {
+                lines.push(self.cell_lines(&cell.body)?);
+            }
+            cells.push(lines);
+        }
+        let header = rows[0].iter().all(|cell| cell.header) && !rows[0].is_empty();
+        self.write_rules(&draw_table(&cells, header && rows.len() > 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::parse;
+    use super::super::{Redaction, Renderer};
+    use super::PlainText;
+
+    fn plain(input: &str) -> String {
+        let mut out = vec![];
+        PlainText::new(&mut out).render(&parse(input)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn wrapped(width: usize, input: &str) -> String {
+        let mut out = vec![];
+        PlainText::new(&mut out)
+            .wrap(width)
+            .render(&parse(input))
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn text_and_decorations() {
+        assert_eq!(
+            plain("[b]Hello[/b], [color=red]world[/color]!\nBye"),
+            "Hello, world!\nBye"
+        );
+        assert_eq!(
+            plain("H[sub]2[/sub]O, x[sup]2(n+1)[/sup], a[sub]1[sup]-2[/sup]3[/sub], [tt]ok[/tt]"),
+            "H₂O, x²⁽n⁺¹⁾, a₁⁻²₃, ok"
+        );
+        assert_eq!(
+            plain("[s]no way[/s]!"),
+            "n\u{336}o\u{336} w\u{336}a\u{336}y\u{336}!"
+        );
+        assert_eq!(
+            wrapped(5, "[s]ab cd[/s] e"),
+            "a\u{336}b\u{336} c\u{336}d\u{336}\ne"
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            plain("Before\n[quote=\"A\"]\nOne\n\nTwo\n[quote]Nested[/quote]\n[/quote]\nAfter"),
+            "Before\nA wrote:\n> One\n>\n> Two\n> > Nested\nAfter"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            plain("[list][*] One\n[*] Two\nlines\n[*][list=a][*]x[*]y[/list][/list]"),
+            "- One\n- Two\n  lines\n- a. x\n  b. y\n"
+        );
+        assert_eq!(plain("[list=1][*]x[*][/list]"), "1. x\n2.\n");
+        let items = "[*]x".repeat(28);
+        let letters = plain(&format!("[list=a]{}[/list]", items));
+        assert!(letters.starts_with("a. x\nb. x\n"));
+        assert!(letters.ends_with("z. x\naa. x\nab. x\n"));
+    }
+
+    #[test]
+    fn code_links_and_images() {
+        assert_eq!(
+            plain("Run:[code]\ncargo test\n  --all\n[/code]"),
+            "Run:\n    cargo test\n      --all\n"
+        );
+        assert_eq!(
+            plain("[url=http://a]the site[/url] or [url]http://b[/url] [img]c.png[/img]"),
+            "the site (http://a) or http://b [image: c.png]"
+        );
+        assert_eq!(
+            plain("Watch [youtube]youtu.be/dQw4w9WgXcQ?t=3[/youtube]"),
+            "Watch https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=3s"
+        );
+    }
+
+    #[test]
+    fn spoilers() {
+        let input = "Ends [spoiler=Who]Snape[/spoiler] [hide]x[/hide].";
+        assert_eq!(plain(input), "Ends [spoiler: Who] [spoiler].");
+        for &(redaction, expected) in &[
+            (Redaction::Blank, "Ends  ."),
+            (Redaction::Keep, "Ends Snape x."),
+        ] {
+            let mut out = vec![];
+            PlainText::new(&mut out)
+                .spoilers(redaction)
+                .render(&parse(input))
+                .unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn headings_and_rules() {
+        assert_eq!(
+            plain("Intro[h1] Getting started[/h1]\nText[h2]Setup[/h2][h3]Notes[/h3][hr]End"),
+            "Intro\nGetting started\n===============\nText\nSetup\n-----\nNotes\n\
+             ----------------------------------------\nEnd"
+        );
+        assert_eq!(
+            wrapped(10, "[quote][h1]aaa bbbbbb c[/h1][hr][/quote]"),
+            "> aaa\n> bbbbbb c\n> ========\n> --------\n"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            plain("Stats:[table]\n[tr][th]Name[/th][th]HP[/th][/tr]\n[tr][td][b]Orc[/b][/td][td]15[/td][/tr]\n[tr][td]Giant\nrat[/td][/tr]\n[/table]\nEnd"),
+            "Stats:\n\
+             +-------+----+\n\
+             | Name  | HP |\n\
+             +=======+====+\n\
+             | Orc   | 15 |\n\
+             +-------+----+\n\
+             | Giant |    |\n\
+             | rat   |    |\n\
+             +-------+----+\n\
+             End"
+        );
+        assert_eq!(
+            wrapped(
+                4,
+                "[quote][table][tr][td]a b c[/td][td][/td][/tr][/table][/quote]"
+            ),
+            "> +-------+--+\n> | a b c |  |\n> +-------+--+\n"
+        );
+        assert_eq!(plain("a[table][/table]b[table][tr][/tr][/table]c"), "abc");
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(
+            wrapped(12, "The quick brown fox jumps   over the lazy dog"),
+            "The quick\nbrown fox\njumps   over\nthe lazy dog"
+        );
+        assert_eq!(
+            wrapped(10, "[quote]aaa bbb ccc[/quote][list][*]dd ee ff[/list]"),
+            "> aaa bbb\n> ccc\n- dd ee ff\n"
+        );
+        assert_eq!(
+            wrapped(6, "unbreakable words[code]long code line[/code]"),
+            "unbreakable\nwords\n    long code line\n"
+        );
+    }
+}
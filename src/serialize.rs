@@ -0,0 +1,310 @@
+//! Serde support, with the `serde` feature.
+//!
+//! Segments are written as maps tagged with their `type`, which would need
+//! `Text` and `Code` to be struct variants if derived; they go through the
+//! `Repr` types here instead, which mirror `Segment` with that change.
+
+use super::{DecorationStyle, ListStyle, Segment, TableCell};
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+use std::borrow::Cow;
+
+/// `Segment` borrowed for serialization.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ReprRef<'s, 'a: 's> {
+    Text {
+        text: &'s str,
+    },
+    Decorated {
+        style: DecorationStyle,
+        text: &'s [Segment<'a>],
+    },
+    Quote {
+        attribution: Option<&'s str>,
+        body: &'s [Segment<'a>],
+    },
+    Code {
+        code: &'s str,
+    },
+    List {
+        style: ListStyle,
+        items: &'s [Vec<Segment<'a>>],
+    },
+    Link {
+        target: &'s str,
+        text: &'s [Segment<'a>],
+    },
+    Image {
+        src: &'s str,
+    },
+    Custom {
+        name: &'s str,
+        attrs: &'s [(Cow<'a, str>, Cow<'a, str>)],
+        body: &'s [Segment<'a>],
+    },
+    Heading {
+        level: u8,
+        text: &'s [Segment<'a>],
+    },
+    Rule,
+    Embed {
+        provider: &'s str,
+        id: &'s str,
+        params: &'s [(Cow<'a, str>, Cow<'a, str>)],
+    },
+    Spoiler {
+        title: Option<&'s str>,
+        body: &'s [Segment<'a>],
+    },
+    Table {
+        rows: &'s [Vec<TableCell<'a>>],
+    },
+}
+
+/// `Segment` as deserialized.
+///
+/// Strings are always owned, so deserialized segments do not borrow from the
+/// input and can be read from any source.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Repr<'a> {
+    Text {
+        text: Cow<'a, str>,
+    },
+    Decorated {
+        style: DecorationStyle,
+        text: Vec<Segment<'a>>,
+    },
+    Quote {
+        attribution: Option<Cow<'a, str>>,
+        body: Vec<Segment<'a>>,
+    },
+    Code {
+        code: Cow<'a, str>,
+    },
+    List {
+        style: ListStyle,
+        items: Vec<Vec<Segment<'a>>>,
+    },
+    Link {
+        target: Cow<'a, str>,
+        text: Vec<Segment<'a>>,
+    },
+    Image {
+        src: Cow<'a, str>,
+    },
+    Custom {
+        name: Cow<'a, str>,
+        attrs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+        body: Vec<Segment<'a>>,
+    },
+    Heading {
+        level: u8,
+        text: Vec<Segment<'a>>,
+    },
+    Rule,
+    Embed {
+        provider: Cow<'a, str>,
+        id: Cow<'a, str>,
+        params: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    },
+    Spoiler {
+        title: Option<Cow<'a, str>>,
+        body: Vec<Segment<'a>>,
+    },
+    Table {
+        rows: Vec<Vec<TableCell<'a>>>,
+    },
+}
+
+impl<'a> Serialize for Segment<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Segment::Text(ref text) => ReprRef::Text { text },
+            Segment::Decorated { style, ref text } => ReprRef::Decorated { style, text },
+            Segment::Quote {
+                ref attribution,
+                ref body,
+            } => ReprRef::Quote {
+                attribution: attribution.as_deref(),
+                body,
+            },
+            Segment::Code(ref code) => ReprRef::Code { code },
+            Segment::List { style, ref items } => ReprRef::List { style, items },
+            Segment::Link {
+                ref target,
+                ref text,
+            } => ReprRef::Link { target, text },
+            Segment::Image { ref src } => ReprRef::Image { src },
+            Segment::Custom {
+                ref name,
+                ref attrs,
+                ref body,
+            } => ReprRef::Custom { name, attrs, body },
+            Segment::Heading { level, ref text } => ReprRef::Heading { level, text },
+            Segment::Rule => ReprRef::Rule,
+            Segment::Embed {
+                ref provider,
+                ref id,
+                ref params,
+            } => ReprRef::Embed {
+                provider,
+                id,
+                params,
+            },
+            Segment::Spoiler {
+                ref title,
+                ref body,
+            } => ReprRef::Spoiler {
+                title: title.as_deref(),
+                body,
+            },
+            Segment::Table { ref rows } => ReprRef::Table { rows },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, 'a> Deserialize<'de> for Segment<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Text { text } => Segment::Text(text),
+            Repr::Decorated { style, text } => Segment::Decorated { style, text },
+            Repr::Quote { attribution, body } => Segment::Quote { attribution, body },
+            Repr::Code { code } => Segment::Code(code),
+            Repr::List { style, items } => Segment::List { style, items },
+            Repr::Link { target, text } => Segment::Link { target, text },
+            Repr::Image { src } => Segment::Image { src },
+            Repr::Custom { name, attrs, body } => Segment::Custom { name, attrs, body },
+            Repr::Heading { level, text } => Segment::Heading { level, text },
+            Repr::Rule => Segment::Rule,
+            Repr::Embed {
+                provider,
+                id,
+                params,
+            } => Segment::Embed {
+                provider,
+                id,
+                params,
+            },
+            Repr::Spoiler { title, body } => Segment::Spoiler { title, body },
+            Repr::Table { rows } => Segment::Table { rows },
+        })
+    }
+}
+
+/// Write a color as `#rrggbb`.
+pub fn serialize_color<S: Serializer>(
+    r: &u8,
+    g: &u8,
+    b: &u8,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("#{:02x}{:02x}{:02x}", r, g, b))
+}
+
+/// Read a color written as `#rrggbb`.
+pub fn deserialize_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<(u8, u8, u8), D::Error> {
+    let s = Cow::<str>::deserialize(deserializer)?;
+    let component = |i: usize| {
+        s.get(i..i + 2)
+            .filter(|c| c.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+    };
+    if s.len() == 7 && s.starts_with('#') {
+        if let (Some(r), Some(g), Some(b)) = (component(1), component(3), component(5)) {
+            return Ok((r, g, b));
+        }
+    }
+    Err(D::Error::custom(format_args!(
+        "invalid color {:?}, expected #rrggbb",
+        s
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use super::super::{parse, DecorationStyle, OwnedSegment, Parser, TagHandler};
+
+    struct Reveal;
+
+    impl TagHandler for Reveal {
+        fn name(&self) -> &str {
+            "reveal"
+        }
+    }
+
+    #[test]
+    fn tagged_representation() {
+        let segments = parse(
+            "a[quote=\"B\"][color=#ff8000]c[/color][size=12]d[/size][/quote]\
+             [list=a][*][url=e]f[/url][/list][code]g[/code][img]h[/img]",
+        );
+        assert_eq!(
+            serde_json::to_value(&segments).unwrap(),
+            serde_json::json!([
+                {"type": "text", "text": "a"},
+                {"type": "quote", "attribution": "B", "body": [
+                    {"type": "decorated", "style": {"color": "#ff8000"}, "text": [
+                        {"type": "text", "text": "c"},
+                    ]},
+                    {"type": "decorated", "style": {"size": 12}, "text": [
+                        {"type": "text", "text": "d"},
+                    ]},
+                ]},
+                {"type": "list", "style": "alphabetic", "items": [[
+                    {"type": "link", "target": "e", "text": [
+                        {"type": "text", "text": "f"},
+                    ]},
+                ]]},
+                {"type": "code", "code": "g"},
+                {"type": "image", "src": "h"},
+            ])
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "[b]x[/b][center][u]y[/u][/center][quote]z[/quote][list][*][i]w[/i][/list]\
+                     [h2]v[/h2][hr][sub]u[/sub][s]t[/s][tt]s[/tt]\
+                     [media]https://vimeo.com/1/ab#t=5[/media][spoiler=r]q[/spoiler]\
+                     [table][tr][th]p[/th][td][b]o[/b][/td][/tr][/table]";
+        let segments = parse(input);
+        let json = serde_json::to_string(&segments).unwrap();
+        let back: Vec<OwnedSegment> = serde_json::from_reader(json.as_bytes()).unwrap();
+        assert_eq!(back, segments);
+    }
+
+    #[test]
+    fn custom_tags() {
+        let parser = Parser::new().register(Reveal);
+        let segments = parser.parse("[reveal=\"Act 2\" open=yes]x[/reveal]");
+        let json = serde_json::to_value(&segments).unwrap();
+        assert_eq!(
+            json[0]["attrs"],
+            serde_json::json!([["", "Act 2"], ["open", "yes"]])
+        );
+        let back: Vec<OwnedSegment> = serde_json::from_value(json).unwrap();
+        assert_eq!(back, segments);
+    }
+
+    #[test]
+    fn invalid_styles() {
+        for style in &[
+            r##"{"color":"#12345"}"##,
+            r#"{"color":"red"}"#,
+            r#"{"size":0}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<DecorationStyle>(style).is_err(),
+                "{}",
+                style
+            );
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Locations of segments in the input.
+//!
+//! `Parser::parse_spanned` produces, alongside the usual segments, a tree of
+//! `Spans` with the same shape, giving where each segment and its tags came
+//! from.
+
+/// A location in the input.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord, Default)]
+pub struct Position {
+    /// Offset in bytes from the start of the input.
+    pub offset: usize,
+    /// Line number, from 1. Lines are separated by `\n`.
+    pub line: usize,
+    /// Column number in the line, from 1, counting Unicode scalar values.
+    pub column: usize,
+}
+
+/// A range of the input, from `start` up to but not including `end`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// Whether the byte at `offset` lies within the span.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start.offset <= offset && offset < self.end.offset
+    }
+
+    /// The text of the input covered by the span.
+    pub fn as_str<'a>(&self, input: &'a str) -> &'a str {
+        &input[self.start.offset..self.end.offset]
+    }
+}
+
+/// Where a segment came from in the input.
+///
+/// Spans form a tree matching that of the segments they describe: each of
+/// `children` corresponds to a segment in the body of this one. A list has
+/// one child for each item, spanning from its `[*]` to the start of the next
+/// item, with the segments of the item as its own children. A table has one
+/// child for each row, spanning from `[tr]` to `[/tr]`, with one child for
+/// each cell, whose children are the segments of the cell.
+///
+/// ```
+/// use bbcode::Parser;
+///
+/// let input = "Hi [b]you[/b]";
+/// let (_, spans) = Parser::new().parse_spanned(input);
+/// let bold = &spans[1];
+/// assert_eq!(bold.span.as_str(input), "[b]you[/b]");
+/// assert_eq!(bold.open.unwrap().as_str(input), "[b]");
+/// assert_eq!(bold.close.unwrap().as_str(input), "[/b]");
+/// assert_eq!(bold.children[0].span.start.column, 7);
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct Spans {
+    /// The whole segment, including any tags.
+    pub span: Span,
+    /// The opening tag, or item marker of a list item; `None` for text.
+    pub open: Option<Span>,
+    /// The closing tag, if there is one.
+    pub close: Option<Span>,
+    pub children: Vec<Spans>,
+}
+
+/// Computes positions from offsets into the input.
+///
+/// Finding a position takes time proportional to the distance from the one
+/// before it, so locating offsets in increasing order takes linear time
+/// overall.
+pub struct Locator<'a> {
+    input: &'a str,
+    last: Position,
+}
+
+impl<'a> Locator<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Locator {
+            input,
+            last: Position {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    /// Find the position of an offset, which must lie on a char boundary.
+    pub fn locate(&mut self, offset: usize) -> Position {
+        if offset < self.last.offset {
+            *self = Locator::new(self.input);
+        }
+        for c in self.input[self.last.offset..offset].chars() {
+            if c == '\n' {
+                self.last.line += 1;
+                self.last.column = 1;
+            } else {
+                self.last.column += 1;
+            }
+        }
+        self.last.offset = offset;
+        self.last
+    }
+
+    /// Find the span between two offsets.
+    pub fn span(&mut self, start: usize, end: usize) -> Span {
+        Span {
+            start: self.locate(start),
+            end: self.locate(end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse, Parser};
+    use super::Locator;
+
+    #[test]
+    fn positions() {
+        let mut locator = Locator::new("ab\nçd\n");
+        let p = locator.locate(5);
+        assert_eq!((p.line, p.column), (2, 2));
+        assert_eq!(locator.locate(7).line, 3);
+        assert_eq!(locator.locate(1).column, 2);
+    }
+
+    #[test]
+    fn nested_spans() {
+        let input = "a[b]b[i]c[/i][/b][code]x[/code][url]y[/url]";
+        let (segments, spans) = Parser::new().parse_spanned(input);
+        assert_eq!(segments, parse(input));
+        assert_eq!(spans.len(), 4);
+
+        assert_eq!(spans[0].span.as_str(input), "a");
+        assert_eq!(spans[0].open, None);
+        let italic = &spans[1].children[1];
+        assert_eq!(italic.span.as_str(input), "[i]c[/i]");
+        assert_eq!(italic.close.unwrap().as_str(input), "[/i]");
+        assert_eq!(italic.children[0].span.as_str(input), "c");
+        assert_eq!(spans[2].open.unwrap().as_str(input), "[code]");
+        assert!(spans[2].children.is_empty());
+        assert_eq!(spans[3].children[0].span.as_str(input), "y");
+    }
+
+    #[test]
+    fn list_item_spans() {
+        let input = "[list][*]a\n[*][b]b[/b][/list]";
+        let (segments, spans) = Parser::new().parse_spanned(input);
+        assert_eq!(segments, parse(input));
+
+        let items = &spans[0].children;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].span.as_str(input), "[*]a\n");
+        assert_eq!(items[0].open.unwrap().as_str(input), "[*]");
+        assert_eq!(items[1].span.start.line, 2);
+        assert_eq!(items[1].children[0].span.as_str(input), "[b]b[/b]");
+        assert_eq!(spans[0].close.unwrap().start.column, 12);
+    }
+
+    #[test]
+    fn table_spans() {
+        let input = "[table]\n[tr] [td]a[/td][th][b]b[/b][/th][/tr]\n[tr][/tr][/table]";
+        let (segments, spans) = Parser::new().parse_spanned(input);
+        assert_eq!(segments, parse(input));
+
+        let rows = &spans[0].children;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].span.as_str(input),
+            "[tr] [td]a[/td][th][b]b[/b][/th][/tr]"
+        );
+        assert_eq!(rows[0].close.unwrap().as_str(input), "[/tr]");
+        assert_eq!(rows[0].children[1].open.unwrap().as_str(input), "[th]");
+        assert_eq!(
+            rows[0].children[1].children[0].span.as_str(input),
+            "[b]b[/b]"
+        );
+        assert!(rows[1].children.is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+//! Spoilers, hidden until the reader chooses to see them.
+
+#[cfg(test)]
+use super::parse;
+#[cfg(test)]
+use super::Segment::{Spoiler, Text};
+
+// Recognizes the start of the opening tag of a spoiler, giving the name of
+// the tag that closes it: `[spoiler` or `[hide`.
+//
+// The rest of the tag is either `]`, or a title like a link target:
+// `[spoiler="Title"]` or `[spoiler=Title]`.
+named!(pub head(&str) -> &'static str,
+    alt!(value!("spoiler", tag_no_case!("[spoiler"))
+        | value!("hide", tag_no_case!("[hide"))
+    )
+);
+
+#[test]
+fn untitled_spoiler() {
+    assert_eq!(
+        parse("[spoiler]Snape[/spoiler][HIDE][/hide]"),
+        vec![
+            Spoiler {
+                title: None,
+                body: vec![Text("Snape".into())],
+            },
+            Spoiler {
+                title: None,
+                body: vec![],
+            },
+        ]
+    );
+}
+
+#[test]
+fn titled_spoiler() {
+    assert_eq!(
+        parse("[spoiler=Book 6]a[/spoiler][hide=\"[x]\"]b[/hide][spoiler=]c[/spoiler]"),
+        vec![
+            Spoiler {
+                title: Some("Book 6".into()),
+                body: vec![Text("a".into())],
+            },
+            Spoiler {
+                title: Some("[x]".into()),
+                body: vec![Text("b".into())],
+            },
+            Spoiler {
+                title: Some("".into()),
+                body: vec![Text("c".into())],
+            },
+        ]
+    );
+}
+
+#[test]
+fn mismatched_spoiler() {
+    assert_eq!(
+        parse("[spoiler]a[/hide][spoilers]b[hide=c"),
+        vec![Text("[spoiler]a[/hide][spoilers]b[hide=c".into())]
+    );
+}
@@ -0,0 +1,104 @@
+//! Tables of rows and cells.
+
+use super::Segment;
+
+#[cfg(test)]
+use super::parse;
+#[cfg(test)]
+use super::DecorationStyle;
+#[cfg(test)]
+use super::Segment::{Decorated, Table, Text};
+
+/// A cell in a row of a table.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TableCell<'a> {
+    /// Whether this is a header cell, `[th]`, rather than a data cell, `[td]`.
+    pub header: bool,
+    pub body: Vec<Segment<'a>>,
+}
+
+impl<'a> TableCell<'a> {
+    /// Copy any borrowed strings, like `Segment::into_owned`.
+    pub fn into_owned(self) -> TableCell<'static> {
+        TableCell {
+            header: self.header,
+            body: self.body.into_iter().map(Segment::into_owned).collect(),
+        }
+    }
+}
+
+// Recognizes the opening tag of a table.
+//
+// The body of a table is a sequence of rows, `[tr]...[/tr]`, each holding a
+// sequence of cells, `[th]...[/th]` or `[td]...[/td]`, followed by
+// `[/table]`. Only whitespace may come between them.
+named!(pub head(&str) -> &str, tag_no_case!("[table]"));
+
+#[cfg(test)]
+fn cell(header: bool, text: &str) -> TableCell<'_> {
+    TableCell {
+        header,
+        body: vec![Text(text.into())],
+    }
+}
+
+#[test]
+fn table_with_rows() {
+    assert_eq!(
+        parse("[table]\n  [tr][th]Name[/th][TH]HP[/th][/tr]\n  [tr][td]Orc[/td][td]15[/td][/tr]\n[/table]."),
+        vec![
+            Table {
+                rows: vec![
+                    vec![cell(true, "Name"), cell(true, "HP")],
+                    vec![cell(false, "Orc"), cell(false, "15")],
+                ],
+            },
+            Text(".".into()),
+        ]
+    );
+}
+
+#[test]
+fn empty_table() {
+    assert_eq!(
+        parse("[table][/table][table] [tr][/tr] [/table]"),
+        vec![Table { rows: vec![] }, Table { rows: vec![vec![]] },]
+    );
+}
+
+#[test]
+fn nested_in_cells() {
+    assert_eq!(
+        parse("[table][tr][td][b]a[table][tr][td]b[/td][/tr][/table][/b][/td][/tr][/table]"),
+        vec![Table {
+            rows: vec![vec![TableCell {
+                header: false,
+                body: vec![Decorated {
+                    style: DecorationStyle::Bold,
+                    text: vec![
+                        Text("a".into()),
+                        Table {
+                            rows: vec![vec![cell(false, "b")]],
+                        },
+                    ],
+                }],
+            }]],
+        }]
+    );
+}
+
+#[test]
+fn text_outside_cells() {
+    for input in &[
+        "[table]a[tr][td]b[/td][/tr][/table]",
+        "[table][tr]a[td]b[/td][/tr][/table]",
+        "[table][tr][td]b[/td]a[/tr][/table]",
+        "[table][tr][td]b[/td][/tr]a[/table]",
+        "[table][td]b[/td][/table]",
+        "[table][tr][td]b[/tr][/table]",
+        "[td]b[/td]",
+    ] {
+        assert_eq!(parse(input), vec![Text((*input).into())]);
+    }
+}
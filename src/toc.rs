@@ -0,0 +1,246 @@
+//! Tables of contents.
+
+use super::visit::{walk_segment, Visitor};
+use super::{ListStyle, OwnedSegment, Segment};
+use std::collections::{HashMap, HashSet};
+
+/// A heading in a table of contents, with the headings under it.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct TocEntry {
+    /// The level of the heading, from 1 to 6.
+    pub level: u8,
+    /// The text of the heading, without markup and with whitespace collapsed.
+    pub title: String,
+    /// An anchor for the heading, unique within the document.
+    pub anchor: String,
+    /// The headings of greater level that follow this one, up to the next
+    /// heading at its level or above.
+    pub children: Vec<TocEntry>,
+}
+
+/// Collect the headings in segments into a nested outline.
+///
+/// Headings are found throughout the tree, in order, and each one goes under
+/// the last heading before it of a lower level, if there is one; skipping a
+/// level, like `[h3]` right after `[h1]`, makes no difference. Anchors are
+/// assigned by `Anchors`, which gives the same ones as the ids `SimpleHtml`
+/// gives headings when rendering the same segments.
+///
+/// ```
+/// use bbcode::{parse, toc};
+///
+/// let outline = toc(&parse(
+///     "[h1]Intro[/h1][h2]Setup[/h2]text[h2]Usage[/h2][h1]Intro[/h1]",
+/// ));
+/// assert_eq!(outline.len(), 2);
+/// assert_eq!(outline[0].children[1].title, "Usage");
+/// assert_eq!(outline[0].anchor, "intro");
+/// assert_eq!(outline[1].anchor, "intro-1");
+/// ```
+pub fn toc(segments: &[Segment]) -> Vec<TocEntry> {
+    let mut outline = Outline {
+        entries: vec![],
+        anchors: Anchors::new(),
+    };
+    outline.visit_segments(segments);
+    outline.entries
+}
+
+/// A table of contents as an unordered list, with an item linking to each
+/// heading by its anchor and nested lists for the headings under it.
+///
+/// An empty outline gives no segments at all.
+///
+/// ```
+/// use bbcode::{parse, toc, toc_list};
+///
+/// let outline = toc(&parse("[h1]A[/h1][h2]B[/h2]"));
+/// assert_eq!(
+///     toc_list(&outline),
+///     parse("[list][*][url=#a]A[/url][list][*][url=#b]B[/url][/list][/list]")
+/// );
+/// ```
+pub fn toc_list(entries: &[TocEntry]) -> Vec<OwnedSegment> {
+    if entries.is_empty() {
+        return vec![];
+    }
+    let items = entries
+        .iter()
+        .map(|entry| {
+            let mut item = vec![Segment::Link {
+                target: format!("#{}", entry.anchor).into(),
+                text: vec![Segment::Text(entry.title.clone().into())],
+            }];
+            item.extend(toc_list(&entry.children));
+            item
+        })
+        .collect();
+    vec![Segment::List {
+        style: ListStyle::Unordered,
+        items,
+    }]
+}
+
+/// Assigns anchors to the headings of a document from their titles.
+///
+/// An anchor is the title in lowercase, keeping only letters, digits, `-`
+/// and `_`, with a hyphen between words; a title without any of those gets
+/// `section`. Later headings with the same anchor have `-1`, `-2` and so on
+/// appended, so every anchor depends only on the headings before it.
+#[derive(Debug, Clone, Default)]
+pub struct Anchors {
+    used: HashSet<String>,
+    /// The next suffix to try for each anchor that has been given, so that
+    /// many headings with one title don't retry every suffix before theirs.
+    next: HashMap<String, usize>,
+}
+
+impl Anchors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The anchor for the next heading, with title `title`.
+    pub fn anchor(&mut self, title: &str) -> String {
+        let words: Vec<String> = title
+            .split_whitespace()
+            .map(|word| {
+                word.chars()
+                    .filter(|&c| c.is_alphanumeric() || c == '-' || c == '_')
+                    .flat_map(char::to_lowercase)
+                    .collect::<String>()
+            })
+            .filter(|word| !word.is_empty())
+            .collect();
+        let base = if words.is_empty() {
+            "section".to_owned()
+        } else {
+            words.join("-")
+        };
+
+        let mut n = self.next.get(&base).cloned().unwrap_or(0);
+        let mut anchor = base.clone();
+        if n > 0 {
+            anchor = format!("{}-{}", base, n);
+        }
+        // A suffixed anchor may have been taken by a title like `a 1`.
+        while self.used.contains(&anchor) {
+            n += 1;
+            anchor = format!("{}-{}", base, n);
+        }
+        self.next.insert(base, n + 1);
+        self.used.insert(anchor.clone());
+        anchor
+    }
+}
+
+/// The title of a heading with text segments: all of the text inside
+/// them, with whitespace collapsed.
+pub fn title(text: &[Segment]) -> String {
+    struct Title(String);
+
+    impl<'s> Visitor<'s> for Title {
+        fn visit_segment(&mut self, segment: &'s Segment<'s>) {
+            match *segment {
+                Segment::Text(ref s) | Segment::Code(ref s) => self.0.push_str(s),
+                _ => walk_segment(self, segment),
+            }
+        }
+    }
+
+    let mut title = Title(String::new());
+    title.visit_segments(text);
+    title.0.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Collects headings into entries as they are visited.
+struct Outline {
+    entries: Vec<TocEntry>,
+    anchors: Anchors,
+}
+
+impl<'s> Visitor<'s> for Outline {
+    fn visit_segment(&mut self, segment: &'s Segment<'s>) {
+        if let Segment::Heading { level, ref text } = *segment {
+            let title = title(text);
+            let entry = TocEntry {
+                level,
+                anchor: self.anchors.anchor(&title),
+                title,
+                children: vec![],
+            };
+            let mut entries = &mut self.entries;
+            while entries.last().is_some_and(|last| last.level < level) {
+                entries = &mut entries.last_mut().unwrap().children;
+            }
+            entries.push(entry);
+        }
+        walk_segment(self, segment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse;
+    use super::{toc, Anchors, TocEntry};
+
+    fn entry(level: u8, title: &str, anchor: &str, children: Vec<TocEntry>) -> TocEntry {
+        TocEntry {
+            level,
+            title: title.into(),
+            anchor: anchor.into(),
+            children,
+        }
+    }
+
+    #[test]
+    fn nests_by_level() {
+        let segments = parse(
+            "[h2]A[/h2][h1]B[/h1][h3]C[/h3][h2]D[/h2][h3]E[/h3]\
+             [quote][h1]F[/h1][/quote][list][*][h2]G[/h2][/list]",
+        );
+        assert_eq!(
+            toc(&segments),
+            vec![
+                entry(2, "A", "a", vec![]),
+                entry(
+                    1,
+                    "B",
+                    "b",
+                    vec![
+                        entry(3, "C", "c", vec![]),
+                        entry(2, "D", "d", vec![entry(3, "E", "e", vec![])]),
+                    ]
+                ),
+                entry(1, "F", "f", vec![entry(2, "G", "g", vec![])]),
+            ]
+        );
+    }
+
+    #[test]
+    fn titles() {
+        let outline = toc(&parse(
+            "[h1] Getting [b]started[/b]\n with [code]cargo[/code] [/h1]\
+             [h1][url=x]Q&A[/url][img]y.png[/img][/h1]",
+        ));
+        assert_eq!(outline[0].title, "Getting started with cargo");
+        assert_eq!(outline[1].title, "Q&A");
+    }
+
+    #[test]
+    fn anchors() {
+        let mut anchors = Anchors::new();
+        assert_eq!(anchors.anchor("Hello, World!"), "hello-world");
+        assert_eq!(anchors.anchor("Hello -- world"), "hello----world");
+        assert_eq!(anchors.anchor("Ünïcode_ok 2"), "ünïcode_ok-2");
+        assert_eq!(anchors.anchor("?!"), "section");
+        assert_eq!(anchors.anchor(""), "section-1");
+        assert_eq!(anchors.anchor("Hello world"), "hello-world-1");
+        assert_eq!(anchors.anchor("hello-world-1"), "hello-world-1-1");
+        assert_eq!(anchors.anchor("HELLO WORLD"), "hello-world-2");
+        assert_eq!(anchors.anchor("a 2"), "a-2");
+        assert_eq!(anchors.anchor("a"), "a");
+        assert_eq!(anchors.anchor("a"), "a-1");
+        assert_eq!(anchors.anchor("a"), "a-3");
+    }
+}
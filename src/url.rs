@@ -1,74 +1,272 @@
-use super::{segment, Segment};
+//! Hyperlinks.
+//!
+//! There are several forms of URL tag:
+//!  * `[url]http://example.com/[/url]`: the target is also the text
+//!  * `[url="http://example.com/"]Foo[/url]`: quote-delimited target
+//!  * `[url=example.com]Bar[/url]`: non-delimited target
+//!
+//! The target of a delimited link runs to the first `"]`, and a non-delimited
+//! one to the first `]`. Finding those is left to the parser, which can do so
+//! without rescanning the input for every candidate tag.
 
-/// Handle any valid URL tag.
+use super::visit::{walk_segment_mut, VisitorMut};
+use super::Segment;
+#[cfg(test)]
+use super::{parse, DecorationStyle};
+use std::mem;
+
+// Recognizes `[url]`, whose body is both the target and the text of a link.
+named!(pub bare_head(&str) -> &str, tag_no_case!("[url]"));
+
+// Recognizes the start of `[url="target"]`, up to the target.
+named!(pub quoted_head(&str) -> &str, tag_no_case!("[url=\""));
+
+// Recognizes the start of `[url=target]`, up to the target.
+named!(pub unquoted_head(&str) -> &str, tag_no_case!("[url="));
+
+/// What to do with a link or image whose URL a `UrlPolicy` rejects.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum RejectedUrl {
+    /// Remove it, along with the text of a link.
+    Drop,
+    /// Replace it with text: the text of a link, or the source of an image.
+    Text,
+    /// Keep it, but with the given URL instead.
+    Placeholder(&'static str),
+}
+
+/// Which URLs links and images may have.
 ///
-/// This has several cases:
-///  * [url]http://example.com/[/url] target=text
-///  * [url="http://example.com/"]Foo[/url] quote-delimited target
-///  * [url=example.com]Bar[/url] non-delimited target
-named!(pub url(&str) -> Segment,
-    alt_complete!(
-        map!(
-            delimited!(tag_no_case!("[url]"),
-                       take_until_no_case!("[/url]"),
-                       tag_no_case!("[/url]")),
-            |body| Segment::Link {
-                target: body,
-                text: vec![Segment::Text(body)],
-            }
-        )
-        | do_parse!(
-            tag_no_case!("[url=\"") >>
-            target: take_until_and_consume!("\"]") >>
-            text: many0!(call!(segment, "[/url]")) >>
-            tag_no_case!("[/url]") >>
-            ( Segment::Link { target, text } )
-        )
-        | do_parse!(
-            tag_no_case!("[url=") >>
-            target: take_until_and_consume!("]") >>
-            text: many0!(call!(segment, "[/url]")) >>
-            tag_no_case!("[/url]") >>
-            ( Segment::Link { target, text } )
-        )
-    )
-);
+/// By default URLs must be relative or use one of `http`, `https`, `mailto`
+/// or `ftp`, and links with other URLs are replaced by their text.
+///
+/// A policy can be applied to parsed segments with `sanitize`, or when
+/// rendering HTML with `SimpleHtml::url_policy`.
+///
+/// ```
+/// use bbcode::{parse, RejectedUrl, Segment, UrlPolicy};
+///
+/// let policy = UrlPolicy::new()
+///     .schemes(&["https"])
+///     .relative(false)
+///     .on_reject(RejectedUrl::Placeholder("#"));
+/// assert!(policy.allows("HTTPS://example.com"));
+/// assert!(!policy.allows("/index.html"));
+/// assert_eq!(
+///     policy.sanitize(parse("[img]javascript:alert(1)[/img]")),
+///     vec![Segment::Image { src: "#".into() }]
+/// );
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct UrlPolicy {
+    schemes: Vec<String>,
+    relative: bool,
+    rejected: RejectedUrl,
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        UrlPolicy::new()
+    }
+}
+
+impl UrlPolicy {
+    pub fn new() -> Self {
+        UrlPolicy {
+            schemes: ["http", "https", "mailto", "ftp"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            relative: true,
+            rejected: RejectedUrl::Text,
+        }
+    }
+
+    /// Allow only URLs with the given schemes, ignoring ASCII case.
+    pub fn schemes(mut self, schemes: &[&str]) -> Self {
+        self.schemes = schemes.iter().map(|s| s.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Allow or reject relative URLs, which have no scheme.
+    pub fn relative(mut self, allow: bool) -> Self {
+        self.relative = allow;
+        self
+    }
+
+    /// Choose what to do with rejected URLs.
+    pub fn on_reject(mut self, rejected: RejectedUrl) -> Self {
+        self.rejected = rejected;
+        self
+    }
+
+    /// What is done with rejected URLs.
+    pub fn rejection(&self) -> RejectedUrl {
+        self.rejected
+    }
+
+    /// Whether the policy allows a URL.
+    ///
+    /// The scheme is found the way browsers find it, ignoring leading spaces
+    /// and control characters and any tabs or newlines, so a URL like
+    /// `" java\tscript:"` is not mistaken for a relative one.
+    pub fn allows(&self, url: &str) -> bool {
+        let url: String = url
+            .trim_start_matches(|c: char| c <= ' ')
+            .chars()
+            .filter(|&c| c != '\t' && c != '\n' && c != '\r')
+            .collect();
+        match scheme(&url) {
+            Some(scheme) => self.schemes.iter().any(|s| s.eq_ignore_ascii_case(scheme)),
+            None => self.relative,
+        }
+    }
+
+    /// Apply the policy to the links and images in segments.
+    pub fn sanitize<'a>(&self, mut segments: Vec<Segment<'a>>) -> Vec<Segment<'a>> {
+        Sanitize(self).visit_segments_mut(&mut segments);
+        segments
+    }
+}
+
+/// Applies a `UrlPolicy` to segments.
+struct Sanitize<'p>(&'p UrlPolicy);
+
+impl<'p, 'a> VisitorMut<'a> for Sanitize<'p> {
+    fn visit_segments_mut(&mut self, segments: &mut Vec<Segment<'a>>) {
+        let policy = self.0;
+        for segment in mem::take(segments) {
+            let mut segment = match segment {
+                Segment::Link { target, mut text } if !policy.allows(&target) => {
+                    match policy.rejected {
+                        RejectedUrl::Drop => continue,
+                        RejectedUrl::Text => {
+                            // The text may hold links and images of its own.
+                            self.visit_segments_mut(&mut text);
+                            segments.extend(text);
+                            continue;
+                        }
+                        RejectedUrl::Placeholder(target) => Segment::Link {
+                            target: target.into(),
+                            text,
+                        },
+                    }
+                }
+                Segment::Image { src } if !policy.allows(&src) => match policy.rejected {
+                    RejectedUrl::Drop => continue,
+                    RejectedUrl::Text => Segment::Text(src),
+                    RejectedUrl::Placeholder(src) => Segment::Image { src: src.into() },
+                },
+                segment => segment,
+            };
+            walk_segment_mut(self, &mut segment);
+            segments.push(segment);
+        }
+    }
+}
+
+/// The scheme of a URL, if it is absolute.
+fn scheme(url: &str) -> Option<&str> {
+    let scheme = &url[..url.find(':')?];
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if valid {
+        Some(scheme)
+    } else {
+        None
+    }
+}
 
 #[test]
 fn url_parses() {
-    use super::DecorationStyle;
-
     assert_eq!(
-        url("[URL]example.com[/URL]"),
-        Ok((
-            "",
-            Segment::Link {
-                target: "example.com",
-                text: vec![Segment::Text("example.com")],
-            }
-        ))
+        parse("[URL]example.com[/URL]"),
+        vec![Segment::Link {
+            target: "example.com".into(),
+            text: vec![Segment::Text("example.com".into())],
+        }]
     );
     assert_eq!(
-        url("[url=example.com/\"quote\"]for [i]example[/url]"),
-        Ok((
-            "",
-            Segment::Link {
-                target: "example.com/\"quote\"",
-                text: vec![Segment::Text("for [i]example")],
-            }
-        ))
+        parse("[url=example.com/\"quote\"]for [i]example[/url]"),
+        vec![Segment::Link {
+            target: "example.com/\"quote\"".into(),
+            text: vec![Segment::Text("for [i]example".into())],
+        }]
     );
     assert_eq!(
-        url("[url=\"example.com\"][b]orly?[/b][/url]more"),
-        Ok((
-            "more",
+        parse("[url=\"example.com\"][b]orly?[/b][/url]more"),
+        vec![
             Segment::Link {
-                target: "example.com",
+                target: "example.com".into(),
                 text: vec![Segment::Decorated {
                     style: DecorationStyle::Bold,
-                    text: vec![Segment::Text("orly?")],
+                    text: vec![Segment::Text("orly?".into())],
                 }],
-            }
-        ))
+            },
+            Segment::Text("more".into()),
+        ]
+    );
+}
+
+#[test]
+fn policy_finds_schemes() {
+    let policy = UrlPolicy::new();
+    assert!(policy.allows("http://example.com"));
+    assert!(policy.allows("example.com/a:b"));
+    assert!(policy.allows("mailto:a@example.com"));
+    assert!(!policy.allows("javascript:alert(1)"));
+    assert!(!policy.allows("\u{1} JAVA\nSCRIPT:alert(1)"));
+    assert!(!policy.allows("data:text/html,x"));
+    assert!(!policy.relative(false).allows("example.com"));
+}
+
+#[test]
+fn policy_handles_every_form_alike() {
+    let input = "[url]data:x[/url] [url=\"data:x\"]a[/url] [url=data:x]b[/url] [img]data:x[/img]";
+    let drop = UrlPolicy::new().on_reject(RejectedUrl::Drop);
+    assert_eq!(
+        drop.sanitize(parse(input)),
+        vec![
+            Segment::Text(" ".into()),
+            Segment::Text(" ".into()),
+            Segment::Text(" ".into())
+        ]
+    );
+    assert_eq!(
+        UrlPolicy::new().sanitize(parse(input)),
+        vec![
+            Segment::Text("data:x".into()),
+            Segment::Text(" ".into()),
+            Segment::Text("a".into()),
+            Segment::Text(" ".into()),
+            Segment::Text("b".into()),
+            Segment::Text(" ".into()),
+            Segment::Text("data:x".into()),
+        ]
+    );
+    let placeholder = UrlPolicy::new().on_reject(RejectedUrl::Placeholder("#"));
+    assert_eq!(
+        placeholder.sanitize(parse("[b][url=data:x][img]data:y[/img][/url][/b]")),
+        vec![Segment::Decorated {
+            style: DecorationStyle::Bold,
+            text: vec![Segment::Link {
+                target: "#".into(),
+                text: vec![Segment::Image { src: "#".into() }],
+            }],
+        }]
+    );
+}
+
+#[test]
+fn policy_checks_text_of_rejected_links() {
+    let policy = UrlPolicy::new();
+    assert_eq!(
+        policy.sanitize(parse("[url=data:x][img]javascript:alert(1)[/img][/url]")),
+        vec![Segment::Text("javascript:alert(1)".into())]
+    );
+    assert_eq!(
+        policy.sanitize(parse("[url=data:x][url]javascript:y[/url][/url]")),
+        vec![Segment::Text("javascript:y".into())]
     );
 }
@@ -0,0 +1,189 @@
+//! Reporting malformed markup.
+//!
+//! Parsing never fails: anything that doesn't form a valid tag is left as
+//! text. `Parser::validate` instead explains why tags were left as text, so
+//! users can be warned about them.
+
+use super::span::Span;
+use std::error::Error;
+use std::fmt;
+
+/// The kinds of problem that can be found in markup.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum DiagnosticKind {
+    /// An opening tag with no closing tag after it.
+    Unclosed,
+    /// A closing tag with no opening tag before it.
+    StrayClose,
+    /// An opening tag whose closing tag is inside another tag, or vice
+    /// versa: `[b][i][/b][/i]`.
+    Misnested,
+    /// `[color=...]` with a value that is not a color.
+    InvalidColor,
+    /// `[size=...]` with a value that is not a number in the supported
+    /// range.
+    InvalidSize,
+    /// A list with text before its first `[*]`.
+    TextBeforeItem,
+    /// A custom tag with attributes its `TagHandler` does not accept.
+    RejectedAttributes,
+    /// An embed tag like `[youtube]` whose body no `EmbedProvider`
+    /// recognizes.
+    UnknownMedia,
+    /// A table with text or tags outside its `[tr]` rows and their cells.
+    MalformedTable,
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DiagnosticKind::Unclosed => "tag is never closed",
+            DiagnosticKind::StrayClose => "closing tag does not match any opening tag",
+            DiagnosticKind::Misnested => "tag is closed inside another tag",
+            DiagnosticKind::InvalidColor => "invalid color",
+            DiagnosticKind::InvalidSize => "invalid size",
+            DiagnosticKind::TextBeforeItem => "list has text before its first item",
+            DiagnosticKind::RejectedAttributes => "invalid attributes",
+            DiagnosticKind::UnknownMedia => "unrecognized media",
+            DiagnosticKind::MalformedTable => "table has text or tags outside its cells",
+        })
+    }
+}
+
+/// A problem with the markup in some input.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// The tag at fault.
+    pub span: Span,
+    /// The closing tag that an opening tag would be closed by, if it is
+    /// misnested or malformed.
+    pub related: Option<Span>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.start.line, self.span.start.column, self.kind
+        )
+    }
+}
+
+impl Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse, Parser, Tag};
+    use super::DiagnosticKind::{self, *};
+
+    /// Summarize diagnostics as their kinds, faulty tags and related tags.
+    fn check(input: &str) -> Vec<(DiagnosticKind, &str, Option<&str>)> {
+        Parser::new()
+            .validate(input)
+            .into_iter()
+            .map(|d| {
+                (
+                    d.kind,
+                    d.span.as_str(input),
+                    d.related.map(|r| r.as_str(input)),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid_markup() {
+        let input = "[b]x[/b] [code][i][/code] [list][*]a[/list] [ [/] [blink]";
+        assert_eq!(check(input), vec![]);
+        assert_eq!(Parser::new().parse_strict(input), Ok(parse(input)));
+    }
+
+    #[test]
+    fn unclosed_and_stray() {
+        assert_eq!(
+            check("[/i][b]x[quote=\"A\"]"),
+            vec![
+                (StrayClose, "[/i]", None),
+                (Unclosed, "[b]", None),
+                (Unclosed, "[quote=\"A\"]", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn misnested() {
+        assert_eq!(
+            check("[b][i]x[/b][/i]"),
+            vec![(Misnested, "[b]", Some("[/b]"))]
+        );
+        assert_eq!(
+            check("[list][*][u]a[/list][/u]"),
+            vec![(Misnested, "[list]", Some("[/list]"))]
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        let diagnostics = Parser::new().validate("a\n[color=nope]x[/color][size=99]y[/size]");
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].kind, InvalidColor);
+        assert_eq!(diagnostics[0].to_string(), "2:1: invalid color");
+        assert_eq!(diagnostics[1].kind, InvalidSize);
+        assert_eq!(diagnostics[1].span.start.column, 22);
+    }
+
+    #[test]
+    fn text_before_item() {
+        assert_eq!(
+            check("[list]a[*]b[/list]"),
+            vec![(TextBeforeItem, "[list]", Some("[/list]"))]
+        );
+    }
+
+    #[test]
+    fn unknown_media() {
+        assert_eq!(
+            check("[youtube]nope[/youtube] [media=x]y[/media] [video]"),
+            vec![
+                (UnknownMedia, "[youtube]", Some("[/youtube]")),
+                (UnknownMedia, "[media=x]", Some("[/media]")),
+                (Unclosed, "[video]", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_table() {
+        assert_eq!(
+            check("[table][tr]a[td]b[/td][/tr][/table][table][tr][/td]"),
+            vec![
+                (MalformedTable, "[table]", Some("[/table]")),
+                (StrayClose, "[/td]", None),
+                (StrayClose, "[/tr]", None),
+                (Unclosed, "[table]", None),
+                (StrayClose, "[/td]", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_spoilers() {
+        assert_eq!(
+            check("[spoiler=\"A\"][b]x[/spoiler][/b][hide=b"),
+            vec![(Misnested, "[spoiler=\"A\"]", Some("[/spoiler]"))]
+        );
+        assert_eq!(
+            check("[hide=b]x[spoiler]"),
+            vec![(Unclosed, "[hide=b]", None), (Unclosed, "[spoiler]", None)]
+        );
+    }
+
+    #[test]
+    fn disabled_tags_are_not_reported() {
+        let parser = Parser::new().disable(Tag::Image);
+        assert_eq!(parser.validate("[img]x[/img][img]"), vec![]);
+        assert!(parser.parse_strict("[/b]").is_err());
+    }
+}
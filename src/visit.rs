@@ -0,0 +1,221 @@
+//! Walking trees of segments.
+//!
+//! A `Visitor` is given every segment in a tree, and a `VisitorMut` can also
+//! change them. Each method walks the segments inside the one it is given by
+//! default, so implementations override only the methods for what they look
+//! at, calling the matching `walk_` function to carry on into the segments
+//! inside.
+//!
+//! ```
+//! use bbcode::parse;
+//! use bbcode::visit::{walk_segment, Visitor};
+//! use bbcode::Segment;
+//!
+//! struct CountQuotes(usize);
+//!
+//! impl<'s> Visitor<'s> for CountQuotes {
+//!     fn visit_segment(&mut self, segment: &'s Segment<'s>) {
+//!         if let Segment::Quote { .. } = *segment {
+//!             self.0 += 1;
+//!         }
+//!         walk_segment(self, segment);
+//!     }
+//! }
+//!
+//! let mut count = CountQuotes(0);
+//! count.visit_segments(&parse("[quote][quote]a[/quote][/quote][quote]b[/quote]"));
+//! assert_eq!(count.0, 3);
+//! ```
+
+use super::Segment;
+
+/// Looks at every segment in a tree.
+///
+/// Segments are borrowed for `'s`, so a visitor can keep references to them.
+pub trait Visitor<'s> {
+    /// Visit a sequence of segments, like the body of a quote.
+    fn visit_segments(&mut self, segments: &'s [Segment<'s>]) {
+        walk_segments(self, segments)
+    }
+
+    /// Visit one segment.
+    fn visit_segment(&mut self, segment: &'s Segment<'s>) {
+        walk_segment(self, segment)
+    }
+}
+
+/// Visit each of segments in turn.
+pub fn walk_segments<'s, V: Visitor<'s> + ?Sized>(visitor: &mut V, segments: &'s [Segment<'s>]) {
+    for segment in segments {
+        visitor.visit_segment(segment);
+    }
+}
+
+/// Visit the segments inside segment: the text of a decoration, link or
+/// heading, the body of a quote or custom tag, each item of a list, or each
+/// cell of a table.
+pub fn walk_segment<'s, V: Visitor<'s> + ?Sized>(visitor: &mut V, segment: &'s Segment<'s>) {
+    match *segment {
+        Segment::Decorated { ref text, .. }
+        | Segment::Link { ref text, .. }
+        | Segment::Heading { ref text, .. } => visitor.visit_segments(text),
+        Segment::Quote { ref body, .. }
+        | Segment::Custom { ref body, .. }
+        | Segment::Spoiler { ref body, .. } => visitor.visit_segments(body),
+        Segment::List { ref items, .. } => {
+            for item in items {
+                visitor.visit_segments(item);
+            }
+        }
+        Segment::Table { ref rows } => {
+            for cell in rows.iter().flatten() {
+                visitor.visit_segments(&cell.body);
+            }
+        }
+        Segment::Text(_)
+        | Segment::Code(_)
+        | Segment::Image { .. }
+        | Segment::Rule
+        | Segment::Embed { .. } => {}
+    }
+}
+
+/// Changes segments throughout a tree.
+///
+/// Sequences of segments are given as `Vec`s, so segments can be added or
+/// removed as well as changed.
+///
+/// ```
+/// use bbcode::parse;
+/// use bbcode::visit::{walk_segments_mut, VisitorMut};
+/// use bbcode::Segment;
+///
+/// struct StripImages;
+///
+/// impl<'a> VisitorMut<'a> for StripImages {
+///     fn visit_segments_mut(&mut self, segments: &mut Vec<Segment<'a>>) {
+///         segments.retain(|s| match *s {
+///             Segment::Image { .. } => false,
+///             _ => true,
+///         });
+///         walk_segments_mut(self, segments);
+///     }
+/// }
+///
+/// let mut segments = parse("a[img]x.png[/img][b]b[img]y.png[/img][/b]");
+/// StripImages.visit_segments_mut(&mut segments);
+/// assert_eq!(segments, parse("a[b]b[/b]"));
+/// ```
+pub trait VisitorMut<'a> {
+    /// Visit a sequence of segments, like the body of a quote.
+    fn visit_segments_mut(&mut self, segments: &mut Vec<Segment<'a>>) {
+        walk_segments_mut(self, segments)
+    }
+
+    /// Visit one segment.
+    fn visit_segment_mut(&mut self, segment: &mut Segment<'a>) {
+        walk_segment_mut(self, segment)
+    }
+}
+
+/// Visit each of segments in turn.
+pub fn walk_segments_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    segments: &mut Vec<Segment<'a>>,
+) {
+    for segment in segments {
+        visitor.visit_segment_mut(segment);
+    }
+}
+
+/// Visit the segments inside segment, like `walk_segment`.
+pub fn walk_segment_mut<'a, V: VisitorMut<'a> + ?Sized>(
+    visitor: &mut V,
+    segment: &mut Segment<'a>,
+) {
+    match *segment {
+        Segment::Decorated { ref mut text, .. }
+        | Segment::Link { ref mut text, .. }
+        | Segment::Heading { ref mut text, .. } => visitor.visit_segments_mut(text),
+        Segment::Quote { ref mut body, .. }
+        | Segment::Custom { ref mut body, .. }
+        | Segment::Spoiler { ref mut body, .. } => visitor.visit_segments_mut(body),
+        Segment::List { ref mut items, .. } => {
+            for item in items {
+                visitor.visit_segments_mut(item);
+            }
+        }
+        Segment::Table { ref mut rows } => {
+            for cell in rows.iter_mut().flatten() {
+                visitor.visit_segments_mut(&mut cell.body);
+            }
+        }
+        Segment::Text(_)
+        | Segment::Code(_)
+        | Segment::Image { .. }
+        | Segment::Rule
+        | Segment::Embed { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse, Segment};
+    use super::{walk_segment, walk_segment_mut, Visitor, VisitorMut};
+
+    #[test]
+    fn visits_every_segment() {
+        struct Texts<'s>(Vec<&'s str>);
+
+        impl<'s> Visitor<'s> for Texts<'s> {
+            fn visit_segment(&mut self, segment: &'s Segment<'s>) {
+                if let Segment::Text(ref s) = *segment {
+                    self.0.push(s);
+                }
+                walk_segment(self, segment);
+            }
+        }
+
+        let segments = parse(
+            "a[b]b[/b][quote]c[/quote][list][*]d[*][url=x]e[/url][/list][code]f[/code][img]g[/img]",
+        );
+        let mut texts = Texts(vec![]);
+        texts.visit_segments(&segments);
+        assert_eq!(texts.0, ["a", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn rewrites_segments() {
+        struct Https;
+
+        impl<'a> VisitorMut<'a> for Https {
+            fn visit_segment_mut(&mut self, segment: &mut Segment<'a>) {
+                if let Segment::Link { ref mut target, .. } = *segment {
+                    if target.starts_with("http:") {
+                        *target = format!("https:{}", &target[5..]).into();
+                    }
+                }
+                walk_segment_mut(self, segment);
+            }
+        }
+
+        let mut segments = parse("[i][url=http://a]x[url]http://b[/url][/url][/i]");
+        Https.visit_segments_mut(&mut segments);
+        assert_eq!(
+            segments,
+            vec![Segment::Decorated {
+                style: super::super::DecorationStyle::Italic,
+                text: vec![Segment::Link {
+                    target: "https://a".into(),
+                    text: vec![
+                        Segment::Text("x".into()),
+                        Segment::Link {
+                            target: "https://b".into(),
+                            text: vec![Segment::Text("http://b".into())],
+                        },
+                    ],
+                }],
+            }]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! OWASP XSS filter evasion vectors, placed everywhere bbcode accepts
+//! user-controlled text, must never produce HTML that can run script.
+
+extern crate bbcode;
+
+use bbcode::render::{Renderer, SimpleHtml};
+use bbcode::{RejectedUrl, Segment, UrlPolicy};
+
+/// Payloads from the OWASP XSS Filter Evasion Cheat Sheet.
+const VECTORS: &[&str] = &[
+    "<script>alert('XSS')</script>",
+    "<SCRIPT SRC=http://xss.rocks/xss.js></SCRIPT>",
+    "javascript:alert('XSS')",
+    "JaVaScRiPt:alert('XSS')",
+    "javascript:alert(&quot;XSS&quot;)",
+    "&#106;&#97;&#118;&#97;&#115;&#99;&#114;&#105;&#112;&#116;&#58;alert('XSS')",
+    "&#x6A&#x61&#x76&#x61&#x73&#x63&#x72&#x69&#x70&#x74&#x3A;alert('XSS')",
+    "jav\tascript:alert('XSS');",
+    "jav&#x09;ascript:alert('XSS');",
+    "jav\nascript:alert('XSS');",
+    "\u{1} \u{14}javascript:alert('XSS');",
+    " javascript:alert('XSS');",
+    "vbscript:msgbox(\"XSS\")",
+    "data:text/html;base64,PHNjcmlwdD5hbGVydCgnWFNTJyk8L3NjcmlwdD4K",
+    "\"><script>alert(1)</script>",
+    "'><script>alert(1)</script>",
+    "\" onerror=\"alert(1)",
+    "x\" onmouseover=\"alert(1)\" y=\"",
+    "<IMG SRC=\"javascript:alert('XSS');\">",
+    "<IMG \"\"\"><SCRIPT>alert(\"XSS\")</SCRIPT>\"\\>",
+    "<img src=x onerror=alert(1)//",
+    "<svg/onload=alert('XSS')>",
+    "<BODY ONLOAD=alert('XSS')>",
+    "<iframe src=javascript:alert('XSS')></iframe>",
+    "<a href=\"javascript:alert(1)\">x</a>",
+    "<STYLE>li {list-style-image: url(\"javascript:alert('XSS')\");}</STYLE>",
+    "<DIV STYLE=\"background-image: url(javascript:alert('XSS'))\">",
+    "expression(alert('XSS'))",
+    "<!--<script>alert(1)</script>-->",
+    "<![CDATA[<script>alert(1)</script>]]>",
+    "<<SCRIPT>alert(\"XSS\");//<</SCRIPT>",
+    "\\\";alert('XSS');//",
+    "</TITLE><SCRIPT>alert(\"XSS\");</SCRIPT>",
+];
+
+/// Ways of putting a payload into bbcode.
+const CONTEXTS: &[&str] = &[
+    "{}",
+    "[b]{}[/b]",
+    "[url]{}[/url]",
+    "[url={}]x[/url]",
+    "[url=\"{}\"]x[/url]",
+    "[url=http://example.com]{}[/url]",
+    "[img]{}[/img]",
+    "[quote=\"{}\"]x[/quote]",
+    "[quote]{}[/quote]",
+    "[code]{}[/code]",
+    "[color={}]x[/color]",
+    "[size={}]x[/size]",
+    "[list][*]{}[/list]",
+    "[list=a][*][url]{}[/url][/list]",
+    "[h2]{}[/h2]",
+    "[hr]{}[hr]",
+    "[sub]{}[/sub][sup]{}[/sup]",
+    "[s]{}[/s][tt]{}[/tt]",
+    "[youtube]{}[/youtube][media=vimeo]{}[/media][video]{}[/video]",
+    "[youtube]youtu.be/dQw4w9WgXcQ?t={}[/youtube][media]vimeo.com/1/{}[/media]",
+    "[spoiler={}]x[/spoiler][hide=\"{}\"]{}[/hide]",
+    "[table][tr][th]{}[/th][td][url]{}[/url][/td][/tr][/table]",
+];
+
+fn render(input: &str) -> String {
+    render_with(UrlPolicy::default(), input)
+}
+
+fn render_with(policy: UrlPolicy, input: &str) -> String {
+    render_segments(policy, &bbcode::parse(input))
+}
+
+fn render_segments(policy: UrlPolicy, segments: &Vec<Segment>) -> String {
+    let mut out = vec![];
+    SimpleHtml::new(&mut out)
+        .url_policy(policy)
+        .render(segments)
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Undo the escaping of an attribute value.
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Whether a URL would run script when followed.
+fn runs_script(url: &str) -> bool {
+    let url: String = unescape(url)
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    ["javascript:", "vbscript:", "data:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+/// Check that html only contains tags and attributes the renderer is known to
+/// write, with safe values.
+fn assert_safe(input: &str, html: &str) {
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        assert!(!rest[..start].contains('>'), "{:?} -> {:?}", input, html);
+        let end = rest[start..].find('>').expect("unterminated tag") + start;
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        let (name, attr) = match tag.find(' ') {
+            Some(space) => (&tag[..space], Some(&tag[space + 1..])),
+            None => (tag, None),
+        };
+        let name = name.trim_start_matches('/');
+        assert!(
+            [
+                "b", "i", "u", "s", "sub", "sup", "div", "span", "br", "pre", "ul", "ol", "li",
+                "a", "img", "h2", "hr", "iframe", "details", "summary", "table", "tr", "th", "td"
+            ]
+            .contains(&name),
+            "unexpected tag {:?} in {:?} -> {:?}",
+            tag,
+            input,
+            html
+        );
+        let mut attrs = match attr {
+            Some(attrs) => attrs,
+            None => continue,
+        };
+        while !attrs.is_empty() {
+            let (key, value) = match attrs.find([' ', '=']) {
+                Some(i) if attrs[i..].starts_with("=\"") => {
+                    let (key, value) = (&attrs[..i], &attrs[i + 2..]);
+                    let quote = value.find('"').expect("unterminated attribute");
+                    attrs = &value[quote + 1..];
+                    (key, Some(&value[..quote]))
+                }
+                Some(i) if attrs[i..].starts_with('=') => panic!("unquoted attribute"),
+                Some(i) => {
+                    let key = &attrs[..i];
+                    attrs = &attrs[i..];
+                    (key, None)
+                }
+                None => {
+                    let key = attrs;
+                    attrs = "";
+                    (key, None)
+                }
+            };
+            assert!(
+                attrs.is_empty() || attrs.starts_with(' '),
+                "attribute breaks out of its quotes in {:?} -> {:?}",
+                input,
+                html
+            );
+            attrs = attrs.trim_start_matches(' ');
+            check_attribute(input, html, key, value);
+        }
+    }
+    assert!(!rest.contains('>'), "{:?} -> {:?}", input, html);
+}
+
+/// Check that an attribute is one the renderer is known to write, with a
+/// safe value.
+fn check_attribute(input: &str, html: &str, key: &str, value: Option<&str>) {
+    let value = match (key, value) {
+        ("allowfullscreen", None) => return,
+        (_, Some(value)) => value,
+        (_, None) => panic!(
+            "unexpected attribute {:?} in {:?} -> {:?}",
+            key, input, html
+        ),
+    };
+    match key {
+        "href" | "src" => assert!(
+            !runs_script(value),
+            "scriptable URL in {:?} -> {:?}",
+            input,
+            html
+        ),
+        "style" => assert!(
+            value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || " :#-".contains(c)),
+            "unexpected style {:?} in {:?}",
+            value,
+            input
+        ),
+        "type" => assert_eq!(value, "a"),
+        "loading" => assert_eq!(value, "lazy"),
+        "id" => assert!(
+            value
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_'),
+            "unexpected id {:?} in {:?}",
+            value,
+            input
+        ),
+        _ => panic!(
+            "unexpected attribute {:?} in {:?} -> {:?}",
+            key, input, html
+        ),
+    }
+}
+
+#[test]
+fn vectors_in_every_context() {
+    let rejections = [
+        RejectedUrl::Text,
+        RejectedUrl::Drop,
+        RejectedUrl::Placeholder("#"),
+    ];
+    for &rejected in &rejections {
+        for vector in VECTORS {
+            for context in CONTEXTS {
+                let input = context.replace("{}", vector);
+                let policy = UrlPolicy::new().on_reject(rejected);
+                assert_safe(&input, &render_with(policy, &input));
+            }
+        }
+    }
+}
+
+#[test]
+fn vectors_in_embedded_media() {
+    for vector in VECTORS {
+        for provider in &["youtube", "vimeo"] {
+            let segment = Segment::Embed {
+                provider: (*provider).into(),
+                id: (*vector).into(),
+                params: vec![
+                    ("start".into(), (*vector).into()),
+                    ("h".into(), (*vector).into()),
+                ],
+            };
+            let html = render_segments(UrlPolicy::default(), &vec![segment]);
+            assert_safe(vector, &html);
+            assert!(!html.contains(vector), "{:?} -> {:?}", vector, html);
+        }
+    }
+}
+
+#[test]
+fn safe_urls_are_kept() {
+    assert_eq!(
+        render("[url=https://example.com/?a=1&b=2]x[/url]"),
+        r#"<a href="https://example.com/?a=1&amp;b=2">x</a>"#
+    );
+    assert_eq!(render("[url]/a:b[/url]"), r#"<a href="/a:b">/a:b</a>"#);
+    assert_eq!(
+        render("[img]HTTP://example.com/a.png[/img]"),
+        r#"<img src="HTTP://example.com/a.png">"#
+    );
+}
+
+#[test]
+fn unsafe_urls_are_rejected() {
+    assert_eq!(render("[url=javascript:alert(1)]x[/url]"), "x");
+    assert_eq!(render("[url] JaVa\tscript:x[/url]"), " JaVa\tscript:x");
+    assert_eq!(
+        render("[img]data:image/svg+xml,<svg>[/img]"),
+        "data:image/svg+xml,&lt;svg&gt;"
+    );
+
+    let input = "a[url=javascript:alert(1)]x[/url][img]data:,[/img]b";
+    let drop = UrlPolicy::new().on_reject(RejectedUrl::Drop);
+    assert_eq!(render_with(drop, input), "ab");
+    let placeholder = UrlPolicy::new().on_reject(RejectedUrl::Placeholder("#"));
+    assert_eq!(
+        render_with(placeholder, input),
+        r##"a<a href="#">x</a><img src="#">b"##
+    );
+}